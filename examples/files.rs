@@ -41,6 +41,13 @@ pub fn main() -> Result<()> {
         println!("   {:?}", itm);
     }
 
+    // Globs look through all subdirectories of all resource
+    // directories at once.
+    println!("All the .png files in all the resource directories:");
+    for itm in filesystem::glob(ctx, "/**/*.png")? {
+        println!("   {:?}", itm);
+    }
+
     println!();
     println!("Let's write to a file, it should end up in the user config dir");
 
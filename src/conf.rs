@@ -30,6 +30,22 @@ pub enum FullscreenType {
     Desktop,
 }
 
+/// What units window sizes, drawing and mouse positions are in,
+/// which only makes a difference on high DPI displays.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DpiMode {
+    /// Logical pixels, which the OS scales by the hidpi factor of the
+    /// monitor the window is on, so the game looks the same size on
+    /// every monitor.  Anything drawn at a smaller scale than that
+    /// comes out blurry.
+    Logical,
+    /// Physical pixels, the ones the display actually has.  Everything
+    /// stays sharp, but the game has to scale things up itself on high
+    /// DPI monitors; see [`graphics::hidpi_factor()`](../graphics/fn.hidpi_factor.html)
+    /// and [`EventHandler::dpi_changed_event()`](../event/trait.EventHandler.html#method.dpi_changed_event).
+    Physical,
+}
+
 /// A builder structure containing window settings
 /// that can be set at runtime and changed with [`graphics::set_mode()`](../graphics/fn.set_mode.html).
 ///
@@ -49,6 +65,7 @@ pub enum FullscreenType {
 ///     min_height: 0.0,
 ///     max_height: 0.0,
 ///     resizable: false,
+///     dpi_mode: DpiMode::Logical,
 /// }
 /// # , WindowMode::default());}
 /// ```
@@ -84,6 +101,10 @@ pub struct WindowMode {
     /// Whether or not the window is resizable
     #[default = false]
     pub resizable: bool,
+    /// Whether the sizes above, drawing and mouse positions are in
+    /// logical or physical pixels
+    #[default(DpiMode::Logical)]
+    pub dpi_mode: DpiMode,
 }
 
 impl WindowMode {
@@ -131,6 +152,12 @@ impl WindowMode {
         self.resizable = resizable;
         self
     }
+
+    /// Set whether to use logical or physical pixels.
+    pub fn dpi_mode(mut self, dpi_mode: DpiMode) -> Self {
+        self.dpi_mode = dpi_mode;
+        self
+    }
 }
 
 /// A builder structure containing window settings
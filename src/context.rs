@@ -129,8 +129,8 @@ impl Context {
         match event.clone() {
             winit_event::Event::WindowEvent { event, .. } => match event {
                 winit_event::WindowEvent::Resized(logical_size) => {
-                    let hidpi_factor = self.gfx_context.window.get_hidpi_factor();
-                    let physical_size = logical_size.to_physical(hidpi_factor as f64);
+                    let hidpi_factor = f64::from(self.gfx_context.hidpi_factor);
+                    let physical_size = logical_size.to_physical(hidpi_factor);
                     self.gfx_context.window.resize(physical_size);
                     self.gfx_context.resize_viewport();
                 }
@@ -138,10 +138,11 @@ impl Context {
                     position: logical_position,
                     ..
                 } => {
-                    self.mouse_context.set_last_position(Point2::new(
+                    let position = self.gfx_context.window_to_screen(Point2::new(
                         logical_position.x as f32,
                         logical_position.y as f32,
                     ));
+                    self.mouse_context.set_last_position(position);
                 }
                 winit_event::WindowEvent::MouseInput { button, state, .. } => {
                     let pressed = match state {
@@ -168,15 +169,25 @@ impl Context {
                         .set_modifiers(keyboard::KeyMods::from(modifiers));
                     self.keyboard_context.set_key(keycode, pressed);
                 }
-                winit_event::WindowEvent::HiDpiFactorChanged(_) => {
-                    // Nope.
+                winit_event::WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                    // The window keeps its logical size, so it
+                    // has a different number of pixels now.
+                    let gfx = &mut self.gfx_context;
+                    gfx.hidpi_factor = hidpi_factor as f32;
+                    if let Some(logical_size) = gfx.window.get_inner_size() {
+                        gfx.window.resize(logical_size.to_physical(hidpi_factor));
+                        gfx.resize_viewport();
+                    }
                 }
                 _ => (),
             },
             winit_event::Event::DeviceEvent { event, .. } => {
                 if let winit_event::DeviceEvent::MouseMotion { delta: (x, y) } = event {
+                    let gfx = &self.gfx_context;
+                    let origin = gfx.window_to_screen(Point2::origin());
+                    let delta = gfx.window_to_screen(Point2::new(x as f32, y as f32)) - origin;
                     self.mouse_context
-                        .set_last_delta(Point2::new(x as f32, y as f32));
+                        .set_last_delta(Point2::new(delta.x, delta.y));
                 }
             }
 
@@ -197,6 +208,7 @@ pub struct ContextBuilder {
     pub(crate) paths: Vec<path::PathBuf>,
     pub(crate) memory_zip_files: Vec<Cow<'static, [u8]>>,
     pub(crate) load_conf_file: bool,
+    pub(crate) strict_filesystem: bool,
 }
 
 impl ContextBuilder {
@@ -209,6 +221,7 @@ impl ContextBuilder {
             paths: vec![],
             memory_zip_files: vec![],
             load_conf_file: true,
+            strict_filesystem: false,
         }
     }
 
@@ -283,9 +296,20 @@ impl ContextBuilder {
         self
     }
 
+    /// Specifies whether the filesystem starts out in strict mode,
+    /// refusing any path that leads outside of the directory it is
+    /// looked up in.  Defaults to `false`.  This way even the
+    /// `conf.toml` file is loaded in strict mode.  See
+    /// [`filesystem::set_strict()`](filesystem/fn.set_strict.html).
+    pub fn strict_filesystem(mut self, strict: bool) -> Self {
+        self.strict_filesystem = strict;
+        self
+    }
+
     /// Build the `Context`.
     pub fn build(self) -> GameResult<(Context, winit::EventsLoop)> {
         let mut fs = Filesystem::new(self.game_id.as_ref(), self.author.as_ref())?;
+        fs.set_strict(self.strict_filesystem);
 
         for path in &self.paths {
             fs.mount(path, true);
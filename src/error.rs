@@ -28,8 +28,12 @@ pub enum GameError {
     EventLoopError(String),
     /// An error trying to load a resource, such as getting an invalid image file.
     ResourceLoadError(String),
-    /// Unable to find a resource; the `Vec` is the paths it searched for and associated errors
-    ResourceNotFound(String, Vec<(std::path::PathBuf, GameError)>),
+    /// Unable to find a resource; the `Vec` is the layers of the filesystem it
+    /// searched and the error it got from each
+    ResourceNotFound(String, Vec<(crate::filesystem::Layer, GameError)>),
+    /// A path tried to leave its filesystem root while the filesystem
+    /// was in strict mode, via `..` or a symlink.
+    SandboxViolation(String),
     /// Something went wrong in the renderer
     RenderError(String),
     /// Something went wrong in the audio playback
@@ -55,14 +59,19 @@ pub enum GameError {
 impl fmt::Display for GameError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            GameError::FilesystemError(ref s) => write!(f, "Filesystem error: {}", s),
             GameError::ConfigError(ref s) => write!(f, "Config error: {}", s),
             GameError::ResourceLoadError(ref s) => write!(f, "Error loading resource: {}", s),
-            GameError::ResourceNotFound(ref s, ref paths) => write!(
-                f,
-                "Resource not found: {}, searched in paths {:?}",
-                s, paths
-            ),
+            GameError::ResourceNotFound(ref s, ref layers) => {
+                write!(f, "Resource not found: {}, searched in:", s)?;
+                for (layer, e) in layers {
+                    write!(f, "\n  {}: {}", layer, e)?;
+                }
+                Ok(())
+            }
             GameError::WindowError(ref e) => write!(f, "Window creation error: {}", e),
+            GameError::SandboxViolation(ref s) => write!(f, "Sandbox violation: {}", s),
+            GameError::IOError(ref e) => write!(f, "IO error: {}", e),
             _ => write!(f, "GameError {:?}", self),
         }
     }
@@ -146,6 +146,15 @@ pub trait EventHandler {
     /// Called when the user resizes the window, or when it is resized
     /// via [`graphics::set_mode()`](../graphics/fn.set_mode.html).
     fn resize_event(&mut self, _ctx: &mut Context, _width: f32, _height: f32) {}
+
+    /// Called when the hidpi factor of the window changes, usually
+    /// because it was moved to a monitor with a different DPI.
+    /// The window keeps its size in logical pixels, so with
+    /// [`DpiMode::Physical`](../conf/enum.DpiMode.html) its
+    /// [`drawable_size()`](../graphics/fn.drawable_size.html) changes,
+    /// and with `DpiMode::Logical` you might want to redraw
+    /// things at a different resolution to keep them sharp.
+    fn dpi_changed_event(&mut self, _ctx: &mut Context, _hidpi_factor: f32) {}
 }
 
 /// Terminates the [`ggez::event::run()`](fn.run.html) loop by setting
@@ -177,13 +186,16 @@ where
             match event {
                 Event::WindowEvent { event, .. } => match event {
                     WindowEvent::Resized(logical_size) => {
-                        // let actual_size = logical_size;
+                        let scale = ctx.gfx_context.logical_scale();
                         state.resize_event(
                             ctx,
-                            logical_size.width as f32,
-                            logical_size.height as f32,
+                            logical_size.width as f32 * scale,
+                            logical_size.height as f32 * scale,
                         );
                     }
+                    WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                        state.dpi_changed_event(ctx, hidpi_factor as f32);
+                    }
                     WindowEvent::CloseRequested => {
                         if !state.quit_event(ctx) {
                             quit(ctx);
@@ -224,7 +236,8 @@ where
                         let (x, y) = match delta {
                             MouseScrollDelta::LineDelta(x, y) => (x, y),
                             MouseScrollDelta::PixelDelta(dpi::LogicalPosition { x, y }) => {
-                                (x as f32, y as f32)
+                                let scale = ctx.gfx_context.logical_scale();
+                                (x as f32 * scale, y as f32 * scale)
                             }
                         };
                         state.mouse_wheel_event(ctx, x, y);
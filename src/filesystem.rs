@@ -22,25 +22,39 @@
 //!
 //! See the source of the [`files` example](https://github.com/ggez/ggez/blob/master/examples/files.rs) for more details.
 //!
-//! Note that the file lookups WILL follow symlinks!  This module's
-//! directory isolation is intended for convenience, not security, so
-//! don't assume it will be secure.
+//! Note that by default the file lookups WILL follow symlinks!  This
+//! module's directory isolation is intended for convenience, not security,
+//! so don't assume it will be secure.  If you are loading files you don't
+//! trust, such as user-made mods, turn on strict mode with
+//! [`set_strict()`](fn.set_strict.html) or
+//! [`ContextBuilder::strict_filesystem()`](../struct.ContextBuilder.html#method.strict_filesystem).
+//! Then paths are resolved, symlinks and all, before anything is opened,
+//! and any path that would end up outside of the directory it was looked
+//! up in is refused with a
+//! [`GameError::SandboxViolation`](../enum.GameError.html#variant.SandboxViolation).
 
 use std::env;
 use std::fmt;
-use std::io;
+use std::io::{self, Read};
 use std::path;
 
 use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use toml;
 
 use crate::conf;
 use crate::vfs::{self, VFS};
 use crate::{Context, GameError, GameResult};
 
-pub use crate::vfs::OpenOptions;
+pub use crate::vfs::{Layer, LayerSource, OpenOptions};
 
 const CONFIG_NAME: &str = "/conf.toml";
 
+/// How many directories deep `walk_dir()` will go before deciding it
+/// is probably stuck in a symlink loop.
+const MAX_WALK_DEPTH: usize = 64;
+
 /// A structure that contains the filesystem state and cache.
 #[derive(Debug)]
 pub struct Filesystem {
@@ -49,6 +63,7 @@ pub struct Filesystem {
     zip_path: path::PathBuf,
     user_config_path: path::PathBuf,
     user_data_path: path::PathBuf,
+    strict: bool,
 }
 
 /// Represents a file, either in the filesystem, or in the resources zip file,
@@ -91,6 +106,29 @@ impl io::Write for File {
     }
 }
 
+/// Which layer of the filesystem a path resolves to, as returned by
+/// [`resolve()`](fn.resolve.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// The path that was resolved.
+    pub path: path::PathBuf,
+    /// The layer that the path will actually be loaded from.
+    pub layer: Layer,
+    /// Every other layer that also has something at this path, in the
+    /// order they are searched.  These are all hidden by `layer`.
+    pub shadowed: Vec<Layer>,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is loaded from {}", self.path.display(), self.layer)?;
+        for layer in &self.shadowed {
+            write!(f, "\n  shadowing {}", layer)?;
+        }
+        Ok(())
+    }
+}
+
 impl Filesystem {
     /// Create a new `Filesystem` instance, using the given `id` and (on
     /// some platforms) the `author` as a portion of the user
@@ -167,6 +205,7 @@ impl Filesystem {
             zip_path: resources_zip_path,
             user_config_path: user_config_path.to_path_buf(),
             user_data_path: user_data_path.to_path_buf(),
+            strict: false,
         };
 
         Ok(fs)
@@ -205,6 +244,27 @@ impl Filesystem {
         self.vfs.create(path.as_ref()).map(|f| File::VfsFile(f))
     }
 
+    /// Replaces the contents of a file in the user directory,
+    /// creating it if it doesn't exist, without ever leaving it
+    /// half-written.
+    pub(crate) fn write_atomic<P: AsRef<path::Path>>(
+        &mut self,
+        path: P,
+        bytes: &[u8],
+    ) -> GameResult {
+        self.vfs.write_atomic(path.as_ref(), bytes)
+    }
+
+    /// Moves a file or directory in the user directory, replacing
+    /// whatever is at `to`.
+    pub(crate) fn rename<P: AsRef<path::Path>, Q: AsRef<path::Path>>(
+        &mut self,
+        from: P,
+        to: Q,
+    ) -> GameResult {
+        self.vfs.rename(from.as_ref(), to.as_ref())
+    }
+
     /// Create an empty directory in the user dir
     /// with the given name.  Any parents to that directory
     /// that do not exist will be created.
@@ -258,11 +318,86 @@ impl Filesystem {
         Ok(Box::new(itr))
     }
 
+    /// Returns every file and directory in the given directory and
+    /// all of its subdirectories, sorted, in depth-first order.
+    pub(crate) fn walk_dir<P: AsRef<path::Path>>(
+        &mut self,
+        path: P,
+    ) -> GameResult<Box<dyn Iterator<Item = path::PathBuf>>> {
+        let mut found = Vec::new();
+        self.walk_into(path.as_ref(), 0, &mut found)?;
+        Ok(Box::new(found.into_iter()))
+    }
+
+    fn walk_into(
+        &self,
+        dir: &path::Path,
+        depth: usize,
+        found: &mut Vec<path::PathBuf>,
+    ) -> GameResult {
+        if depth > MAX_WALK_DEPTH {
+            return Err(GameError::FilesystemError(format!(
+                "Directory {:?} is nested more than {} deep; is there a symlink loop?",
+                dir, MAX_WALK_DEPTH
+            )));
+        }
+        // The overlay already gets rid of duplicates.
+        let mut entries = self.vfs.read_dir(dir)?.collect::<GameResult<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            if self.is_dir(&entry) {
+                found.push(entry.clone());
+                self.walk_into(&entry, depth + 1, found)?;
+            } else {
+                found.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns every file and directory matching the given glob
+    /// pattern, sorted.  See [`glob()`](fn.glob.html).
+    pub(crate) fn glob(
+        &mut self,
+        pattern: &str,
+    ) -> GameResult<Box<dyn Iterator<Item = path::PathBuf>>> {
+        let glob = Glob::new(pattern)?;
+        // No sense walking the whole filesystem if the pattern
+        // starts with plain directory names.
+        let base = glob.base();
+        if !glob.has_wildcards() {
+            let found = if self.exists(&base) { Some(base) } else { None };
+            return Ok(Box::new(found.into_iter()));
+        }
+        let found = self
+            .walk_dir(&base)?
+            .filter(|p| glob.matches(p))
+            .collect::<Vec<_>>();
+        Ok(Box::new(found.into_iter()))
+    }
+
+    /// Finds out which layer the given path will be loaded from,
+    /// and which other layers it hides.
+    pub(crate) fn resolve<P: AsRef<path::Path>>(&self, path: P) -> GameResult<Resolution> {
+        let mut layers = self.vfs.resolve(path.as_ref())?;
+        let layer = layers.remove(0);
+        Ok(Resolution {
+            path: path.as_ref().to_path_buf(),
+            layer,
+            shadowed: layers,
+        })
+    }
+
+    /// Returns every layer the filesystem searches, in order.
+    pub(crate) fn layers(&self) -> Vec<Layer> {
+        self.vfs.layers().map(|(layer, _)| layer).collect()
+    }
+
     fn write_to_string(&mut self) -> String {
         use std::fmt::Write;
         let mut s = String::new();
-        for vfs in self.vfs.roots() {
-            write!(s, "Source {:?}", vfs).expect("Could not write to string; should never happen?");
+        for (layer, vfs) in self.vfs.layers() {
+            write!(s, "Source {}", layer).expect("Could not write to string; should never happen?");
             match vfs.read_dir(path::Path::new("/")) {
                 Ok(files) => {
                     for itm in files {
@@ -298,11 +433,19 @@ impl Filesystem {
     /// But it can be very nice for debugging and dev purposes, such as
     /// by pushing `$CARGO_MANIFEST_DIR/resources` to it
     pub(crate) fn mount(&mut self, path: &path::Path, readonly: bool) {
-        let physfs = vfs::PhysicalFS::new(path, readonly);
+        let mut physfs = vfs::PhysicalFS::new(path, readonly);
+        physfs.set_strict(self.strict);
         trace!("Mounting new path: {:?}", physfs);
         self.vfs.push_back(Box::new(physfs));
     }
 
+    /// Turns strict mode on or off for all current and future
+    /// mounted paths.
+    pub(crate) fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+        self.vfs.set_strict(strict);
+    }
+
     /// Adds any object that implements Read + Seek as a zip file.
     ///
     /// Note: This is not intended for system files for the same reasons as
@@ -339,8 +482,9 @@ impl Filesystem {
     /// overwriting any file already there.
     pub(crate) fn write_config(&mut self, conf: &conf::Conf) -> GameResult<()> {
         let conf_path = path::Path::new(CONFIG_NAME);
-        let mut file = self.create(conf_path)?;
-        conf.to_toml_file(&mut file)?;
+        let mut bytes = Vec::new();
+        conf.to_toml_file(&mut bytes)?;
+        self.write_atomic(conf_path, &bytes)?;
         if self.is_file(conf_path) {
             Ok(())
         } else {
@@ -375,6 +519,18 @@ pub fn create<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Fi
     ctx.filesystem.create(path)
 }
 
+/// Replaces the contents of a file in the user directory with the
+/// given bytes, creating it if it doesn't exist.
+///
+/// Unlike writing to a file from [`create()`](fn.create.html), which
+/// empties the file first, this writes a temporary file next to it
+/// and then renames it over the top.  So if the game crashes or the
+/// power goes out halfway through, the file still has either all of
+/// its old contents or all of its new ones, never a mix.
+pub fn write_atomic<P: AsRef<path::Path>>(ctx: &mut Context, path: P, bytes: &[u8]) -> GameResult {
+    ctx.filesystem.write_atomic(path, bytes)
+}
+
 /// Create an empty directory in the user dir
 /// with the given name.  Any parents to that directory
 /// that do not exist will be created.
@@ -435,6 +591,67 @@ pub fn read_dir<P: AsRef<path::Path>>(
     ctx.filesystem.read_dir(path)
 }
 
+/// Returns every file and directory in the given directory and all of
+/// its subdirectories, not including the directory itself.  Just
+/// like [`read_dir()`](fn.read_dir.html) this looks in all the
+/// resource directories and zip files at once, but anything that
+/// exists in more than one of them is only listed once.  The results
+/// are sorted and in depth-first order, so a directory always comes
+/// right before its contents.
+///
+/// Directories that are only implied by the names of the files in a zip
+/// file, rather than having entries of their own, are walked into
+/// just like real ones.
+pub fn walk_dir<P: AsRef<path::Path>>(
+    ctx: &mut Context,
+    path: P,
+) -> GameResult<Box<dyn Iterator<Item = path::PathBuf>>> {
+    ctx.filesystem.walk_dir(path)
+}
+
+/// Returns every file and directory whose path matches the given glob
+/// pattern, sorted.  Like every other path, the pattern is relative to
+/// the root of the resource directories, but the leading `/` is
+/// optional, so `levels/**/*.toml` and `/levels/**/*.toml` are the same.
+///
+/// The pattern syntax is:
+///
+/// * `?` matches any single character except `/`
+/// * `*` matches any number of characters except `/`
+/// * `**`, as a whole path component, matches any number of
+///   directories, including none
+/// * `[abc]` matches any one of the characters in the brackets, and
+///   ranges such as `[a-z]` work too.  `[!abc]` matches any character
+///   that isn't in the brackets.
+///
+/// Returns an error if the pattern is malformed.
+pub fn glob(
+    ctx: &mut Context,
+    pattern: &str,
+) -> GameResult<Box<dyn Iterator<Item = path::PathBuf>>> {
+    ctx.filesystem.glob(pattern)
+}
+
+/// Finds out where the given path will actually be loaded from.  If more
+/// than one of the resource directories or zip files has something at
+/// that path, the first one searched wins and all the others are
+/// ignored, which is how you override a game's resources with
+/// modified ones.  This tells you which layer won and which other
+/// layers it hid, which is handy for figuring out why a mod isn't
+/// taking effect.
+///
+/// Returns `GameError::ResourceNotFound` if nothing has the path,
+/// along with why not for each layer searched.
+pub fn resolve<P: AsRef<path::Path>>(ctx: &Context, path: P) -> GameResult<Resolution> {
+    ctx.filesystem.resolve(path)
+}
+
+/// Returns every layer of the filesystem, that is, all the resource
+/// directories and zip files, in the order they are searched.
+pub fn layers(ctx: &Context) -> Vec<Layer> {
+    ctx.filesystem.layers()
+}
+
 /// Prints the contents of all data directories.
 /// Useful for debugging.
 pub fn print_all(ctx: &mut Context) {
@@ -462,6 +679,29 @@ pub fn mount(ctx: &mut Context, path: &path::Path, readonly: bool) {
     ctx.filesystem.mount(path, readonly)
 }
 
+/// Turns strict mode on or off.
+///
+/// In strict mode every path is resolved, following any symlinks,
+/// before it is used, and anything that would end up outside of the
+/// directory it is being looked up in is refused with
+/// `GameError::SandboxViolation`.  So are paths containing `..`.
+/// This applies to all mounted directories, including ones mounted
+/// later on; zip files can't contain symlinks so they are always safe.
+///
+/// It is off by default.  Turn it on if you load files you don't
+/// trust, such as user-made mods, though remember that it can't stop
+/// other programs from changing the files between ggez checking a
+/// path and actually opening it.
+pub fn set_strict(ctx: &mut Context, strict: bool) {
+    ctx.filesystem.set_strict(strict)
+}
+
+/// Returns whether or not strict mode is on.  See
+/// [`set_strict()`](fn.set_strict.html).
+pub fn is_strict(ctx: &Context) -> bool {
+    ctx.filesystem.strict
+}
+
 /// Looks for a file named `/conf.toml` in any resource directory and
 /// loads it if it finds it.
 /// If it can't read it for some reason, returns an error.
@@ -475,12 +715,376 @@ pub fn write_config(ctx: &mut Context, conf: &conf::Conf) -> GameResult {
     ctx.filesystem.write_config(conf)
 }
 
+/// A set of save slots for storing game state, such as save games
+/// or player settings, in the user directory.
+///
+/// Each slot is a TOML file in the slot directory, `/saves` by
+/// default, holding anything that `serde` can serialize into a TOML
+/// table, which means structs and maps.  Slots are always written with
+/// [`write_atomic()`](fn.write_atomic.html), so a crash while saving
+/// never corrupts them.  In case a save goes wrong in some other way,
+/// such as a bug writing bad data, the last few versions of each slot
+/// are also kept as backups; by default, 2 of them.
+///
+/// ```rust,no_run
+/// # use serde_derive::{Serialize, Deserialize};
+/// # use ggez::{Context, GameResult};
+/// # use ggez::filesystem::SaveSlots;
+/// #[derive(Serialize, Deserialize)]
+/// struct SaveGame {
+///     level: u32,
+///     score: u64,
+/// }
+///
+/// # fn f(ctx: &mut Context) -> GameResult {
+/// let slots = SaveSlots::new("/saves").backups(3);
+/// slots.save(ctx, "slot1", &SaveGame { level: 3, score: 9001 })?;
+/// for name in slots.list(ctx)? {
+///     let game: SaveGame = slots.load(ctx, &name)?;
+///     println!("{}: level {}", name, game.level);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveSlots {
+    dir: path::PathBuf,
+    backups: usize,
+}
+
+impl Default for SaveSlots {
+    fn default() -> Self {
+        SaveSlots::new("/saves")
+    }
+}
+
+impl SaveSlots {
+    /// Creates a new set of save slots, stored in the given directory.
+    pub fn new<P: Into<path::PathBuf>>(dir: P) -> Self {
+        SaveSlots {
+            dir: dir.into(),
+            backups: 2,
+        }
+    }
+
+    /// Sets how many old versions of each slot to keep around.
+    pub fn backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Returns the names of all the save slots there are, sorted.
+    pub fn list(&self, ctx: &mut Context) -> GameResult<Vec<String>> {
+        self.list_in(&mut ctx.filesystem)
+    }
+
+    /// Returns whether or not the given slot has anything saved in it.
+    pub fn exists(&self, ctx: &Context, slot: &str) -> bool {
+        self.slot_path(slot, 0)
+            .map(|p| ctx.filesystem.is_file(p))
+            .unwrap_or(false)
+    }
+
+    /// Saves the given state into the slot, overwriting what was
+    /// there before (but keeping it as a backup).
+    ///
+    /// Slot names can be anything that is a valid file name and
+    /// doesn't start with a `.`.
+    pub fn save<T: Serialize>(&self, ctx: &mut Context, slot: &str, state: &T) -> GameResult {
+        self.save_in(&mut ctx.filesystem, slot, state)
+    }
+
+    /// Loads the state saved in the slot.
+    pub fn load<T: DeserializeOwned>(&self, ctx: &mut Context, slot: &str) -> GameResult<T> {
+        self.load_in(&mut ctx.filesystem, slot, 0)
+    }
+
+    /// Loads one of the slot's backups.  Backup 1 is the state that was
+    /// saved just before the current one, backup 2 the one before that,
+    /// and so on.
+    pub fn load_backup<T: DeserializeOwned>(
+        &self,
+        ctx: &mut Context,
+        slot: &str,
+        backup: usize,
+    ) -> GameResult<T> {
+        if backup == 0 {
+            return Err(GameError::FilesystemError(String::from(
+                "Save slot backups are numbered starting at 1",
+            )));
+        }
+        self.load_in(&mut ctx.filesystem, slot, backup)
+    }
+
+    /// Deletes the slot, along with all of its backups.
+    pub fn delete(&self, ctx: &mut Context, slot: &str) -> GameResult {
+        self.delete_in(&mut ctx.filesystem, slot)
+    }
+
+    /// The path of the given slot, or of one of its backups if
+    /// `backup` isn't 0.
+    fn slot_path(&self, slot: &str, backup: usize) -> GameResult<path::PathBuf> {
+        if slot.is_empty() || slot.starts_with('.') || slot.contains(&['/', '\\'][..]) {
+            return Err(GameError::FilesystemError(format!(
+                "Invalid save slot name: {:?}",
+                slot
+            )));
+        }
+        let name = if backup == 0 {
+            format!("{}.toml", slot)
+        } else {
+            format!("{}.toml.bak{}", slot, backup)
+        };
+        Ok(self.dir.join(name))
+    }
+
+    fn list_in(&self, fs: &mut Filesystem) -> GameResult<Vec<String>> {
+        let mut slots = fs
+            .read_dir(&self.dir)?
+            .filter(|p| fs.is_file(p))
+            .filter_map(|p| {
+                let name = p.file_name()?.to_str()?;
+                if name.starts_with('.') || !name.ends_with(".toml") {
+                    None
+                } else {
+                    Some(name[..name.len() - ".toml".len()].to_string())
+                }
+            })
+            .collect::<Vec<_>>();
+        slots.sort();
+        Ok(slots)
+    }
+
+    fn save_in<T: Serialize>(&self, fs: &mut Filesystem, slot: &str, state: &T) -> GameResult {
+        let contents = toml::to_string(state)?;
+        let path = self.slot_path(slot, 0)?;
+        fs.create_dir(&self.dir)?;
+        if self.backups > 0 && fs.is_file(&path) {
+            // Shuffle the existing backups along, which drops the oldest...
+            for n in (1..self.backups).rev() {
+                let backup = self.slot_path(slot, n)?;
+                if fs.is_file(&backup) {
+                    fs.rename(&backup, self.slot_path(slot, n + 1)?)?;
+                }
+            }
+            // ...and copy the current save rather than moving it, so
+            // there is always something in the slot.
+            let mut old = Vec::new();
+            let _ = fs.open(&path)?.read_to_end(&mut old)?;
+            fs.write_atomic(self.slot_path(slot, 1)?, &old)?;
+        }
+        fs.write_atomic(&path, contents.as_bytes())
+    }
+
+    fn load_in<T: DeserializeOwned>(
+        &self,
+        fs: &mut Filesystem,
+        slot: &str,
+        backup: usize,
+    ) -> GameResult<T> {
+        let mut contents = String::new();
+        let _ = fs
+            .open(self.slot_path(slot, backup)?)?
+            .read_to_string(&mut contents)?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    fn delete_in(&self, fs: &mut Filesystem, slot: &str) -> GameResult {
+        fs.delete(self.slot_path(slot, 0)?)?;
+        // Also catch any left over from when there were more backups.
+        let mut n = 1;
+        loop {
+            let backup = self.slot_path(slot, n)?;
+            if fs.is_file(&backup) {
+                fs.delete(&backup)?;
+            } else if n > self.backups {
+                return Ok(());
+            }
+            n += 1;
+        }
+    }
+}
+
+/// One piece of a path component in a glob pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum GlobToken {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyChars,
+    /// `[...]`, with whether it was negated and the (inclusive)
+    /// character ranges in it.
+    Class(bool, Vec<(char, char)>),
+}
+
+/// A path component in a glob pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum GlobComponent {
+    /// `**`
+    AnyDirs,
+    Tokens(Vec<GlobToken>),
+}
+
+impl GlobComponent {
+    /// Returns the name this component matches, if it has no
+    /// wildcards in it.
+    fn literal(&self) -> Option<String> {
+        match self {
+            GlobComponent::AnyDirs => None,
+            GlobComponent::Tokens(tokens) => tokens
+                .iter()
+                .map(|t| match t {
+                    GlobToken::Char(c) => Some(*c),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A parsed glob pattern, see [`glob()`](fn.glob.html).
+#[derive(Debug, Clone, PartialEq)]
+struct Glob {
+    components: Vec<GlobComponent>,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> GameResult<Glob> {
+        let components = pattern
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(|c| {
+                if c == "**" {
+                    Ok(GlobComponent::AnyDirs)
+                } else {
+                    Glob::parse_component(pattern, c).map(GlobComponent::Tokens)
+                }
+            })
+            .collect::<GameResult<Vec<_>>>()?;
+        Ok(Glob { components })
+    }
+
+    fn parse_component(pattern: &str, component: &str) -> GameResult<Vec<GlobToken>> {
+        let invalid = |why: &str| {
+            GameError::FilesystemError(format!("Invalid glob pattern {:?}: {}", pattern, why))
+        };
+        let mut tokens = Vec::new();
+        let mut chars = component.chars();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => GlobToken::AnyChar,
+                '*' => GlobToken::AnyChars,
+                '[' => {
+                    let mut class: Vec<char> = Vec::new();
+                    loop {
+                        match chars.next() {
+                            // A `]` right at the start is just a character.
+                            Some(']') if !class.is_empty() && class != ['!'] => break,
+                            Some(c) => class.push(c),
+                            None => return Err(invalid("unclosed `[`")),
+                        }
+                    }
+                    let negated = class[0] == '!';
+                    let class = if negated { &class[1..] } else { &class[..] };
+                    let mut ranges = Vec::new();
+                    let mut i = 0;
+                    while i < class.len() {
+                        if i + 2 < class.len() && class[i + 1] == '-' {
+                            if class[i] > class[i + 2] {
+                                return Err(invalid("backwards character range"));
+                            }
+                            ranges.push((class[i], class[i + 2]));
+                            i += 3;
+                        } else {
+                            ranges.push((class[i], class[i]));
+                            i += 1;
+                        }
+                    }
+                    GlobToken::Class(negated, ranges)
+                }
+                c => GlobToken::Char(c),
+            };
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    fn has_wildcards(&self) -> bool {
+        self.components.iter().any(|c| c.literal().is_none())
+    }
+
+    /// The directory made of all the components at the start of the
+    /// pattern that have no wildcards in them, or the whole path if
+    /// there are no wildcards at all.
+    fn base(&self) -> path::PathBuf {
+        let mut base = path::PathBuf::from("/");
+        for name in self.components.iter().map(GlobComponent::literal) {
+            match name {
+                Some(name) => base.push(name),
+                None => break,
+            }
+        }
+        base
+    }
+
+    fn matches(&self, path: &path::Path) -> bool {
+        let names = path
+            .components()
+            .filter_map(|c| match c {
+                path::Component::Normal(s) => s.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        Glob::match_components(&self.components, &names)
+    }
+
+    fn match_components(components: &[GlobComponent], names: &[&str]) -> bool {
+        match components.split_first() {
+            None => names.is_empty(),
+            Some((GlobComponent::AnyDirs, rest)) => {
+                (0..=names.len()).any(|i| Glob::match_components(rest, &names[i..]))
+            }
+            Some((GlobComponent::Tokens(tokens), rest)) => match names.split_first() {
+                Some((name, names_rest)) => {
+                    let name = name.chars().collect::<Vec<_>>();
+                    Glob::match_tokens(tokens, &name) && Glob::match_components(rest, names_rest)
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn match_tokens(tokens: &[GlobToken], name: &[char]) -> bool {
+        match tokens.split_first() {
+            None => name.is_empty(),
+            Some((GlobToken::AnyChars, rest)) => {
+                (0..=name.len()).any(|i| Glob::match_tokens(rest, &name[i..]))
+            }
+            Some((token, rest)) => match name.split_first() {
+                Some((c, name_rest)) => {
+                    let ok = match token {
+                        GlobToken::Char(t) => t == c,
+                        GlobToken::AnyChar => true,
+                        GlobToken::Class(negated, ranges) => {
+                            ranges.iter().any(|&(lo, hi)| lo <= *c && *c <= hi) != *negated
+                        }
+                        GlobToken::AnyChars => unreachable!(),
+                    };
+                    ok && Glob::match_tokens(rest, name_rest)
+                }
+                None => false,
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::conf;
     use crate::error::*;
     use crate::filesystem::*;
-    use std::io::{Read, Write};
+    use std::io::{self, Read, Write};
     use std::path;
 
     fn dummy_fs_for_tests() -> Filesystem {
@@ -496,6 +1100,7 @@ mod tests {
             zip_path: "".into(),
             user_config_path: "".into(),
             user_data_path: "".into(),
+            strict: false,
         }
     }
 
@@ -564,6 +1169,208 @@ mod tests {
         }
     }
 
+    #[test]
+    fn headless_test_glob_matching() {
+        let matches =
+            |pattern: &str, path: &str| Glob::new(pattern).unwrap().matches(path::Path::new(path));
+        assert!(matches("levels/**/*.toml", "/levels/one.toml"));
+        assert!(matches("/levels/**/*.toml", "/levels/a/b/one.toml"));
+        assert!(!matches("levels/**/*.toml", "/levels/one.png"));
+        assert!(!matches("levels/*.toml", "/levels/a/one.toml"));
+        assert!(matches("**", "/levels/a"));
+        assert!(matches("level?.toml", "/level1.toml"));
+        assert!(!matches("level?.toml", "/level10.toml"));
+        assert!(matches("level[0-9].toml", "/level3.toml"));
+        assert!(!matches("level[!0-9].toml", "/level3.toml"));
+        assert!(matches("level[!0-9].toml", "/levelx.toml"));
+        assert!(matches("[]]", "/]"));
+        assert!(matches("*", "/.hidden"));
+
+        assert!(Glob::new("level[0-9.toml").is_err());
+        assert!(Glob::new("level[9-0].toml").is_err());
+        assert_eq!(
+            Glob::new("levels/a/*/b").unwrap().base(),
+            path::Path::new("/levels/a")
+        );
+        assert_eq!(Glob::new("**/b").unwrap().base(), path::Path::new("/"));
+    }
+
+    #[test]
+    fn headless_test_walk_dir_and_glob() {
+        let dir = std::env::temp_dir().join("ggez_headless_test_walk_dir");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("levels/b")).unwrap();
+        std::fs::write(dir.join("levels/two.toml"), b"physical").unwrap();
+        std::fs::write(dir.join("levels/b/three.toml"), b"").unwrap();
+
+        // Only has implicit directories.
+        let zip_bytes = {
+            let mut zip_archive = zip::ZipWriter::new(io::Cursor::new(vec![]));
+            let options = zip::write::FileOptions::default();
+            for name in &["levels/a/one.toml", "levels/two.toml", "other.png"] {
+                zip_archive.start_file(*name, options).unwrap();
+            }
+            zip_archive.finish().unwrap().into_inner()
+        };
+
+        let mut fs = dummy_fs_for_tests();
+        fs.vfs = vfs::OverlayFS::new();
+        fs.mount(&dir, true);
+        fs.add_zip_file(io::Cursor::new(zip_bytes)).unwrap();
+
+        let walked = fs.walk_dir("/levels").unwrap().collect::<Vec<_>>();
+        let expected = [
+            "/levels/a",
+            "/levels/a/one.toml",
+            "/levels/b",
+            "/levels/b/three.toml",
+            "/levels/two.toml",
+        ];
+        assert_eq!(
+            walked,
+            expected.iter().map(path::PathBuf::from).collect::<Vec<_>>()
+        );
+
+        let globbed = fs.glob("levels/**/*.toml").unwrap().collect::<Vec<_>>();
+        let expected = [
+            "/levels/a/one.toml",
+            "/levels/b/three.toml",
+            "/levels/two.toml",
+        ];
+        assert_eq!(
+            globbed,
+            expected.iter().map(path::PathBuf::from).collect::<Vec<_>>()
+        );
+        assert_eq!(fs.glob("/other.png").unwrap().count(), 1);
+        assert_eq!(fs.glob("/levels/*/*.png").unwrap().count(), 0);
+
+        // The physical copy wins.
+        let mut contents = String::new();
+        let _ = fs
+            .open("/levels/two.toml")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "physical");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn headless_test_resolve() {
+        let dir = std::env::temp_dir().join("ggez_headless_test_resolve");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("both.txt"), b"").unwrap();
+
+        let zip_bytes = {
+            let mut zip_archive = zip::ZipWriter::new(io::Cursor::new(vec![]));
+            let options = zip::write::FileOptions::default();
+            for name in &["both.txt", "zip_only.txt"] {
+                zip_archive.start_file(*name, options).unwrap();
+            }
+            zip_archive.finish().unwrap().into_inner()
+        };
+
+        let mut fs = dummy_fs_for_tests();
+        fs.vfs = vfs::OverlayFS::new();
+        fs.mount(&dir, true);
+        fs.add_zip_file(io::Cursor::new(zip_bytes)).unwrap();
+        let dir_layer = Layer {
+            index: 0,
+            source: LayerSource::Directory {
+                root: dir.clone(),
+                readonly: true,
+            },
+        };
+        let zip_layer = Layer {
+            index: 1,
+            source: LayerSource::ZipReader,
+        };
+        assert_eq!(fs.layers(), vec![dir_layer.clone(), zip_layer.clone()]);
+
+        let r = fs.resolve("/both.txt").unwrap();
+        assert_eq!(r.layer, dir_layer);
+        assert_eq!(r.shadowed, vec![zip_layer.clone()]);
+
+        let r = fs.resolve("/zip_only.txt").unwrap();
+        assert_eq!(r.layer, zip_layer);
+        assert!(r.shadowed.is_empty());
+
+        match fs.resolve("/neither.txt") {
+            Err(GameError::ResourceNotFound(_, tried)) => {
+                let tried = tried.into_iter().map(|(l, _)| l).collect::<Vec<_>>();
+                assert_eq!(tried, vec![dir_layer, zip_layer]);
+            }
+            other => panic!("Expected ResourceNotFound, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn headless_test_write_atomic() {
+        let mut fs = dummy_fs_for_tests();
+        let test_file = path::Path::new("/atomic_test.txt");
+        fs.write_atomic(test_file, b"first").unwrap();
+        fs.write_atomic(test_file, b"second").unwrap();
+
+        let mut contents = String::new();
+        let _ = fs
+            .open(test_file)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+        // No temporary files left lying around.
+        assert!(!fs
+            .read_dir("/")
+            .unwrap()
+            .any(|p| p.to_string_lossy().contains("atomic_test.txt.")));
+        fs.delete(test_file).unwrap();
+    }
+
+    #[test]
+    fn headless_test_save_slots() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct State {
+            level: u32,
+            name: String,
+        }
+        let state = |level| State {
+            level,
+            name: "ferris".into(),
+        };
+
+        let mut fs = dummy_fs_for_tests();
+        let slots = SaveSlots::new("/save_slot_test").backups(2);
+        let _ = fs.delete_dir("/save_slot_test");
+        assert!(slots.list_in(&mut fs).unwrap().is_empty());
+
+        for level in 1..=4 {
+            slots.save_in(&mut fs, "one", &state(level)).unwrap();
+        }
+        slots.save_in(&mut fs, "two", &state(10)).unwrap();
+        assert_eq!(slots.list_in(&mut fs).unwrap(), vec!["one", "two"]);
+
+        let loaded: State = slots.load_in(&mut fs, "one", 0).unwrap();
+        assert_eq!(loaded, state(4));
+        let loaded: State = slots.load_in(&mut fs, "one", 1).unwrap();
+        assert_eq!(loaded, state(3));
+        let loaded: State = slots.load_in(&mut fs, "one", 2).unwrap();
+        assert_eq!(loaded, state(2));
+        assert!(slots.load_in::<State>(&mut fs, "one", 3).is_err());
+        assert!(slots.load_in::<State>(&mut fs, "two", 1).is_err());
+
+        assert!(slots.save_in(&mut fs, "../escape", &state(1)).is_err());
+        assert!(slots.save_in(&mut fs, ".hidden", &state(1)).is_err());
+
+        slots.delete_in(&mut fs, "one").unwrap();
+        assert_eq!(slots.list_in(&mut fs).unwrap(), vec!["two"]);
+        assert!(!fs.exists("/save_slot_test/one.toml.bak1"));
+        fs.delete_dir("/save_slot_test").unwrap();
+    }
+
     #[test]
     fn headless_test_write_config() {
         let mut f = dummy_fs_for_tests();
@@ -0,0 +1,555 @@
+//! Frame-based sprite animations.
+//!
+//! An [`Animation`](struct.Animation.html) is just a list of
+//! [`Frame`](struct.Frame.html)s, each a region of an image plus how long
+//! to show it for, along with a [`PlayMode`](enum.PlayMode.html) saying
+//! what to do once the last frame is reached.  It holds no playback state,
+//! so one `Animation` can be shared by any number of sprites.
+//!
+//! An [`AnimatedSprite`](struct.AnimatedSprite.html) plays an `Animation`
+//! on a particular `Image`.  Call its
+//! [`update()`](struct.AnimatedSprite.html#method.update) once per frame
+//! and then either draw it directly, or use
+//! [`param()`](struct.AnimatedSprite.html#method.param) to get a `DrawParam`
+//! with the right `src` to add to a
+//! [`SpriteBatch`](../spritebatch/struct.SpriteBatch.html).
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::*;
+//! # use ggez::graphics::animation::*;
+//! # use std::time::Duration;
+//! # fn main() -> GameResult {
+//! # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+//! let image = Image::new(ctx, "/player_sheet.png")?;
+//! // The first four 32x32 cells of the sheet, a tenth of a second each.
+//! let walk = Animation::from_grid(&image, 32, 32, &[0, 1, 2, 3], Duration::from_millis(100))?
+//!     .mode(PlayMode::PingPong);
+//! let mut player = AnimatedSprite::new(image, walk);
+//!
+//! // Every frame:
+//! player.update(ctx);
+//! graphics::draw(ctx, &player, DrawParam::new().dest([100.0, 100.0]))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::spritebatch::{SpriteBatch, SpriteIdx};
+use crate::graphics::{self, BlendMode, DrawParam, Drawable, Image, Rect};
+use crate::timer;
+
+/// A single frame of an [`Animation`](struct.Animation.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// The region of the image to show, as a fraction of the whole
+    /// image, the same as [`DrawParam::src`](../struct.DrawParam.html#structfield.src).
+    pub src: Rect,
+    /// How long to show the frame for.
+    pub duration: Duration,
+    /// Where the top left of the frame goes in the whole sprite, in
+    /// pixels, for frames whose transparent borders were trimmed off
+    /// when packing them.  Zero otherwise.
+    pub offset: mint::Point2<f32>,
+}
+
+impl Frame {
+    /// Creates a new `Frame` without an offset.
+    pub fn new(src: Rect, duration: Duration) -> Self {
+        Frame {
+            src,
+            duration,
+            offset: mint::Point2 { x: 0.0, y: 0.0 },
+        }
+    }
+
+    /// Sets the frame's offset.
+    pub fn offset<P>(mut self, offset: P) -> Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        self.offset = offset.into();
+        self
+    }
+}
+
+/// What an animation does once it gets to its last frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, SmartDefault)]
+pub enum PlayMode {
+    /// Start over from the first frame.
+    #[default]
+    Loop,
+    /// Play backwards to the first frame, then forwards again, and so on.
+    PingPong,
+    /// Stay on the last frame.
+    Once,
+}
+
+/// A sequence of frames and how to play them.
+///
+/// This is only the definition of an animation; see
+/// [`AnimatedSprite`](struct.AnimatedSprite.html) for actually playing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    frames: Vec<Frame>,
+    mode: PlayMode,
+}
+
+impl Animation {
+    /// Creates a new looping `Animation` from the given frames.
+    pub fn new(frames: Vec<Frame>) -> Self {
+        Animation {
+            frames,
+            mode: PlayMode::default(),
+        }
+    }
+
+    /// Creates a new looping `Animation` from cells of a sprite sheet
+    /// laid out in a grid of `frame_width` by `frame_height` pixel cells.
+    ///
+    /// Cells are numbered left to right, top to bottom, starting at 0;
+    /// `cells` lists the ones to use in the order to play them.
+    /// Every frame lasts `frame_duration`; see
+    /// [`frame_durations()`](#method.frame_durations) to change that.
+    pub fn from_grid(
+        image: &Image,
+        frame_width: u16,
+        frame_height: u16,
+        cells: &[usize],
+        frame_duration: Duration,
+    ) -> GameResult<Self> {
+        if frame_width == 0 || frame_height == 0 {
+            let msg = format!(
+                "Tried to create an animation with frames of size {}x{}, each dimension must be >0",
+                frame_width, frame_height
+            );
+            return Err(GameError::RenderError(msg));
+        }
+        let columns = (image.width() / frame_width) as usize;
+        let rows = (image.height() / frame_height) as usize;
+        let rects = cells
+            .iter()
+            .map(|&cell| {
+                if cell >= columns * rows {
+                    let msg = format!(
+                        "Animation frame {} is out of bounds, the image only has {}x{} frames",
+                        cell, columns, rows
+                    );
+                    return Err(GameError::RenderError(msg));
+                }
+                Ok(Rect::new(
+                    f32::from(frame_width) * (cell % columns) as f32,
+                    f32::from(frame_height) * (cell / columns) as f32,
+                    f32::from(frame_width),
+                    f32::from(frame_height),
+                ))
+            })
+            .collect::<GameResult<Vec<_>>>()?;
+        Ok(Self::from_rects(image, &rects, frame_duration))
+    }
+
+    /// Creates a new looping `Animation` from the given regions of an
+    /// image, in pixels.
+    ///
+    /// Every frame lasts `frame_duration`; see
+    /// [`frame_durations()`](#method.frame_durations) to change that.
+    pub fn from_rects(image: &Image, rects: &[Rect], frame_duration: Duration) -> Self {
+        let dimensions = image.dimensions();
+        let frames = rects
+            .iter()
+            .map(|r| {
+                let src = Rect::fraction(r.x, r.y, r.w, r.h, &dimensions);
+                Frame::new(src, frame_duration)
+            })
+            .collect();
+        Self::new(frames)
+    }
+
+    /// Sets what happens once the animation gets to its last frame.
+    pub fn mode(mut self, mode: PlayMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the durations of the frames, in order.  If fewer durations
+    /// than frames are given, the rest of the frames are left alone.
+    pub fn frame_durations(mut self, durations: &[Duration]) -> Self {
+        for (frame, &duration) in self.frames.iter_mut().zip(durations) {
+            frame.duration = duration;
+        }
+        self
+    }
+
+    /// Returns the frames of the animation.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the frames of the animation, mutably.
+    pub fn frames_mut(&mut self) -> &mut [Frame] {
+        &mut self.frames
+    }
+
+    /// Returns the animation's play mode.
+    pub fn play_mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    /// Returns how long it takes to play through every frame once.
+    pub fn total_duration(&self) -> Duration {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+}
+
+/// Where playback of an animation is at.  Kept separate from
+/// `AnimatedSprite` so it can be tested without an `Image`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Playback {
+    frame: usize,
+    elapsed: Duration,
+    backwards: bool,
+    finished: bool,
+}
+
+impl Playback {
+    fn advance(&mut self, animation: &Animation, dt: Duration) {
+        let count = animation.frames.len();
+        // With no time to spend on any frame we'd never get anywhere.
+        if self.finished || count == 0 || animation.total_duration() == Duration::from_secs(0) {
+            return;
+        }
+        self.elapsed += dt;
+        // Going all the way around a repeating animation ends up right
+        // where it started, so skip whole cycles instead of stepping
+        // through every frame of them.
+        if let Some(cycle) = cycle_duration(animation) {
+            if self.elapsed >= cycle {
+                let left = self.elapsed.as_nanos() % cycle.as_nanos();
+                self.elapsed = Duration::from_nanos(left as u64);
+            }
+        }
+        while self.elapsed >= animation.frames[self.frame].duration {
+            self.elapsed -= animation.frames[self.frame].duration;
+            self.step(animation.mode, count);
+            if self.finished {
+                self.elapsed = Duration::from_secs(0);
+                break;
+            }
+        }
+    }
+
+    fn step(&mut self, mode: PlayMode, count: usize) {
+        let last = count - 1;
+        match mode {
+            PlayMode::Loop => {
+                self.frame = if self.frame >= last {
+                    0
+                } else {
+                    self.frame + 1
+                }
+            }
+            PlayMode::Once => {
+                if self.frame >= last {
+                    self.frame = last;
+                    self.finished = true;
+                } else {
+                    self.frame += 1;
+                }
+            }
+            PlayMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.backwards && self.frame == 0 {
+                    self.backwards = false;
+                } else if !self.backwards && self.frame >= last {
+                    self.backwards = true;
+                }
+                if self.backwards {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+        }
+    }
+}
+
+/// How long it takes a repeating animation to get back to where it
+/// started, or `None` if it doesn't repeat.
+fn cycle_duration(animation: &Animation) -> Option<Duration> {
+    let total = animation.total_duration();
+    match (animation.mode, animation.frames.as_slice()) {
+        (PlayMode::Once, _) => None,
+        (PlayMode::Loop, _) | (PlayMode::PingPong, [_]) => Some(total),
+        // There and back again, without repeating the ends.
+        (PlayMode::PingPong, frames) => {
+            let ends = frames
+                .first()
+                .map_or(Duration::from_secs(0), |f| f.duration)
+                + frames.last().map_or(Duration::from_secs(0), |f| f.duration);
+            Some(total * 2 - ends)
+        }
+    }
+}
+
+/// An [`Animation`](struct.Animation.html) being played on an `Image`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimatedSprite {
+    image: Image,
+    animation: Animation,
+    playback: Playback,
+    paused: bool,
+}
+
+impl AnimatedSprite {
+    /// Creates a new `AnimatedSprite` that plays the given animation on
+    /// the given image, starting at the first frame.
+    pub fn new(image: Image, animation: Animation) -> Self {
+        AnimatedSprite {
+            image,
+            animation,
+            playback: Playback::default(),
+            paused: false,
+        }
+    }
+
+    /// Advances the animation by the length of the last frame, as
+    /// returned by [`timer::delta()`](../../timer/fn.delta.html).
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::delta(ctx));
+    }
+
+    /// Advances the animation by the given amount of time.
+    pub fn advance(&mut self, dt: Duration) {
+        if !self.paused {
+            self.playback.advance(&self.animation, dt);
+        }
+    }
+
+    /// Stops the animation from advancing until [`resume()`](#method.resume)
+    /// is called.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Lets a paused animation advance again.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns true if the animation is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns true if the animation is a `PlayMode::Once` one and
+    /// has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.playback.finished
+    }
+
+    /// Goes back to the start of the first frame.
+    pub fn reset(&mut self) {
+        self.playback = Playback::default();
+    }
+
+    /// Returns the index of the frame currently showing.
+    pub fn current_frame(&self) -> usize {
+        self.playback.frame
+    }
+
+    /// Jumps to the start of the given frame.
+    pub fn set_current_frame(&mut self, frame: usize) -> GameResult {
+        if frame < self.animation.frames.len() {
+            self.playback = Playback {
+                frame,
+                ..Playback::default()
+            };
+            Ok(())
+        } else {
+            Err(GameError::RenderError(String::from(
+                "Provided index is out of bounds.",
+            )))
+        }
+    }
+
+    /// Returns the `src` rect of the frame currently showing, or the
+    /// whole image if the animation has no frames.
+    pub fn src(&self) -> Rect {
+        self.animation
+            .frames
+            .get(self.playback.frame)
+            .map(|f| f.src)
+            .unwrap_or_else(Rect::one)
+    }
+
+    /// Returns the given `DrawParam` with its `src` set to the frame
+    /// currently showing, for adding the sprite to a `SpriteBatch`
+    /// drawing the same image.
+    ///
+    /// The `dest` is moved by the frame's offset, scaled and rotated
+    /// along with the frame, so that trimmed frames stay where they
+    /// were in the whole sprite.  `DrawParam::offset` is still relative
+    /// to the frame as it is drawn.
+    pub fn param(&self, param: DrawParam) -> DrawParam {
+        match self.animation.frames.get(self.playback.frame) {
+            Some(frame) => frame_param(frame, param),
+            None => param.src(Rect::one()),
+        }
+    }
+
+    /// Adds the frame currently showing to the given `SpriteBatch`,
+    /// which should be drawing the same image.
+    pub fn add_to(&self, batch: &mut SpriteBatch, param: DrawParam) -> SpriteIdx {
+        batch.add(self.param(param))
+    }
+
+    /// Switches to a different animation, starting at its first frame.
+    pub fn set_animation(&mut self, animation: Animation) {
+        self.animation = animation;
+        self.reset();
+    }
+
+    /// Returns the animation being played.
+    pub fn animation(&self) -> &Animation {
+        &self.animation
+    }
+
+    /// Returns the image the animation is played on.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+}
+
+/// Sets up a `DrawParam` to draw the given frame.
+fn frame_param(frame: &Frame, param: DrawParam) -> DrawParam {
+    let x = frame.offset.x * param.scale.x;
+    let y = frame.offset.y * param.scale.y;
+    let (sin, cos) = param.rotation.sin_cos();
+    let mut param = param.src(frame.src);
+    param.dest.x += x * cos - y * sin;
+    param.dest.y += x * sin + y * cos;
+    param
+}
+
+impl Drawable for AnimatedSprite {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.image.draw(ctx, self.param(param))
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        let src = self.src();
+        let size = self.image.dimensions();
+        Some(Rect::new(0.0, 0.0, src.w * size.w, src.h * size.h))
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        graphics::Drawable::set_blend_mode(&mut self.image, mode);
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.image.blend_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(durations: &[u64]) -> Animation {
+        let frames = durations
+            .iter()
+            .map(|&ms| Frame::new(Rect::one(), Duration::from_millis(ms)))
+            .collect();
+        Animation::new(frames)
+    }
+
+    fn play(animation: &Animation, steps: usize, dt: u64) -> Vec<usize> {
+        let mut playback = Playback::default();
+        (0..steps)
+            .map(|_| {
+                playback.advance(animation, Duration::from_millis(dt));
+                playback.frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn headless_test_animation_modes() {
+        let animation = frames(&[10, 10, 10]);
+        assert_eq!(play(&animation, 7, 10), vec![1, 2, 0, 1, 2, 0, 1]);
+
+        let animation = frames(&[10, 10, 10]).mode(PlayMode::PingPong);
+        assert_eq!(play(&animation, 7, 10), vec![1, 2, 1, 0, 1, 2, 1]);
+
+        let animation = frames(&[10, 10, 10]).mode(PlayMode::Once);
+        assert_eq!(play(&animation, 5, 10), vec![1, 2, 2, 2, 2]);
+
+        let mut playback = Playback::default();
+        playback.advance(&animation, Duration::from_millis(1000));
+        assert_eq!(playback.frame, 2);
+        assert!(playback.finished);
+
+        // A single frame never goes anywhere.
+        let animation = frames(&[10]).mode(PlayMode::PingPong);
+        assert_eq!(play(&animation, 3, 10), vec![0, 0, 0]);
+        // Nor does one that takes no time at all.
+        let animation = frames(&[0, 0]);
+        assert_eq!(play(&animation, 3, 10), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn headless_test_animation_frame_durations() {
+        let animation = frames(&[10, 10, 10])
+            .frame_durations(&[Duration::from_millis(5), Duration::from_millis(20)]);
+        assert_eq!(animation.total_duration(), Duration::from_millis(35));
+        // 5ms on the first frame, 20 on the second, 10 on the third.
+        assert_eq!(play(&animation, 8, 5), vec![1, 1, 1, 1, 2, 2, 0, 1]);
+        // Big steps can skip over frames entirely, or all the way around.
+        assert_eq!(play(&animation, 1, 30), vec![2]);
+        assert_eq!(play(&animation, 2, 35), vec![0, 0]);
+    }
+
+    #[test]
+    fn headless_test_frame_offset() {
+        let frame = Frame::new(Rect::new(0.5, 0.0, 0.25, 1.0), Duration::from_millis(10))
+            .offset([2.0, 1.0]);
+        let param = frame_param(&frame, DrawParam::new().dest([100.0, 50.0]));
+        assert_eq!(param.src, frame.src);
+        assert_eq!(param.dest, mint::Point2 { x: 102.0, y: 51.0 });
+
+        // Scaled and rotated a quarter turn clockwise along with the frame.
+        let param = DrawParam::new()
+            .dest([100.0, 50.0])
+            .scale([2.0, 3.0])
+            .rotation(std::f32::consts::FRAC_PI_2);
+        let param = frame_param(&frame, param);
+        assert!((param.dest.x - 97.0).abs() < 1e-4);
+        assert!((param.dest.y - 54.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn headless_test_animation_long_pause() {
+        // Enough time for trillions of frames, which would take ages to
+        // step through one by one, and a whole number of both cycles.
+        let pause = Duration::from_millis(63 * 1_000_000_000_000);
+        let animation = frames(&[1, 2, 4]);
+        let mut playback = Playback::default();
+        playback.advance(&animation, Duration::from_millis(1));
+        playback.advance(&animation, pause + Duration::from_millis(3));
+        assert_eq!(playback.frame, 2);
+        assert_eq!(playback.elapsed, Duration::from_millis(1));
+
+        // There and back again takes 1 + 2 + 4 + 2 ms.
+        let animation = frames(&[1, 2, 4]).mode(PlayMode::PingPong);
+        let mut playback = Playback::default();
+        playback.advance(&animation, pause + Duration::from_millis(7));
+        assert_eq!(playback.frame, 1);
+        assert!(playback.backwards);
+        assert_eq!(playback.elapsed, Duration::from_millis(0));
+    }
+}
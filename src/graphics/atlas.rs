@@ -0,0 +1,359 @@
+//! A [`TextureAtlas`](struct.TextureAtlas.html) packs lots of small images
+//! into a few big ones at runtime, so they can all be drawn from the same
+//! [`SpriteBatch`](spritebatch/struct.SpriteBatch.html).
+
+use std::cmp;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::{Image, Rect};
+
+/// Packs many images into one or more large textures ("pages") at
+/// runtime, using the skyline bottom-left algorithm.
+///
+/// Every image added gets an [`AtlasRegion`](struct.AtlasRegion.html)
+/// telling which page it ended up on and where; its `src` can be fed
+/// straight to [`DrawParam::src()`](struct.DrawParam.html#method.src)
+/// when drawing that page.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// let mut atlas = TextureAtlas::new(1024, 1024)?.padding(2);
+/// let image = Image::new(ctx, "/player.png")?;
+/// let player = atlas.add_image(ctx, &image)?;
+/// let mut batch = spritebatch::SpriteBatch::new(atlas.page(player.page).unwrap().clone());
+/// batch.add(DrawParam::new().src(player.src));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Pages are never shrunk or repacked, so this works best when you add
+/// everything up front, biggest images first.
+#[derive(Debug)]
+pub struct TextureAtlas {
+    page_width: u16,
+    page_height: u16,
+    padding: u16,
+    pages: Vec<AtlasPage>,
+}
+
+/// Where an image ended up in a [`TextureAtlas`](struct.TextureAtlas.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtlasRegion {
+    /// Which page of the atlas the image is on.
+    pub page: usize,
+    /// The region of the page containing the image, in the
+    /// 0.0-1.0 coordinates that `DrawParam::src` uses.
+    pub src: Rect,
+    /// The region of the page containing the image, in pixels.
+    pub pixels: Rect,
+}
+
+#[derive(Debug)]
+struct AtlasPage {
+    image: Image,
+    skyline: Skyline,
+}
+
+impl TextureAtlas {
+    /// Creates a new, empty atlas whose pages are `width` by `height` pixels.
+    pub fn new(width: u16, height: u16) -> GameResult<Self> {
+        if width == 0 || height == 0 {
+            let msg = format!(
+                "Tried to create a texture atlas with pages of size {}x{}, each dimension must be >0",
+                width, height
+            );
+            return Err(GameError::RenderError(msg));
+        }
+        Ok(TextureAtlas {
+            page_width: width,
+            page_height: height,
+            padding: 0,
+            pages: vec![],
+        })
+    }
+
+    /// Leaves this many transparent pixels between images, so that
+    /// linear filtering doesn't bleed neighbouring images into each other.
+    pub fn padding(mut self, padding: u16) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Adds an image given as a buffer of `u8` RGBA values, laid out the
+    /// same way as for [`Image::from_rgba8()`](struct.Image.html#method.from_rgba8),
+    /// starting a new page if it doesn't fit on any of the existing ones.
+    pub fn add_rgba8(
+        &mut self,
+        ctx: &mut Context,
+        width: u16,
+        height: u16,
+        rgba: &[u8],
+    ) -> GameResult<AtlasRegion> {
+        let expected_bytes = width as usize * height as usize * 4;
+        if rgba.len() != expected_bytes {
+            let msg = format!(
+                "Tried to add a {}x{} image to a texture atlas, which should be {} bytes, but got {} bytes",
+                width,
+                height,
+                expected_bytes,
+                rgba.len()
+            );
+            return Err(GameError::RenderError(msg));
+        }
+        if width == 0 || height == 0 || width > self.page_width || height > self.page_height {
+            let msg = format!(
+                "Can't fit a {}x{} image into a texture atlas with pages of size {}x{}",
+                width, height, self.page_width, self.page_height
+            );
+            return Err(GameError::RenderError(msg));
+        }
+
+        let (width32, height32) = (u32::from(width), u32::from(height));
+        let padding = u32::from(self.padding);
+        let mut placed = None;
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            if let Some(pos) = page.skyline.insert(width32, height32, padding) {
+                placed = Some((i, pos));
+                break;
+            }
+        }
+        let (page, (x, y)) = match placed {
+            Some(placed) => placed,
+            None => {
+                let mut skyline =
+                    Skyline::new(u32::from(self.page_width), u32::from(self.page_height));
+                let pos = skyline
+                    .insert(width32, height32, padding)
+                    .expect("Image fits into an empty page; should never happen");
+                let blank = vec![0; self.page_width as usize * self.page_height as usize * 4];
+                let image = Image::from_rgba8(ctx, self.page_width, self.page_height, &blank)?;
+                self.pages.push(AtlasPage { image, skyline });
+                (self.pages.len() - 1, pos)
+            }
+        };
+
+        let (x, y) = (x as u16, y as u16);
+        let pixels = Rect::new(
+            f32::from(x),
+            f32::from(y),
+            f32::from(width),
+            f32::from(height),
+        );
+        self.pages[page].image.replace_region(ctx, pixels, rgba)?;
+        let src = Rect::fraction(
+            pixels.x,
+            pixels.y,
+            pixels.w,
+            pixels.h,
+            &self.pages[page].image.dimensions(),
+        );
+        Ok(AtlasRegion { page, src, pixels })
+    }
+
+    /// Adds a copy of the given `Image`, starting a new page if it
+    /// doesn't fit on any of the existing ones.
+    pub fn add_image(&mut self, ctx: &mut Context, image: &Image) -> GameResult<AtlasRegion> {
+        let rgba = image.to_rgba8(ctx)?;
+        self.add_rgba8(ctx, image.width(), image.height(), &rgba)
+    }
+
+    /// Returns the `Image` for the given page, if there is one.
+    pub fn page(&self, page: usize) -> Option<&Image> {
+        self.pages.get(page).map(|p| &p.image)
+    }
+
+    /// Returns the number of pages in use.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns the size of each page as (width, height).
+    pub fn page_size(&self) -> (u16, u16) {
+        (self.page_width, self.page_height)
+    }
+}
+
+/// One horizontal segment of the skyline: everything below `y`
+/// between `x` and `x + width` is taken.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct SkylineNode {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// A skyline bottom-left rectangle packer.
+///
+/// Not the tightest packing there is, but it's simple, fast and does
+/// well enough on the kind of same-ish sized sprites games are made of.
+#[derive(Debug)]
+struct Skyline {
+    width: u32,
+    height: u32,
+    nodes: Vec<SkylineNode>,
+}
+
+impl Skyline {
+    fn new(width: u32, height: u32) -> Self {
+        Skyline {
+            width,
+            height,
+            nodes: vec![SkylineNode { x: 0, y: 0, width }],
+        }
+    }
+
+    /// Finds a spot for a `width` by `height` rectangle and marks it
+    /// as taken, returning its top left corner.  `padding` more pixels
+    /// to the right of and below it are kept free as well, except past
+    /// the edges of the page, where nothing else can go anyway.
+    fn insert(&mut self, width: u32, height: u32, padding: u32) -> Option<(u32, u32)> {
+        // Put it wherever its bottom ends up lowest, and if there's
+        // a tie, on the narrowest segment so we waste less space.
+        let mut best: Option<(usize, u32)> = None;
+        for i in 0..self.nodes.len() {
+            if let Some(y) = self.fit(i, width, height, padding) {
+                let better = match best {
+                    Some((best_i, best_y)) => {
+                        y < best_y
+                            || (y == best_y && self.nodes[i].width < self.nodes[best_i].width)
+                    }
+                    None => true,
+                };
+                if better {
+                    best = Some((i, y));
+                }
+            }
+        }
+        let (i, y) = best?;
+        let x = self.nodes[i].x;
+        self.add_node(
+            i,
+            SkylineNode {
+                x,
+                y: cmp::min(y + height + padding, self.height),
+                width: cmp::min(width + padding, self.width - x),
+            },
+        );
+        Some((x, y))
+    }
+
+    /// Returns the y the rectangle would have to go at if its left
+    /// edge were at the start of node `i`, or `None` if it doesn't fit.
+    fn fit(&self, i: usize, width: u32, height: u32, padding: u32) -> Option<u32> {
+        let x = self.nodes[i].x;
+        if x + width > self.width {
+            return None;
+        }
+        let mut y = 0;
+        let mut width_left = cmp::min(width + padding, self.width - x);
+        for node in &self.nodes[i..] {
+            if width_left == 0 {
+                break;
+            }
+            y = cmp::max(y, node.y);
+            if y + height > self.height {
+                return None;
+            }
+            width_left = width_left.saturating_sub(node.width);
+        }
+        Some(y)
+    }
+
+    /// Inserts a new node at index `i`, cutting away whatever it
+    /// covers of the nodes after it.
+    fn add_node(&mut self, i: usize, node: SkylineNode) {
+        self.nodes.insert(i, node);
+        let right = node.x + node.width;
+        let j = i + 1;
+        while j < self.nodes.len() {
+            let next = &mut self.nodes[j];
+            if next.x >= right {
+                break;
+            }
+            let overlap = right - next.x;
+            if overlap >= next.width {
+                let _ = self.nodes.remove(j);
+            } else {
+                next.x += overlap;
+                next.width -= overlap;
+                break;
+            }
+        }
+        // Merge neighbours at the same height.
+        let mut k = 0;
+        while k + 1 < self.nodes.len() {
+            if self.nodes[k].y == self.nodes[k + 1].y {
+                self.nodes[k].width += self.nodes[k + 1].width;
+                let _ = self.nodes.remove(k + 1);
+            } else {
+                k += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Skyline;
+
+    #[test]
+    fn headless_test_skyline_packing() {
+        let mut skyline = Skyline::new(64, 64);
+        let sizes = [
+            (32, 32),
+            (16, 16),
+            (16, 8),
+            (8, 40),
+            (32, 16),
+            (16, 16),
+            (10, 10),
+            (6, 6),
+        ];
+        let mut placed = vec![];
+        for &(w, h) in &sizes {
+            let (x, y) = skyline.insert(w, h, 0).expect("Should fit");
+            assert!(x + w <= 64 && y + h <= 64);
+            for &(ox, oy, ow, oh) in &placed {
+                let overlaps = x < ox + ow && ox < x + w && y < oy + oh && oy < y + h;
+                assert!(
+                    !overlaps,
+                    "({},{} {}x{}) overlaps ({},{} {}x{})",
+                    x, y, w, h, ox, oy, ow, oh
+                );
+            }
+            placed.push((x, y, w, h));
+        }
+        // Something the size of the whole page doesn't fit any more...
+        assert_eq!(skyline.insert(64, 64, 0), None);
+        // ...and neither does something bigger than it.
+        assert_eq!(Skyline::new(64, 64).insert(65, 1, 0), None);
+        // But it does fit an empty page exactly.
+        assert_eq!(Skyline::new(64, 64).insert(64, 64, 0), Some((0, 0)));
+    }
+
+    #[test]
+    fn headless_test_skyline_padding() {
+        let mut skyline = Skyline::new(64, 32);
+        assert_eq!(skyline.insert(30, 10, 2), Some((0, 0)));
+        // Ends right at the edge of the page, so it needs no padding
+        // there, but still leaves some between it and the first one.
+        assert_eq!(skyline.insert(32, 10, 2), Some((32, 0)));
+        assert_eq!(skyline.insert(64, 20, 2), Some((0, 12)));
+        assert_eq!(skyline.insert(1, 1, 2), None);
+
+        // Padding keeps images apart even where the skyline is higher
+        // to the right.
+        let skyline = || {
+            let mut skyline = Skyline::new(64, 64);
+            assert_eq!(skyline.insert(20, 10, 0), Some((0, 0)));
+            assert_eq!(skyline.insert(44, 30, 0), Some((20, 0)));
+            skyline
+        };
+        assert_eq!(skyline().insert(18, 10, 2), Some((0, 10)));
+        assert_eq!(skyline().insert(19, 10, 2), Some((0, 30)));
+    }
+}
@@ -0,0 +1,174 @@
+//! Automatic batching of consecutive draws.
+//!
+//! Every `Image` or `Mesh` drawn with [`graphics::draw()`](../fn.draw.html)
+//! is queued up here instead of being sent to the graphics card right
+//! away.  As long as consecutive draws share the same vertices (the unit
+//! quad for images, a mesh's own buffers for meshes), texture, sampler,
+//! shader, blend mode, transform, render target, scissor rectangle and
+//! stencil state they get appended to the same batch, which is then drawn
+//! with a single instanced draw call once something incompatible comes
+//! along.  Anything else that touches the encoder (drawing a
+//! `SpriteBatch`, clearing the screen, switching canvases, presenting,
+//! ...) flushes the batch first, so the end result is the same as if
+//! everything had been drawn on its own.
+
+use gfx;
+use gfx::handle::{Buffer, RawDepthStencilView, RawRenderTargetView, RawShaderResourceView};
+use gfx::texture::SamplerInfo;
+use gfx::Factory;
+use gfx::{Rect, Slice};
+
+use crate::error::GameResult;
+use crate::graphics::{BackendSpec, BlendMode, InstanceProperties, ShaderId, StencilMode, Vertex};
+
+/// The vertex buffer and slice of a mesh.
+pub(crate) type MeshVertices<R> = (Buffer<R, Vertex>, Slice<R>);
+
+/// The render state a batch was started with.  Only draws with
+/// exactly the same state can be appended to it.
+pub(crate) struct BatchState<B>
+where
+    B: BackendSpec,
+{
+    /// The vertices every instance is drawn with, or `None` for the
+    /// unit quad that images are drawn with.
+    pub(crate) mesh: Option<MeshVertices<B::Resources>>,
+    pub(crate) texture: RawShaderResourceView<B::Resources>,
+    pub(crate) sampler_info: SamplerInfo,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) shader: ShaderId,
+    pub(crate) mvp_matrix: [[f32; 4]; 4],
+    pub(crate) target: RawRenderTargetView<B::Resources>,
+    pub(crate) depth: RawDepthStencilView<B::Resources>,
+    pub(crate) scissor: Rect,
+    pub(crate) stencil: (StencilMode, u8),
+}
+
+impl<B> PartialEq for BatchState<B>
+where
+    B: BackendSpec,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.mesh == other.mesh
+            && self.texture == other.texture
+            && self.sampler_info == other.sampler_info
+            && self.blend_mode == other.blend_mode
+            && self.shader == other.shader
+            && self.mvp_matrix == other.mvp_matrix
+            && self.target == other.target
+            && self.depth == other.depth
+            && self.scissor == other.scissor
+            && self.stencil == other.stencil
+    }
+}
+
+/// Queued up instances waiting to be drawn, plus the
+/// GPU buffer they get uploaded into when flushed.
+pub(crate) struct DrawBatch<B>
+where
+    B: BackendSpec,
+{
+    state: Option<BatchState<B>>,
+    instances: Vec<InstanceProperties>,
+    buffer: Buffer<B::Resources, InstanceProperties>,
+    capacity: usize,
+}
+
+impl<B> DrawBatch<B>
+where
+    B: BackendSpec,
+{
+    /// The number of instances the GPU buffer starts out with.
+    const INITIAL_CAPACITY: usize = 64;
+
+    /// Creates a new, empty batch.
+    pub(crate) fn new(factory: &mut B::Factory) -> GameResult<Self> {
+        let buffer = Self::create_buffer(factory, Self::INITIAL_CAPACITY)?;
+        Ok(Self {
+            state: None,
+            instances: Vec::with_capacity(Self::INITIAL_CAPACITY),
+            buffer,
+            capacity: Self::INITIAL_CAPACITY,
+        })
+    }
+
+    fn create_buffer(
+        factory: &mut B::Factory,
+        capacity: usize,
+    ) -> GameResult<Buffer<B::Resources, InstanceProperties>> {
+        let buffer = factory.create_buffer(
+            capacity,
+            gfx::buffer::Role::Vertex,
+            gfx::memory::Usage::Dynamic,
+            gfx::memory::Bind::TRANSFER_DST,
+        )?;
+        Ok(buffer)
+    }
+
+    /// Returns true if a draw with the given state can be appended to
+    /// the current batch without flushing it first.
+    pub(crate) fn accepts(&self, state: &BatchState<B>) -> bool {
+        match self.state {
+            Some(ref current) => current == state,
+            None => true,
+        }
+    }
+
+    /// Appends an instance to the batch.  The caller must make sure the
+    /// batch [`accepts()`](#method.accepts) the state beforehand.
+    pub(crate) fn push(&mut self, state: BatchState<B>, instance: InstanceProperties) {
+        debug_assert!(self.accepts(&state));
+        if self.state.is_none() {
+            self.state = Some(state);
+        }
+        self.instances.push(instance);
+    }
+
+    /// Returns true if there is nothing waiting to be drawn.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// Uploads the queued instances to the GPU and empties the batch,
+    /// returning the state it was started with and how many instances
+    /// are now in [`buffer()`](#method.buffer).
+    ///
+    /// The buffer grows by doubling whenever it is too small.
+    pub(crate) fn upload(
+        &mut self,
+        factory: &mut B::Factory,
+        encoder: &mut gfx::Encoder<B::Resources, B::CommandBuffer>,
+    ) -> GameResult<Option<(BatchState<B>, u32)>> {
+        let state = match self.state.take() {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        let count = self.instances.len();
+        // Whatever happens, the queued instances are gone afterwards;
+        // we don't want a failed flush to leak into the next batch.
+        let result = self.upload_instances(factory, encoder);
+        self.instances.clear();
+        result?;
+        Ok(Some((state, count as u32)))
+    }
+
+    fn upload_instances(
+        &mut self,
+        factory: &mut B::Factory,
+        encoder: &mut gfx::Encoder<B::Resources, B::CommandBuffer>,
+    ) -> GameResult {
+        let count = self.instances.len();
+        if count > self.capacity {
+            let capacity = count.next_power_of_two();
+            self.buffer = Self::create_buffer(factory, capacity)?;
+            self.capacity = capacity;
+        }
+        encoder.update_buffer(&self.buffer, &self.instances[..], 0)?;
+        Ok(())
+    }
+
+    /// The instance buffer the last [`upload()`](#method.upload) went into.
+    pub(crate) fn buffer(&self) -> &Buffer<B::Resources, InstanceProperties> {
+        &self.buffer
+    }
+}
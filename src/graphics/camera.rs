@@ -0,0 +1,375 @@
+//! A simple 2D camera.
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::types::{Matrix4, Rect};
+use crate::graphics::{self, na};
+
+/// The smallest zoom `Camera2D::set_zoom()` allows.
+const MIN_ZOOM: f32 = 0.0001;
+
+/// A 2D camera looking at a point in the game world, possibly zoomed in
+/// or out and rotated, which shows what it sees in a viewport on the
+/// window.
+///
+/// The camera can be applied by pushing it onto the transform stack with
+/// [`push()`](#method.push), which handles everything, or by setting the
+/// [`screen coordinates`](fn.set_screen_coordinates.html) with
+/// [`set_screen_coordinates()`](#method.set_screen_coordinates), which
+/// can't rotate.  Either way, [`world_to_screen()`](#method.world_to_screen)
+/// and [`screen_to_world()`](#method.screen_to_world) convert between
+/// world coordinates and window coordinates, the same ones
+/// [`mouse::position()`](../input/mouse/fn.position.html) uses:
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # use ggez::input::mouse;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// let (w, h) = graphics::drawable_size(ctx);
+/// let mut camera = Camera2D::new(Rect::new(0.0, 0.0, w, h));
+/// camera.set_position([1000.0, 500.0]);
+/// camera.set_zoom(2.0);
+///
+/// // In your `update()`:
+/// let clicked = camera.screen_to_world(ctx, mouse::position(ctx));
+///
+/// // In your `draw()`:
+/// camera.push(ctx)?;
+/// // ...draw the world...
+/// camera.pop(ctx)?;
+/// // ...draw the UI...
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The conversions work as if the camera was pushed on top of the
+/// transform stack as it currently is, using the current projection, so
+/// don't call them while the camera is pushed, or it will be applied twice.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera2D {
+    position: mint::Point2<f32>,
+    zoom: f32,
+    rotation: f32,
+    viewport: Rect,
+}
+
+impl Camera2D {
+    /// Creates a new camera that shows what it sees in the given part
+    /// of the window, in pixels.  It starts out looking at the center of
+    /// the viewport, so that with the default screen coordinates nothing
+    /// changes until it's moved.
+    pub fn new(viewport: Rect) -> Self {
+        Camera2D {
+            position: mint::Point2 {
+                x: viewport.x + viewport.w / 2.0,
+                y: viewport.y + viewport.h / 2.0,
+            },
+            zoom: 1.0,
+            rotation: 0.0,
+            viewport,
+        }
+    }
+
+    /// Returns the point in the world that is shown at the center of
+    /// the viewport.
+    pub fn position(&self) -> mint::Point2<f32> {
+        self.position
+    }
+
+    /// Makes the camera look at the given point in the world.
+    pub fn set_position<P>(&mut self, position: P)
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        self.position = position.into();
+    }
+
+    /// Moves the camera by the given amount, in world coordinates.
+    pub fn move_by<V>(&mut self, delta: V)
+    where
+        V: Into<mint::Vector2<f32>>,
+    {
+        let delta = delta.into();
+        self.position.x += delta.x;
+        self.position.y += delta.y;
+    }
+
+    /// Returns the camera's zoom.  At 2.0, everything looks twice as big.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets the camera's zoom.  Zooms smaller than `0.0001`, including
+    /// zero and negative ones, are raised to `0.0001`, since the camera
+    /// couldn't turn screen coordinates back into world coordinates
+    /// otherwise.  NaN and infinite zooms are ignored.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if zoom.is_finite() {
+            self.zoom = zoom.max(MIN_ZOOM);
+        }
+    }
+
+    /// Returns how far the camera is turned clockwise, in radians.
+    /// Turning the camera clockwise turns the world counterclockwise.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Sets how far the camera is turned clockwise, in radians.
+    pub fn set_rotation(&mut self, rotation: f32) {
+        self.rotation = rotation;
+    }
+
+    /// Returns the part of the window the camera shows its view in, in pixels.
+    pub fn viewport(&self) -> Rect {
+        self.viewport
+    }
+
+    /// Sets the part of the window the camera shows its view in, in
+    /// pixels.  You'll usually want to call this when the window is
+    /// resized.
+    ///
+    /// Note that nothing stops things from being drawn outside of the
+    /// viewport; it only decides where the camera's view ends up.
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+    }
+
+    /// Returns the part of the world that is visible in the viewport,
+    /// or if the camera is rotated, the smallest rectangle containing it.
+    pub fn visible_rect(&self) -> Rect {
+        let inverse = self
+            .view_matrix()
+            .try_inverse()
+            .expect("Camera view is not invertible; should never happen");
+        let v = self.viewport;
+        let corners = [
+            (v.x, v.y),
+            (v.x + v.w, v.y),
+            (v.x, v.y + v.h),
+            (v.x + v.w, v.y + v.h),
+        ];
+        let points: Vec<_> = corners
+            .iter()
+            .map(|&(x, y)| inverse.transform_point(&na::Point3::new(x, y, 0.0)))
+            .collect();
+        let (mut min, mut max) = (points[0], points[0]);
+        for p in &points[1..] {
+            min = na::Point3::new(min.x.min(p.x), min.y.min(p.y), 0.0);
+            max = na::Point3::new(max.x.max(p.x), max.y.max(p.y), 0.0);
+        }
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    /// Returns the matrix that takes points in the world to where they
+    /// should be drawn in the current screen coordinates.
+    pub fn to_matrix(&self, ctx: &Context) -> mint::ColumnMatrix4<f32> {
+        self.screen_matrix(graphics::screen_coordinates(ctx), window_size(ctx))
+            .into()
+    }
+
+    /// Applies the camera by pushing it on top of the transform stack,
+    /// so everything drawn until [`pop()`](#method.pop) is seen through it.
+    pub fn push(&self, ctx: &mut Context) -> GameResult {
+        let current = Matrix4::from(graphics::transform(ctx));
+        let camera = Matrix4::from(self.to_matrix(ctx));
+        graphics::push_transform(ctx, Some(current * camera));
+        graphics::apply_transformations(ctx)
+    }
+
+    /// Stops applying the camera by popping it off of the transform stack.
+    pub fn pop(&self, ctx: &mut Context) -> GameResult {
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)
+    }
+
+    /// Applies the camera by setting the screen coordinates, so that
+    /// the whole window shows the world at the same place and scale as
+    /// the viewport would.  This is an alternative to
+    /// [`push()`](#method.push) for when you're setting the screen
+    /// coordinates anyway; it can't rotate, so it returns an error if
+    /// the camera's rotation isn't zero.
+    pub fn set_screen_coordinates(&self, ctx: &mut Context) -> GameResult {
+        if self.rotation != 0.0 {
+            return Err(GameError::RenderError(
+                "Can't apply a rotated camera through the screen coordinates, use Camera2D::push() instead"
+                    .to_string(),
+            ));
+        }
+        let (w, h) = window_size(ctx);
+        let center = viewport_center(self.viewport);
+        let rect = Rect::new(
+            self.position.x - center.x / self.zoom,
+            self.position.y - center.y / self.zoom,
+            w / self.zoom,
+            h / self.zoom,
+        );
+        graphics::set_screen_coordinates(ctx, rect)
+    }
+
+    /// Converts a point in the world into window coordinates, in pixels.
+    pub fn world_to_screen<P>(&self, ctx: &Context, point: P) -> mint::Point2<f32>
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let transform = self.world_to_window(ctx);
+        let p = point.into();
+        let p = transform.transform_point(&na::Point3::new(p.x, p.y, 0.0));
+        mint::Point2 { x: p.x, y: p.y }
+    }
+
+    /// Converts a point in window coordinates, in pixels, such as the
+    /// mouse position, into world coordinates.
+    pub fn screen_to_world<P>(&self, ctx: &Context, point: P) -> mint::Point2<f32>
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        let p = point.into();
+        match self.world_to_window(ctx).try_inverse() {
+            Some(inverse) => {
+                let p = inverse.transform_point(&na::Point3::new(p.x, p.y, 0.0));
+                mint::Point2 { x: p.x, y: p.y }
+            }
+            // Only happens if someone squashed the projection or
+            // transform flat, in which case nothing is visible anyway.
+            None => self.position,
+        }
+    }
+
+    /// The whole chain from the world to window pixels: the camera, the
+    /// current transform and projection, then from the -1.0 to 1.0
+    /// normalized device coordinates that leaves us with to pixels.
+    fn world_to_window(&self, ctx: &Context) -> Matrix4 {
+        let (w, h) = window_size(ctx);
+        let projection = Matrix4::from(graphics::projection(ctx));
+        let modelview = Matrix4::from(graphics::transform(ctx));
+        let camera = self.screen_matrix(graphics::screen_coordinates(ctx), (w, h));
+        ndc_to_window(w, h) * projection * modelview * camera
+    }
+
+    /// Takes points in the world to window pixels, if the
+    /// screen coordinates are the default ones.
+    fn view_matrix(&self) -> Matrix4 {
+        let center = viewport_center(self.viewport);
+        Matrix4::new_translation(&na::Vector3::new(center.x, center.y, 0.0))
+            * Matrix4::new_rotation(na::Vector3::z() * -self.rotation)
+            * Matrix4::new_scaling(self.zoom)
+            * Matrix4::new_translation(&na::Vector3::new(-self.position.x, -self.position.y, 0.0))
+    }
+
+    /// Takes points in the world to the given screen coordinates.
+    fn screen_matrix(&self, screen: Rect, (w, h): (f32, f32)) -> Matrix4 {
+        if w <= 0.0 || h <= 0.0 {
+            return self.view_matrix();
+        }
+        let pixels_to_screen = Matrix4::new_translation(&na::Vector3::new(screen.x, screen.y, 0.0))
+            * Matrix4::new_nonuniform_scaling(&na::Vector3::new(screen.w / w, screen.h / h, 1.0));
+        pixels_to_screen * self.view_matrix()
+    }
+}
+
+fn viewport_center(viewport: Rect) -> mint::Point2<f32> {
+    mint::Point2 {
+        x: viewport.x + viewport.w / 2.0,
+        y: viewport.y + viewport.h / 2.0,
+    }
+}
+
+/// The size of the window in the same units as the mouse position,
+/// which is the size of the virtual screen if there is one.
+fn window_size(ctx: &Context) -> (f32, f32) {
+    match graphics::virtual_resolution(ctx) {
+        Some(resolution) => (f32::from(resolution.width), f32::from(resolution.height)),
+        None => graphics::drawable_size(ctx),
+    }
+}
+
+/// Takes normalized device coordinates, with y going up, to window
+/// pixels with y going down.
+fn ndc_to_window(w: f32, h: f32) -> Matrix4 {
+    Matrix4::new_translation(&na::Vector3::new(w / 2.0, h / 2.0, 0.0))
+        * Matrix4::new_nonuniform_scaling(&na::Vector3::new(w / 2.0, -h / 2.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use std::f32;
+
+    fn apply(m: &Matrix4, x: f32, y: f32) -> (f32, f32) {
+        let p = m.transform_point(&na::Point3::new(x, y, 0.0));
+        (p.x, p.y)
+    }
+
+    #[test]
+    fn headless_test_camera_view() {
+        let mut camera = Camera2D::new(Rect::new(0.0, 0.0, 800.0, 600.0));
+        // Starts out changing nothing.
+        let (x, y) = apply(&camera.view_matrix(), 123.0, 45.0);
+        assert_relative_eq!(x, 123.0, epsilon = 1e-3);
+        assert_relative_eq!(y, 45.0, epsilon = 1e-3);
+
+        camera.set_position([1000.0, 1000.0]);
+        camera.set_zoom(2.0);
+        let (x, y) = apply(&camera.view_matrix(), 1010.0, 990.0);
+        assert_relative_eq!(x, 420.0, epsilon = 1e-3);
+        assert_relative_eq!(y, 280.0, epsilon = 1e-3);
+        assert_eq!(camera.visible_rect(), Rect::new(800.0, 850.0, 400.0, 300.0));
+
+        // Turning the camera a quarter clockwise makes something to the
+        // right of it show up above it.
+        camera.set_rotation(std::f32::consts::FRAC_PI_2);
+        let (x, y) = apply(&camera.view_matrix(), 1010.0, 1000.0);
+        assert_relative_eq!(x, 400.0, epsilon = 1e-3);
+        assert_relative_eq!(y, 280.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn headless_test_camera_zoom_limits() {
+        let mut camera = Camera2D::new(Rect::new(0.0, 0.0, 800.0, 600.0));
+        camera.set_zoom(0.0);
+        assert_eq!(camera.zoom(), MIN_ZOOM);
+        camera.set_zoom(-3.0);
+        assert_eq!(camera.zoom(), MIN_ZOOM);
+        camera.set_zoom(3.0);
+        camera.set_zoom(f32::NAN);
+        camera.set_zoom(f32::INFINITY);
+        assert_eq!(camera.zoom(), 3.0);
+    }
+
+    #[test]
+    fn headless_test_camera_screen_round_trip() {
+        let mut camera = Camera2D::new(Rect::new(100.0, 0.0, 400.0, 300.0));
+        camera.set_position([-50.0, 20.0]);
+        camera.set_zoom(0.5);
+        camera.set_rotation(0.3);
+        // Screen coordinates with y going up and a different scale, like
+        // people set up for physics; the projection is what
+        // `set_screen_coordinates()` would make of them.
+        let (w, h) = (800.0, 600.0);
+        let screen = Rect::new(-10.0, 7.5, 20.0, -15.0);
+        let mut projection = Matrix4::identity();
+        projection[(0, 0)] = 2.0 / screen.w;
+        projection[(1, 1)] = -2.0 / screen.h;
+        projection[(0, 3)] = -(2.0 * screen.x + screen.w) / screen.w;
+        projection[(1, 3)] = (2.0 * screen.y + screen.h) / screen.h;
+        let modelview = Matrix4::identity();
+        let full =
+            ndc_to_window(w, h) * projection * modelview * camera.screen_matrix(screen, (w, h));
+        // The camera's position ends up in the middle of the viewport...
+        let (x, y) = apply(&full, -50.0, 20.0);
+        assert_relative_eq!(x, 300.0, epsilon = 1e-3);
+        assert_relative_eq!(y, 150.0, epsilon = 1e-3);
+        // ...the same place it would with the default screen coordinates.
+        let (dx, dy) = apply(&camera.view_matrix(), 13.0, -4.0);
+        let (sx, sy) = apply(&full, 13.0, -4.0);
+        assert_relative_eq!(dx, sx, epsilon = 1e-3);
+        assert_relative_eq!(dy, sy, epsilon = 1e-3);
+        let (x, y) = apply(&full.try_inverse().unwrap(), sx, sy);
+        assert_relative_eq!(x, 13.0, epsilon = 1e-3);
+        assert_relative_eq!(y, -4.0, epsilon = 1e-3);
+    }
+}
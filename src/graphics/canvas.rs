@@ -1,7 +1,7 @@
 //! I guess these docs will never appear since we re-export the canvas
 //! module from graphics...
-use gfx::format::Swizzle;
-use gfx::handle::RawRenderTargetView;
+use gfx::format::{ChannelType, Format, SurfaceType, Swizzle};
+use gfx::handle::{RawDepthStencilView, RawRenderTargetView};
 use gfx::memory::{Bind, Usage};
 use gfx::texture::{AaMode, Kind};
 use gfx::Factory;
@@ -9,6 +9,7 @@ use gfx::Factory;
 use crate::conf;
 use crate::context::DebugId;
 use crate::error::*;
+use crate::graphics::context::GraphicsContext;
 use crate::graphics::*;
 use crate::Context;
 
@@ -20,8 +21,10 @@ pub struct CanvasGeneric<Spec>
 where
     Spec: BackendSpec,
 {
-    target: RawRenderTargetView<Spec::Resources>,
+    pub(crate) target: RawRenderTargetView<Spec::Resources>,
+    pub(crate) depth: RawDepthStencilView<Spec::Resources>,
     image: Image,
+    format: CanvasFormat,
     debug_id: DebugId,
 }
 
@@ -37,27 +40,125 @@ where
 /// by using shaders that render to an image.
 /// If you just want to draw multiple things efficiently, look at
 /// [`SpriteBatch`](spritebatch/struct.Spritebatch.html).
+///
+/// Canvases with other pixel formats or without their own stencil
+/// buffer can be made with a [`CanvasBuilder`](struct.CanvasBuilder.html).
 pub type Canvas = CanvasGeneric<GlBackendSpec>;
 
-impl Canvas {
-    /// Create a new `Canvas` with the given size and number of samples.
-    pub fn new(
-        ctx: &mut Context,
-        width: u16,
-        height: u16,
-        samples: conf::NumSamples,
-    ) -> GameResult<Canvas> {
+/// The pixel format of a [`Canvas`](type.Canvas.html).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SmartDefault)]
+pub enum CanvasFormat {
+    /// The same format as the screen: 8 bits per channel RGBA, in
+    /// sRGB if [`WindowSetup::srgb`](../conf/struct.WindowSetup.html#structfield.srgb)
+    /// is on.
+    #[default]
+    Screen,
+    /// 8 bits per channel RGBA that is never converted to or from sRGB,
+    /// for things like normal maps or data that isn't a color.
+    Rgba8Linear,
+    /// 16-bit floating point RGBA.  Colors can go past 1.0 without being
+    /// clamped, which is what HDR effects like bloom need to work with.
+    Rgba16Float,
+    /// A single 8-bit channel, for masks or lightmaps.  Drawing to it
+    /// only keeps the red channel, and drawing it shows up in red.
+    R8,
+}
+
+impl CanvasFormat {
+    /// The gfx format this turns into, given what the screen uses.
+    pub(crate) fn to_gfx(self, screen: Format) -> Format {
+        match self {
+            CanvasFormat::Screen => screen,
+            CanvasFormat::Rgba8Linear => Format(SurfaceType::R8_G8_B8_A8, ChannelType::Unorm),
+            CanvasFormat::Rgba16Float => Format(SurfaceType::R16_G16_B16_A16, ChannelType::Float),
+            CanvasFormat::R8 => Format(SurfaceType::R8, ChannelType::Unorm),
+        }
+    }
+}
+
+/// Creates a [`Canvas`](type.Canvas.html) with more options than
+/// [`Canvas::new()`](type.Canvas.html#method.new) takes.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// let hdr = CanvasBuilder::new(800, 600)
+///     .format(CanvasFormat::Rgba16Float)
+///     .depth_stencil(false)
+///     .build(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CanvasBuilder {
+    width: u16,
+    height: u16,
+    samples: conf::NumSamples,
+    format: CanvasFormat,
+    depth_stencil: bool,
+}
+
+impl CanvasBuilder {
+    /// Starts building a canvas of the given size, with no anti-aliasing,
+    /// the same format as the screen and its own stencil buffer, same
+    /// as `Canvas::new()` makes.
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            samples: conf::NumSamples::One,
+            format: CanvasFormat::Screen,
+            depth_stencil: true,
+        }
+    }
+
+    /// Sets the number of samples used for anti-aliasing.
+    pub fn samples(mut self, samples: conf::NumSamples) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Sets the pixel format of the canvas.
+    pub fn format(mut self, format: CanvasFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets whether the canvas gets a depth-stencil buffer of its own.
+    ///
+    /// Drawing always needs one, so a canvas without its own shares a
+    /// buffer with every other such canvas of the same size and number of
+    /// samples.  That saves memory for canvases that never use
+    /// [`draw_stencil()`](fn.draw_stencil.html), but whatever is written
+    /// to the stencil buffer while drawing to one of them may get
+    /// overwritten by drawing to another.
+    pub fn depth_stencil(mut self, depth_stencil: bool) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    /// Creates the `Canvas`.
+    pub fn build(self, ctx: &mut Context) -> GameResult<Canvas> {
         let debug_id = DebugId::get(ctx);
+        let CanvasBuilder {
+            width,
+            height,
+            samples,
+            format,
+            depth_stencil,
+        } = self;
         let aa = match samples {
             conf::NumSamples::One => AaMode::Single,
             s => AaMode::Multi(s as u8),
         };
         let kind = Kind::D2(width, height, aa);
         let levels = 1;
-        let color_format = ctx.gfx_context.color_format();
+        let color_format = format.to_gfx(ctx.gfx_context.color_format());
         let factory = &mut ctx.gfx_context.factory;
         let texture_create_info = gfx::texture::Info {
-            kind,
+            kind: Kind::D2(width, height, AaMode::Single),
             levels,
             format: color_format.0,
             bind: Bind::SHADER_RESOURCE | Bind::RENDER_TARGET | Bind::TRANSFER_SRC,
@@ -77,9 +178,32 @@ impl Canvas {
             level: 0,
             layer: None,
         };
-        let target = factory.view_texture_as_render_target_raw(&tex, render_desc)?;
+        // Anti-aliased textures can't be drawn like any other image,
+        // so those get drawn to separately and resolved into `tex`.
+        let target = if aa == AaMode::Single {
+            factory.view_texture_as_render_target_raw(&tex, render_desc)?
+        } else {
+            let multisample_create_info = gfx::texture::Info {
+                kind,
+                levels,
+                format: color_format.0,
+                bind: Bind::RENDER_TARGET,
+                usage: Usage::Data,
+            };
+            let multisample_tex =
+                factory.create_texture_raw(multisample_create_info, Some(color_format.1), None)?;
+            factory.view_texture_as_render_target_raw(&multisample_tex, render_desc)?
+        };
+        // The screen's stencil buffer is the wrong size, so every canvas
+        // gets its own or a shared one that fits.
+        let depth = if depth_stencil {
+            ctx.gfx_context.create_depth_stencil(kind)?
+        } else {
+            ctx.gfx_context.shared_depth_stencil(kind)?
+        };
         Ok(Canvas {
             target,
+            depth,
             image: Image {
                 texture: resource,
                 texture_handle: tex,
@@ -89,9 +213,24 @@ impl Canvas {
                 height,
                 debug_id,
             },
+            format,
             debug_id,
         })
     }
+}
+
+impl Canvas {
+    /// Create a new `Canvas` with the given size and number of samples.
+    pub fn new(
+        ctx: &mut Context,
+        width: u16,
+        height: u16,
+        samples: conf::NumSamples,
+    ) -> GameResult<Canvas> {
+        CanvasBuilder::new(width, height)
+            .samples(samples)
+            .build(ctx)
+    }
 
     /// Create a new `Canvas` with the current window dimensions.
     pub fn with_window_size(ctx: &mut Context) -> GameResult<Canvas> {
@@ -102,10 +241,19 @@ impl Canvas {
     }
 
     /// Gets the backend `Image` that is being rendered to.
+    ///
+    /// If the canvas is anti-aliased, the image only shows what was drawn
+    /// to the canvas once drawing has switched to something else, such as
+    /// with [`set_canvas()`](fn.set_canvas.html) or [`present()`](fn.present.html).
     pub fn image(&self) -> &Image {
         &self.image
     }
 
+    /// Gets the pixel format of the canvas.
+    pub fn format(&self) -> CanvasFormat {
+        self.format
+    }
+
     /// Get the filter mode for the image.
     pub fn filter(&self) -> FilterMode {
         self.image.filter()
@@ -151,14 +299,72 @@ impl Drawable for Canvas {
 
 /// Set the `Canvas` to render to. Specifying `Option::None` will cause all
 /// rendering to be done directly to the screen.
+///
+/// Anything drawn so far still goes to the previous target.  If that was
+/// an anti-aliased canvas, it gets resolved so that its image shows
+/// everything drawn to it.
 pub fn set_canvas(ctx: &mut Context, target: Option<&Canvas>) {
+    if let Err(e) = ctx.gfx_context.flush_batch() {
+        warn!(
+            "Could not flush batched draws before switching canvas: {}",
+            e
+        );
+    }
+    if let Err(e) = resolve_canvas(&mut ctx.gfx_context) {
+        warn!("Could not resolve anti-aliased canvas: {}", e);
+    }
+    ctx.gfx_context.canvas_resolve = None;
     match target {
         Some(surface) => {
             surface.debug_id.assert(ctx);
-            ctx.gfx_context.data.out = surface.target.clone();
+            let gfx = &mut ctx.gfx_context;
+            gfx.set_render_target(surface.target.clone(), surface.depth.clone());
+            let (_, _, _, aa) = surface.target.get_dimensions();
+            if aa != AaMode::Single {
+                gfx.canvas_resolve = Some(surface.image.texture_handle.clone());
+            }
         }
         None => {
-            ctx.gfx_context.data.out = ctx.gfx_context.screen_render_target.clone();
+            ctx.gfx_context.use_default_render_target();
         }
     };
 }
+
+/// Resolves the anti-aliased canvas currently being drawn to, if any,
+/// into its image.
+pub(crate) fn resolve_canvas(gfx: &mut GraphicsContext) -> GameResult {
+    match gfx.canvas_resolve.clone() {
+        Some(texture) => {
+            let target = gfx.data.out.clone();
+            multisample::resolve(gfx, &target, &texture)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Whatever is currently being drawn to, screen or canvas, so that code
+/// which draws to canvases of its own can switch back to it afterwards.
+#[derive(Debug)]
+pub(crate) struct SavedTarget {
+    color: RawRenderTargetView<gfx_device_gl::Resources>,
+    depth: RawDepthStencilView<gfx_device_gl::Resources>,
+    resolve: Option<gfx::handle::RawTexture<gfx_device_gl::Resources>>,
+}
+
+/// Remembers the current render target for `restore_target()`.
+pub(crate) fn save_target(gfx: &GraphicsContext) -> SavedTarget {
+    SavedTarget {
+        color: gfx.data.out.clone(),
+        depth: gfx::memory::Typed::raw(&gfx.data.stencil.0).clone(),
+        resolve: gfx.canvas_resolve.clone(),
+    }
+}
+
+/// Switches back to a render target remembered by `save_target()`, the
+/// way `set_canvas()` would.
+pub(crate) fn restore_target(ctx: &mut Context, saved: SavedTarget) {
+    set_canvas(ctx, None);
+    let gfx = &mut ctx.gfx_context;
+    gfx.set_render_target(saved.color, saved.depth);
+    gfx.canvas_resolve = saved.resolve;
+}
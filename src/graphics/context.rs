@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::mem;
 use std::rc::Rc;
 
 use gfx::traits::FactoryExt;
@@ -7,7 +8,7 @@ use glutin;
 use glyph_brush::{GlyphBrush, GlyphBrushBuilder};
 use winit::{self, dpi};
 
-use crate::conf::{FullscreenType, WindowMode, WindowSetup};
+use crate::conf::{DpiMode, FullscreenType, WindowMode, WindowSetup};
 use crate::context::DebugId;
 use crate::filesystem::Filesystem;
 use crate::graphics::*;
@@ -26,8 +27,17 @@ where
     shader_globals: Globals,
     pub(crate) projection: Matrix4,
     pub(crate) modelview_stack: Vec<Matrix4>,
+    pub(crate) scissor: Option<Rect>,
+    pub(crate) scissor_stack: Vec<Option<Rect>>,
+    /// Whether anything was drawn since the encoder was last flushed,
+    /// which leaves the scissor test on with whatever rectangle that used.
+    pub(crate) scissor_applied: bool,
+    pub(crate) stencil: (StencilMode, u8),
+    pub(crate) stencil_test: Option<StencilTest>,
     pub(crate) white_image: ImageGeneric<B>,
     pub(crate) screen_rect: Rect,
+    pub(crate) hidpi_factor: f32,
+    pub(crate) dpi_mode: DpiMode,
     color_format: gfx::format::Format,
     depth_format: gfx::format::Format,
     srgb: bool,
@@ -39,17 +49,26 @@ where
     pub(crate) factory: Box<B::Factory>,
     pub(crate) encoder: gfx::Encoder<B::Resources, B::CommandBuffer>,
     pub(crate) screen_render_target: gfx::handle::RawRenderTargetView<B::Resources>,
-    #[allow(dead_code)]
     pub(crate) depth_view: gfx::handle::RawDepthStencilView<B::Resources>,
+    pub(crate) virtual_screen: Option<VirtualScreen<B>>,
+    /// If the canvas being drawn to is anti-aliased, the texture
+    /// of its image, which it gets resolved into.
+    pub(crate) canvas_resolve: Option<gfx::handle::RawTexture<B::Resources>>,
+    /// Depth-stencil buffers shared by canvases without one of their own,
+    /// one for each size and number of samples.
+    shared_depth_stencils: Vec<gfx::handle::RawDepthStencilView<B::Resources>>,
 
     pub(crate) data: pipe::Data<B::Resources>,
     pub(crate) quad_slice: gfx::Slice<B::Resources>,
     pub(crate) quad_vertex_buffer: gfx::handle::Buffer<B::Resources, Vertex>,
+    batch: batch::DrawBatch<B>,
 
     pub(crate) default_sampler_info: texture::SamplerInfo,
     pub(crate) samplers: SamplerCache<B>,
 
     default_shader: ShaderId,
+    /// Used by `draw_stencil()` instead of the default shader.
+    pub(crate) stencil_shader: ShaderId,
     pub(crate) current_shader: Rc<RefCell<Option<ShaderId>>>,
     pub(crate) shaders: Vec<Box<dyn ShaderHandle<B>>>,
 
@@ -131,9 +150,9 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             depth_format,
         )?;
 
-        // see winit #548 about DPI.
-        // We basically ignore it and if it's wrong, that's a winit bug
-        // since we have no good control over it.
+        // From here on the hidpi factor is kept up to date by
+        // `Context::process_event()`; see winit #548 about DPI.
+        let hidpi_factor = window.get_hidpi_factor();
         {
             // Log a bunch of OpenGL state info pulled out of winit and gfx
             let dpi::LogicalSize {
@@ -148,7 +167,6 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             } = window
                 .get_inner_size()
                 .ok_or_else(|| GameError::VideoError("Window doesn't exist!".to_owned()))?;
-            let hidpi_factor = window.get_hidpi_factor();
             debug!(
                 "Window created, desired size {}x{}, hidpi factor {}.",
                 window_mode.width, window_mode.height, hidpi_factor
@@ -196,6 +214,18 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             color_format,
             debug_id,
         )?;
+        let (_, stencil_draw) = create_shader(
+            vs_text,
+            backend.stencil_shader(),
+            EmptyConst,
+            "Empty",
+            &mut encoder,
+            &mut factory,
+            multisample_samples,
+            Some(&blend_modes[..]),
+            color_format,
+            debug_id,
+        )?;
 
         let rect_inst_props = factory.create_buffer(
             1,
@@ -209,6 +239,8 @@ impl GraphicsContextGeneric<GlBackendSpec> {
 
         quad_slice.instances = Some((1, 0));
 
+        let batch = batch::DrawBatch::new(&mut factory)?;
+
         let globals_buffer = factory.create_constant_buffer(1);
         let mut samplers: SamplerCache<GlBackendSpec> = SamplerCache::new();
         let sampler_info =
@@ -226,12 +258,20 @@ impl GraphicsContextGeneric<GlBackendSpec> {
         let texture = white_image.texture.clone();
         let typed_thingy = backend.raw_to_typed_shader_resource(texture);
 
+        let (target_width, target_height, _, _) = screen_render_target.get_dimensions();
         let data = pipe::Data {
             vbuf: quad_vertex_buffer.clone(),
             tex: (typed_thingy, sampler),
             rect_instance_properties: rect_inst_props,
             globals: globals_buffer,
+            scissor: gfx::Rect {
+                x: 0,
+                y: 0,
+                w: target_width,
+                h: target_height,
+            },
             out: screen_render_target.clone(),
+            stencil: (gfx::memory::Typed::new(depth_view.clone()), (0, 0)),
         };
 
         // Glyph cache stuff.
@@ -268,8 +308,15 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             shader_globals: globals,
             projection: initial_projection,
             modelview_stack: vec![initial_transform],
+            scissor: None,
+            scissor_stack: Vec::new(),
+            scissor_applied: false,
+            stencil: (StencilMode::Off, 0),
+            stencil_test: None,
             white_image,
             screen_rect: Rect::new(left, top, right - left, bottom - top),
+            hidpi_factor: hidpi_factor as f32,
+            dpi_mode: window_mode.dpi_mode,
             color_format,
             depth_format,
             srgb,
@@ -282,17 +329,22 @@ impl GraphicsContextGeneric<GlBackendSpec> {
             encoder,
             screen_render_target,
             depth_view,
+            virtual_screen: None,
+            canvas_resolve: None,
+            shared_depth_stencils: Vec::new(),
 
             data,
             quad_slice,
             quad_vertex_buffer,
+            batch,
 
             default_sampler_info: sampler_info,
             samplers,
 
             default_shader: shader.shader_id(),
+            stencil_shader: 1,
             current_shader: Rc::new(RefCell::new(None)),
-            shaders: vec![draw],
+            shaders: vec![draw, stencil_draw],
 
             glyph_brush,
             glyph_cache,
@@ -401,31 +453,190 @@ where
         *last
     }
 
-    /// Converts the given `DrawParam` into an `InstanceProperties` object and
-    /// sends it to the graphics card at the front of the instance buffer.
-    pub(crate) fn update_instance_properties(&mut self, draw_params: DrawTransform) -> GameResult {
-        let mut new_draw_params = draw_params;
-        new_draw_params.color = draw_params.color;
-        let properties = new_draw_params.to_instance_properties(self.srgb);
-        self.encoder
-            .update_buffer(&self.data.rect_instance_properties, &[properties], 0)?;
+    /// Queues up a textured quad to be drawn with the current shader,
+    /// transform and render target.  It gets appended to the pending
+    /// batch if that uses the same state, otherwise the batch is flushed
+    /// and a new one started.
+    pub(crate) fn queue_quad(
+        &mut self,
+        texture: gfx::handle::RawShaderResourceView<B::Resources>,
+        sampler_info: texture::SamplerInfo,
+        blend_mode: Option<BlendMode>,
+        draw_params: DrawTransform,
+    ) -> GameResult {
+        self.queue_instance(None, texture, sampler_info, blend_mode, draw_params)
+    }
+
+    /// Queues up a mesh to be drawn the same way as `queue_quad()`,
+    /// batched with draws of the same mesh.
+    pub(crate) fn queue_mesh(
+        &mut self,
+        vertices: gfx::handle::Buffer<B::Resources, Vertex>,
+        slice: gfx::Slice<B::Resources>,
+        texture: gfx::handle::RawShaderResourceView<B::Resources>,
+        sampler_info: texture::SamplerInfo,
+        draw_params: DrawTransform,
+    ) -> GameResult {
+        self.queue_instance(
+            Some((vertices, slice)),
+            texture,
+            sampler_info,
+            None,
+            draw_params,
+        )
+    }
+
+    fn queue_instance(
+        &mut self,
+        mesh: Option<batch::MeshVertices<B::Resources>>,
+        texture: gfx::handle::RawShaderResourceView<B::Resources>,
+        sampler_info: texture::SamplerInfo,
+        blend_mode: Option<BlendMode>,
+        draw_params: DrawTransform,
+    ) -> GameResult {
+        let state = batch::BatchState {
+            mesh,
+            texture,
+            sampler_info,
+            blend_mode: blend_mode.unwrap_or_else(|| self.blend_mode()),
+            shader: self.current_shader_id(),
+            mvp_matrix: self.shader_globals.mvp_matrix,
+            target: self.data.out.clone(),
+            depth: gfx::memory::Typed::raw(&self.data.stencil.0).clone(),
+            scissor: self.scissor_rect(),
+            stencil: self.stencil,
+        };
+        if !self.batch.accepts(&state) {
+            self.flush_batch()?;
+        }
+        let properties = draw_params.to_instance_properties(self.srgb);
+        self.batch.push(state, properties);
         Ok(())
     }
 
+    /// Draws everything queued up by `queue_quad()` with a single
+    /// instanced draw call.
+    ///
+    /// This temporarily swaps out whatever pipeline data, globals and
+    /// blend mode are currently set and puts them back afterwards, so it
+    /// is safe to call in the middle of setting up some other draw.
+    pub(crate) fn flush_batch(&mut self) -> GameResult {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let (state, count) = match self.batch.upload(&mut self.factory, &mut self.encoder)? {
+            Some(uploaded) => uploaded,
+            None => return Ok(()),
+        };
+
+        let sampler = self
+            .samplers
+            .get_or_insert(state.sampler_info, &mut self.factory);
+        let typed_thingy = self
+            .backend_spec
+            .raw_to_typed_shader_resource(state.texture);
+        let (vertices, mut slice) = match state.mesh {
+            Some(mesh) => mesh,
+            None => (self.quad_vertex_buffer.clone(), self.quad_slice.clone()),
+        };
+        let vbuf = mem::replace(&mut self.data.vbuf, vertices);
+        let tex = mem::replace(&mut self.data.tex, (typed_thingy, sampler));
+        let instances = mem::replace(
+            &mut self.data.rect_instance_properties,
+            self.batch.buffer().clone(),
+        );
+        let out = mem::replace(&mut self.data.out, state.target);
+        let depth = mem::replace(
+            &mut self.data.stencil.0,
+            gfx::memory::Typed::new(state.depth),
+        );
+        let scissor = mem::replace(&mut self.data.scissor, state.scissor);
+
+        slice.instances = Some((count, 0));
+        let result = self.draw_batch(
+            &slice,
+            state.shader,
+            state.blend_mode,
+            state.stencil,
+            state.mvp_matrix,
+        );
+
+        self.data.vbuf = vbuf;
+        self.data.tex = tex;
+        self.data.rect_instance_properties = instances;
+        self.data.out = out;
+        self.data.stencil.0 = depth;
+        self.data.scissor = scissor;
+        result
+    }
+
+    /// Does the actual drawing for `flush_batch()`.
+    fn draw_batch(
+        &mut self,
+        slice: &gfx::Slice<B::Resources>,
+        shader: ShaderId,
+        blend_mode: BlendMode,
+        (stencil_mode, stencil_ref): (StencilMode, u8),
+        mvp_matrix: [[f32; 4]; 4],
+    ) -> GameResult {
+        let globals_changed = mvp_matrix != self.shader_globals.mvp_matrix;
+        if globals_changed {
+            self.encoder
+                .update_buffer(&self.data.globals, &[Globals { mvp_matrix }], 0)?;
+        }
+        self.scissor_applied = true;
+        self.data.stencil.1 = (stencil_ref, stencil_ref);
+        let shader_handle = &mut self.shaders[shader];
+        shader_handle.set_stencil_mode(stencil_mode)?;
+        let previous_mode = shader_handle.blend_mode();
+        if previous_mode != blend_mode {
+            shader_handle.set_blend_mode(blend_mode)?;
+        }
+        let result = shader_handle.draw(&mut self.encoder, slice, &self.data);
+        if previous_mode != blend_mode {
+            shader_handle.set_blend_mode(previous_mode)?;
+        }
+        if globals_changed {
+            self.update_globals()?;
+        }
+        result
+    }
+
     /// Draws with the current encoder, slice, and pixel shader. Prefer calling
     /// this method from `Drawables` so that the pixel shader gets used
+    ///
+    /// Flushes any batched draws first so everything ends up on the
+    /// screen in the order it was drawn.
     pub(crate) fn draw(&mut self, slice: Option<&gfx::Slice<B::Resources>>) -> GameResult {
+        self.flush_batch()?;
+        self.data.scissor = self.scissor_rect();
+        self.scissor_applied = true;
+        let (stencil_mode, stencil_ref) = self.stencil;
+        self.data.stencil.1 = (stencil_ref, stencil_ref);
         let slice = slice.unwrap_or(&self.quad_slice);
         let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
-        let shader_handle = &self.shaders[id];
+        let shader_handle = &mut self.shaders[id];
 
+        shader_handle.set_stencil_mode(stencil_mode)?;
         shader_handle.draw(&mut self.encoder, slice, &self.data)?;
         Ok(())
     }
 
+    /// Returns the scissor rectangle in pixels of the current render
+    /// target, which covers all of it if there is no rectangle set.
+    pub(crate) fn scissor_rect(&self) -> gfx::Rect {
+        let (w, h, _, _) = self.data.out.get_dimensions();
+        scissor_pixels(self.scissor, self.projection, (w, h))
+    }
+
+    /// Returns the id of the shader that draws are currently done with.
+    pub(crate) fn current_shader_id(&self) -> ShaderId {
+        (*self.current_shader.borrow()).unwrap_or(self.default_shader)
+    }
+
     /// Sets the blend mode of the active shader
     pub(crate) fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult {
-        let id = (*self.current_shader.borrow()).unwrap_or(self.default_shader);
+        let id = self.current_shader_id();
         let shader_handle = &mut self.shaders[id];
         shader_handle.set_blend_mode(mode)
     }
@@ -509,6 +720,8 @@ where
 
     /// Sets window mode from a WindowMode object.
     pub(crate) fn set_window_mode(&mut self, mode: WindowMode) -> GameResult {
+        self.dpi_mode = mode.dpi_mode;
+        let scale = f64::from(self.logical_scale());
         let window = &self.window;
 
         window.set_maximized(mode.maximized);
@@ -516,8 +729,8 @@ where
         // TODO LATER: find out if single-dimension constraints are possible?
         let min_dimensions = if mode.min_width > 0.0 && mode.min_height > 0.0 {
             Some(dpi::LogicalSize {
-                width: f64::from(mode.min_width),
-                height: f64::from(mode.min_height),
+                width: f64::from(mode.min_width) / scale,
+                height: f64::from(mode.min_height) / scale,
             })
         } else {
             None
@@ -526,8 +739,8 @@ where
 
         let max_dimensions = if mode.max_width > 0.0 && mode.max_height > 0.0 {
             Some(dpi::LogicalSize {
-                width: f64::from(mode.max_width),
-                height: f64::from(mode.max_height),
+                width: f64::from(mode.max_width) / scale,
+                height: f64::from(mode.max_height) / scale,
             })
         } else {
             None
@@ -540,22 +753,22 @@ where
                 window.set_fullscreen(None);
                 window.set_decorations(!mode.borderless);
                 window.set_inner_size(dpi::LogicalSize {
-                    width: f64::from(mode.width),
-                    height: f64::from(mode.height),
+                    width: f64::from(mode.width) / scale,
+                    height: f64::from(mode.height) / scale,
                 });
                 window.set_resizable(mode.resizable);
             }
             FullscreenType::True => {
                 window.set_fullscreen(Some(monitor));
                 window.set_inner_size(dpi::LogicalSize {
-                    width: f64::from(mode.width),
-                    height: f64::from(mode.height),
+                    width: f64::from(mode.width) / scale,
+                    height: f64::from(mode.height) / scale,
                 });
             }
             FullscreenType::Desktop => {
                 let position = monitor.get_position();
                 let dimensions = monitor.get_dimensions();
-                let hidpi_factor = window.get_hidpi_factor();
+                let hidpi_factor = f64::from(self.hidpi_factor);
                 window.set_fullscreen(None);
                 window.set_decorations(false);
                 window.set_inner_size(dimensions.to_logical(hidpi_factor));
@@ -578,8 +791,86 @@ where
             self.depth_format(),
             &self.window,
         ) {
+            let on_screen = self.data.out == self.screen_render_target;
             self.screen_render_target = cv;
             self.depth_view = dv;
+            if on_screen {
+                self.set_render_target(self.screen_render_target.clone(), self.depth_view.clone());
+            }
+        }
+    }
+
+    /// Returns what gets drawn to when there is no canvas set, along
+    /// with its depth-stencil buffer: the virtual screen if there is
+    /// one, otherwise the actual screen.
+    pub(crate) fn default_render_target(
+        &self,
+    ) -> (
+        gfx::handle::RawRenderTargetView<B::Resources>,
+        gfx::handle::RawDepthStencilView<B::Resources>,
+    ) {
+        match self.virtual_screen {
+            Some(ref screen) => (screen.canvas.target.clone(), screen.canvas.depth.clone()),
+            None => (self.screen_render_target.clone(), self.depth_view.clone()),
+        }
+    }
+
+    /// Makes everything get drawn to the given render target, using
+    /// the given depth-stencil buffer, which has to be the same size.
+    pub(crate) fn set_render_target(
+        &mut self,
+        color: gfx::handle::RawRenderTargetView<B::Resources>,
+        depth: gfx::handle::RawDepthStencilView<B::Resources>,
+    ) {
+        self.data.out = color;
+        self.data.stencil.0 = gfx::memory::Typed::new(depth);
+    }
+
+    /// Switches back to drawing to `default_render_target()`.
+    pub(crate) fn use_default_render_target(&mut self) {
+        let (color, depth) = self.default_render_target();
+        self.set_render_target(color, depth);
+    }
+
+    /// Gets ready to clear the current render target: flushes batched
+    /// draws, and the encoder too if needed, since clears get clipped
+    /// by the scissor test as well, which the last draw leaves switched
+    /// on until the encoder is flushed.
+    pub(crate) fn prepare_clear(&mut self) {
+        if let Err(e) = self.flush_batch() {
+            warn!("Could not flush batched draws before clearing: {}", e);
+        }
+        if self.scissor_applied {
+            self.encoder.flush(&mut *self.device);
+            self.scissor_applied = false;
+        }
+    }
+
+    /// Returns how many of the pixels the game uses make up one
+    /// logical pixel: 1 with `DpiMode::Logical`, or the hidpi factor
+    /// with `DpiMode::Physical`.
+    pub(crate) fn logical_scale(&self) -> f32 {
+        match self.dpi_mode {
+            DpiMode::Logical => 1.0,
+            DpiMode::Physical => self.hidpi_factor,
+        }
+    }
+
+    /// Converts a position in the window, in logical pixels, into the
+    /// pixels the game uses, which are virtual screen pixels if a
+    /// virtual resolution is set.
+    pub(crate) fn window_to_screen(&self, point: Point2) -> Point2 {
+        match self.virtual_screen {
+            Some(ref screen) => screen.window_to_virtual(&self.window, self.hidpi_factor, point),
+            None => point * self.logical_scale(),
+        }
+    }
+
+    /// The inverse of `window_to_screen()`.
+    pub(crate) fn screen_to_window(&self, point: Point2) -> Point2 {
+        match self.virtual_screen {
+            Some(ref screen) => screen.virtual_to_window(&self.window, self.hidpi_factor, point),
+            None => point / self.logical_scale(),
         }
     }
 
@@ -594,6 +885,51 @@ where
         self.depth_format
     }
 
+    /// Creates a new depth-stencil buffer for a render target of the
+    /// given kind.
+    pub(crate) fn create_depth_stencil(
+        &mut self,
+        kind: texture::Kind,
+    ) -> GameResult<gfx::handle::RawDepthStencilView<B::Resources>> {
+        let info = texture::Info {
+            kind,
+            levels: 1,
+            format: self.depth_format.0,
+            bind: gfx::memory::Bind::DEPTH_STENCIL,
+            usage: gfx::memory::Usage::Data,
+        };
+        let depth_texture =
+            self.factory
+                .create_texture_raw(info, Some(self.depth_format.1), None)?;
+        let desc = texture::DepthStencilDesc {
+            level: 0,
+            layer: None,
+            flags: texture::DepthStencilFlags::empty(),
+        };
+        let depth = self
+            .factory
+            .view_texture_as_depth_stencil_raw(&depth_texture, desc)?;
+        Ok(depth)
+    }
+
+    /// Returns the depth-stencil buffer shared by all render targets of
+    /// the given kind, creating it the first time one is needed.
+    pub(crate) fn shared_depth_stencil(
+        &mut self,
+        kind: texture::Kind,
+    ) -> GameResult<gfx::handle::RawDepthStencilView<B::Resources>> {
+        let existing = self
+            .shared_depth_stencils
+            .iter()
+            .find(|depth| depth.get_dimensions() == kind.get_dimensions());
+        if let Some(depth) = existing {
+            return Ok(depth.clone());
+        }
+        let depth = self.create_depth_stencil(kind)?;
+        self.shared_depth_stencils.push(depth.clone());
+        Ok(depth)
+    }
+
     /// Simple shortcut to check whether the context's color
     /// format is SRGB or not.
     pub(crate) fn is_srgb(&self) -> bool {
@@ -604,3 +940,116 @@ where
         }
     }
 }
+
+/// Converts a scissor rectangle in screen coordinates into pixels of a
+/// render target of the given size, with the origin at the top left
+/// like gfx wants it.
+fn scissor_pixels(rect: Option<Rect>, projection: Matrix4, (w, h): (u16, u16)) -> gfx::Rect {
+    let rect = match rect {
+        Some(rect) => rect,
+        None => return gfx::Rect { x: 0, y: 0, w, h },
+    };
+    let to_pixels = |x: f32, y: f32| {
+        let ndc = projection * na::Vector4::new(x, y, 0.0, 1.0);
+        (
+            (ndc.x + 1.0) / 2.0 * f32::from(w),
+            (1.0 - ndc.y) / 2.0 * f32::from(h),
+        )
+    };
+    let clamp = |value: f32, max: u16| value.round().max(0.0).min(f32::from(max)) as u16;
+    let (x1, y1) = to_pixels(rect.x, rect.y);
+    let (x2, y2) = to_pixels(rect.right(), rect.bottom());
+    let (left, right) = (clamp(x1.min(x2), w), clamp(x1.max(x2), w));
+    let (top, bottom) = (clamp(y1.min(y2), h), clamp(y1.max(y2), h));
+    gfx::Rect {
+        x: left,
+        y: top,
+        w: right - left,
+        h: bottom - top,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_test_scissor_pixels() {
+        let projection_for = |screen: Rect| {
+            // Same as `set_projection_rect()` would come up with.
+            Matrix4::new_orthographic(
+                screen.x,
+                screen.right(),
+                screen.bottom(),
+                screen.y,
+                -1.0,
+                1.0,
+            )
+        };
+        let projection = projection_for(Rect::new(0.0, 0.0, 800.0, 600.0));
+        let full = gfx::Rect {
+            x: 0,
+            y: 0,
+            w: 800,
+            h: 600,
+        };
+        assert_eq!(scissor_pixels(None, projection, (800, 600)), full);
+        assert_eq!(
+            scissor_pixels(
+                Some(Rect::new(10.0, 20.0, 100.0, 50.0)),
+                projection,
+                (800, 600)
+            ),
+            gfx::Rect {
+                x: 10,
+                y: 20,
+                w: 100,
+                h: 50,
+            }
+        );
+        // Partly off screen.
+        assert_eq!(
+            scissor_pixels(
+                Some(Rect::new(-10.0, 580.0, 100.0, 50.0)),
+                projection,
+                (800, 600)
+            ),
+            gfx::Rect {
+                x: 0,
+                y: 580,
+                w: 90,
+                h: 20,
+            }
+        );
+        // Screen coordinates at half the resolution of the target,
+        // such as on a high DPI display.
+        assert_eq!(
+            scissor_pixels(
+                Some(Rect::new(10.0, 20.0, 100.0, 50.0)),
+                projection,
+                (1600, 1200)
+            ),
+            gfx::Rect {
+                x: 20,
+                y: 40,
+                w: 200,
+                h: 100,
+            }
+        );
+        // Y going up.
+        let projection = projection_for(Rect::new(0.0, 600.0, 800.0, -600.0));
+        assert_eq!(
+            scissor_pixels(
+                Some(Rect::new(10.0, 20.0, 100.0, 50.0)),
+                projection,
+                (800, 600)
+            ),
+            gfx::Rect {
+                x: 10,
+                y: 530,
+                w: 100,
+                h: 50,
+            }
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! A [`DrawQueue`](struct.DrawQueue.html) collects draws and does
+//! them sorted by layer instead of in the order they were made.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::{DrawParam, Drawable};
+
+/// Collects things to draw over the course of a frame, then draws them
+/// all at once, layer by layer from the lowest layer number to the
+/// highest.  Within a layer things are drawn in the order they were
+/// added, or from top to bottom by their `DrawParam::dest` if the layer
+/// is [y-sorted](#method.set_y_sorted), which is what top-down games
+/// want for their characters and scenery to overlap properly.
+///
+/// Nothing is drawn until [`draw()`](#method.draw) is called, so the
+/// queue borrows everything added to it until then.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// # let (ground, player, tree, hud) = (Image::solid(ctx, 8, WHITE)?, Image::solid(ctx, 8, WHITE)?,
+/// #     Image::solid(ctx, 8, WHITE)?, Image::solid(ctx, 8, WHITE)?);
+/// const GROUND: i32 = 0;
+/// const ENTITIES: i32 = 1;
+/// const HUD: i32 = 2;
+///
+/// let mut queue = DrawQueue::new();
+/// queue.set_y_sorted(ENTITIES, true);
+/// queue.add(HUD, &hud, DrawParam::default());
+/// queue.add(ENTITIES, &tree, DrawParam::new().dest([100.0, 120.0]));
+/// queue.add(ENTITIES, &player, DrawParam::new().dest([110.0, 100.0]));
+/// queue.add(GROUND, &ground, DrawParam::default());
+/// // Draws the ground, then the player, then the tree in front
+/// // of the player, then the HUD on top of everything.
+/// queue.draw(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct DrawQueue<'a> {
+    draws: Vec<QueuedDraw<'a>>,
+    y_sorted: Vec<i32>,
+}
+
+/// A single draw waiting in a `DrawQueue`.
+struct QueuedDraw<'a> {
+    layer: i32,
+    drawable: &'a dyn Drawable,
+    param: DrawParam,
+}
+
+impl<'a> DrawQueue<'a> {
+    /// Creates a new, empty `DrawQueue` with no y-sorted layers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the given layer is drawn sorted by the y coordinate
+    /// of each `DrawParam::dest`, so that things further down the screen
+    /// are drawn in front of things further up, rather than in the order
+    /// they were added.  Things with the same y coordinate are still
+    /// drawn in the order they were added, and things with a NaN y
+    /// coordinate are drawn last.
+    pub fn set_y_sorted(&mut self, layer: i32, y_sorted: bool) {
+        let position = self.y_sorted.iter().position(|l| *l == layer);
+        match (position, y_sorted) {
+            (None, true) => self.y_sorted.push(layer),
+            (Some(i), false) => {
+                let _ = self.y_sorted.swap_remove(i);
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns whether the given layer is drawn sorted by y coordinate.
+    pub fn is_y_sorted(&self, layer: i32) -> bool {
+        self.y_sorted.contains(&layer)
+    }
+
+    /// Adds something to draw on the given layer.
+    pub fn add<D, P>(&mut self, layer: i32, drawable: &'a D, param: P)
+    where
+        D: Drawable,
+        P: Into<DrawParam>,
+    {
+        self.draws.push(QueuedDraw {
+            layer,
+            drawable,
+            param: param.into(),
+        });
+    }
+
+    /// Returns the number of draws in the queue.
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// Returns true if there is nothing in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Removes everything from the queue without drawing it.
+    pub fn clear(&mut self) {
+        self.draws.clear();
+    }
+
+    /// Draws everything in the queue in layer order, and empties it.
+    /// The y-sorted layers are kept.
+    ///
+    /// If drawing something fails, the rest of the queue is thrown away.
+    pub fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.sort();
+        let result = self
+            .draws
+            .iter()
+            .try_for_each(|draw| draw.drawable.draw(ctx, draw.param));
+        self.draws.clear();
+        result
+    }
+
+    /// Puts the queued draws into the order they get drawn in.
+    /// The sort is stable, so anything that compares equal keeps
+    /// the order it was added in.
+    fn sort(&mut self) {
+        let y_sorted = &self.y_sorted;
+        self.draws.sort_by(|a, b| {
+            a.layer.cmp(&b.layer).then_with(|| {
+                if y_sorted.contains(&a.layer) {
+                    compare_y(a.param.dest.y, b.param.dest.y)
+                } else {
+                    Ordering::Equal
+                }
+            })
+        });
+    }
+}
+
+/// Compares y coordinates with NaN after everything else, since sorting
+/// needs a consistent order and `partial_cmp()` doesn't give one.
+fn compare_y(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+impl<'a> fmt::Debug for DrawQueue<'a> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "<DrawQueue: {} draws, y-sorted layers {:?}>",
+            self.draws.len(),
+            self.y_sorted
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::{BlendMode, Rect};
+    use std::f32;
+
+    struct Nothing;
+
+    impl Drawable for Nothing {
+        fn draw(&self, _ctx: &mut Context, _param: DrawParam) -> GameResult {
+            Ok(())
+        }
+        fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+            None
+        }
+        fn set_blend_mode(&mut self, _mode: Option<BlendMode>) {}
+        fn blend_mode(&self) -> Option<BlendMode> {
+            None
+        }
+    }
+
+    /// Fills the queue with draws on various layers and y coordinates,
+    /// numbered by their x coordinates.
+    fn fill_queue<'a>(queue: &mut DrawQueue<'a>, nothing: &'a Nothing) {
+        queue.clear();
+        let draws = [
+            (2, 0.0),
+            (1, 50.0),
+            (-1, 0.0),
+            (1, 10.0),
+            (2, -10.0),
+            (1, 10.0),
+        ];
+        for (i, &(layer, y)) in draws.iter().enumerate() {
+            queue.add(layer, nothing, DrawParam::new().dest([i as f32, y]));
+        }
+    }
+
+    /// Returns the numbers of the draws in the order they get drawn.
+    fn sorted_order(queue: &mut DrawQueue) -> Vec<usize> {
+        queue.sort();
+        queue
+            .draws
+            .iter()
+            .map(|draw| draw.param.dest.x as usize)
+            .collect()
+    }
+
+    #[test]
+    fn headless_test_draw_queue_order() {
+        let nothing = Nothing;
+        let mut queue = DrawQueue::new();
+        // Stable within a layer...
+        fill_queue(&mut queue, &nothing);
+        assert_eq!(sorted_order(&mut queue), vec![2, 1, 3, 5, 0, 4]);
+
+        // ...unless it's y-sorted, where equal y still keeps its order.
+        queue.set_y_sorted(1, true);
+        assert!(queue.is_y_sorted(1));
+        assert!(!queue.is_y_sorted(2));
+        fill_queue(&mut queue, &nothing);
+        assert_eq!(sorted_order(&mut queue), vec![2, 3, 5, 1, 0, 4]);
+
+        queue.set_y_sorted(1, false);
+        queue.set_y_sorted(2, true);
+        fill_queue(&mut queue, &nothing);
+        assert_eq!(sorted_order(&mut queue), vec![2, 1, 3, 5, 4, 0]);
+
+        // NaN goes after everything else in a y-sorted layer.
+        queue.set_y_sorted(1, true);
+        fill_queue(&mut queue, &nothing);
+        queue.add(1, &nothing, DrawParam::new().dest([6.0, f32::NAN]));
+        queue.add(1, &nothing, DrawParam::new().dest([7.0, -5.0]));
+        queue.add(1, &nothing, DrawParam::new().dest([8.0, f32::NAN]));
+        assert_eq!(sorted_order(&mut queue), vec![2, 7, 3, 5, 1, 6, 8, 4, 0]);
+        assert_eq!(compare_y(f32::NAN, 1.0), Ordering::Greater);
+        assert_eq!(compare_y(1.0, f32::NAN), Ordering::Less);
+    }
+}
@@ -134,12 +134,7 @@ impl Image {
     /// Load a new image from the file at the given path. The documentation for the
     /// [`filesystem`](../filesystem/index.html) module explains how the path must be specified.
     pub fn new<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Self> {
-        let img = {
-            let mut buf = Vec::new();
-            let mut reader = context.filesystem.open(path)?;
-            let _ = reader.read_to_end(&mut buf)?;
-            image::load_from_memory(&buf)?.to_rgba()
-        };
+        let img = load_rgba8(context, path)?;
         let (width, height) = img.dimensions();
         Self::from_rgba8(context, width as u16, height as u16, &img)
     }
@@ -171,11 +166,30 @@ impl Image {
     }
 
     /// Dumps the `Image`'s data to a `Vec` of `u8` RGBA values.
+    ///
+    /// This fails for images of canvases in a float or single-channel
+    /// [`CanvasFormat`](enum.CanvasFormat.html); take a
+    /// [`screenshot()`](fn.screenshot.html) of those to convert them first.
     pub fn to_rgba8(&self, ctx: &mut Context) -> GameResult<Vec<u8>> {
         use gfx::memory::Typed;
         use gfx::traits::FactoryExt;
 
         let gfx = &mut ctx.gfx_context;
+        if self.texture_handle.get_info().format != gfx.color_format().0 {
+            return Err(GameError::RenderError(
+                "Can only read back 8-bit RGBA images".to_owned(),
+            ));
+        }
+        // This might be a canvas that still has draws pending.
+        gfx.flush_batch()?;
+        let being_drawn_to = match gfx.canvas_resolve {
+            Some(ref texture) => *texture == self.texture_handle,
+            None => false,
+        };
+        if being_drawn_to {
+            canvas::resolve_canvas(gfx)?;
+        }
+        gfx.encoder.flush(&mut *gfx.device);
         let w = self.width;
         let h = self.height;
 
@@ -228,6 +242,87 @@ impl Image {
         Ok(data)
     }
 
+    /// Replaces the pixels in the given rectangle of the image with the
+    /// given buffer of `u8` RGBA values, laid out the same way as for
+    /// [`from_rgba8()`](#method.from_rgba8), without creating a new texture.
+    ///
+    /// The rectangle is in pixels and has to be whole pixels entirely
+    /// inside the image.  Since clones of an `Image` share the same
+    /// texture, they all see the new pixels.  Anything drawn with the
+    /// image before this is called still shows the old ones.
+    ///
+    /// Like [`to_rgba8()`](#method.to_rgba8), this fails for images of
+    /// canvases that aren't in `CanvasFormat::Screen`.
+    pub fn replace_region(&self, ctx: &mut Context, region: Rect, rgba: &[u8]) -> GameResult {
+        let whole = |v: f32| v >= 0.0 && v.fract() == 0.0;
+        let fits = [region.x, region.y, region.w, region.h]
+            .iter()
+            .all(|&v| whole(v))
+            && region.right() <= f32::from(self.width)
+            && region.bottom() <= f32::from(self.height);
+        if !fits {
+            let msg = format!(
+                "Can't replace region {:?} of a {}x{} image",
+                region, self.width, self.height
+            );
+            return Err(GameError::RenderError(msg));
+        }
+        let (x, y, width, height) = (
+            region.x as u16,
+            region.y as u16,
+            region.w as u16,
+            region.h as u16,
+        );
+        let expected_bytes = usize::from(width) * usize::from(height) * 4;
+        if rgba.len() != expected_bytes {
+            let msg = format!(
+                "Tried to replace {}x{} pixels of an image, but gave {} bytes of data (expected {})",
+                width,
+                height,
+                rgba.len(),
+                expected_bytes
+            );
+            return Err(GameError::RenderError(msg));
+        }
+
+        let gfx = &mut ctx.gfx_context;
+        if self.texture_handle.get_info().format != gfx.color_format().0 {
+            return Err(GameError::RenderError(
+                "Can only replace the pixels of 8-bit RGBA images".to_owned(),
+            ));
+        }
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        // Anything drawn with the image so far has to see the old contents.
+        gfx.flush_batch()?;
+        let info = gfx::texture::ImageInfoCommon {
+            xoffset: x,
+            yoffset: y,
+            zoffset: 0,
+            width,
+            height,
+            depth: 0,
+            format: (),
+            mipmap: 0,
+        };
+        let pixels: Vec<[u8; 4]> = rgba.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect();
+        let typed_tex = gfx
+            .backend_spec
+            .raw_to_typed_texture(self.texture_handle.clone());
+        gfx.encoder
+            .update_texture::<<BuggoSurfaceFormat as gfx::format::Formatted>::Surface, BuggoSurfaceFormat>(
+                &typed_tex, None, info, &pixels,
+            )
+            .map_err(|e| GameError::RenderError(format!("Could not update texture: {:?}", e)))
+    }
+
+    /// Replaces all the pixels of the image, the same as
+    /// [`replace_region()`](#method.replace_region) with the whole image.
+    pub fn replace(&self, ctx: &mut Context, rgba: &[u8]) -> GameResult {
+        self.replace_region(ctx, self.dimensions(), rgba)
+    }
+
     /// Encode the `Image` to the given file format and
     /// write it out to the given path.
     ///
@@ -239,21 +334,8 @@ impl Image {
         format: ImageFormat,
         path: P,
     ) -> GameResult {
-        use std::io;
         let data = self.to_rgba8(ctx)?;
-        let f = filesystem::create(ctx, path)?;
-        let writer = &mut io::BufWriter::new(f);
-        let color_format = image::ColorType::RGBA(8);
-        match format {
-            ImageFormat::Png => image::png::PNGEncoder::new(writer)
-                .encode(
-                    &data,
-                    u32::from(self.width),
-                    u32::from(self.height),
-                    color_format,
-                )
-                .map_err(Into::into),
-        }
+        encode_rgba8(ctx, format, path, self.width, self.height, &data)
     }
 
     /// A little helper function that creates a new `Image` that is just
@@ -307,6 +389,38 @@ impl Image {
     }
 }
 
+/// Loads the image file at the given path and converts it to RGBA.
+pub(crate) fn load_rgba8<P: AsRef<path::Path>>(
+    ctx: &mut Context,
+    path: P,
+) -> GameResult<image::RgbaImage> {
+    let mut buf = Vec::new();
+    let mut reader = ctx.filesystem.open(path)?;
+    let _ = reader.read_to_end(&mut buf)?;
+    Ok(image::load_from_memory(&buf)?.to_rgba())
+}
+
+/// Encodes RGBA pixels to the given file format and writes them out
+/// to the given path.
+pub(crate) fn encode_rgba8<P: AsRef<path::Path>>(
+    ctx: &mut Context,
+    format: ImageFormat,
+    path: P,
+    width: u16,
+    height: u16,
+    rgba: &[u8],
+) -> GameResult {
+    use std::io;
+    let f = filesystem::create(ctx, path)?;
+    let writer = &mut io::BufWriter::new(f);
+    let color_format = image::ColorType::RGBA(8);
+    match format {
+        ImageFormat::Png => image::png::PNGEncoder::new(writer)
+            .encode(rgba, u32::from(width), u32::from(height), color_format)
+            .map_err(Into::into),
+    }
+}
+
 impl fmt::Debug for Image {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -338,32 +452,14 @@ impl Drawable for Image {
         let mut new_param = param;
         new_param.scale = real_scale.into();
 
-        gfx.update_instance_properties(new_param.into())?;
-        let sampler = gfx
-            .samplers
-            .get_or_insert(self.sampler_info, gfx.factory.as_mut());
-        gfx.data.vbuf = gfx.quad_vertex_buffer.clone();
-        let typed_thingy = gfx
-            .backend_spec
-            .raw_to_typed_shader_resource(self.texture.clone());
-        gfx.data.tex = (typed_thingy, sampler);
-        let previous_mode: Option<BlendMode> = if let Some(mode) = self.blend_mode {
-            let current_mode = gfx.blend_mode();
-            if current_mode != mode {
-                gfx.set_blend_mode(mode)?;
-                Some(current_mode)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        gfx.draw(None)?;
-        if let Some(mode) = previous_mode {
-            gfx.set_blend_mode(mode)?;
-        }
-        Ok(())
+        // Images don't get drawn right away; consecutive draws of the
+        // same image get merged into one instanced draw call.
+        gfx.queue_quad(
+            self.texture.clone(),
+            self.sampler_info,
+            self.blend_mode,
+            new_param.into(),
+        )
     }
 
     fn dimensions(&self, _: &mut Context) -> Option<graphics::Rect> {
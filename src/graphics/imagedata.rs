@@ -0,0 +1,421 @@
+use std::path;
+
+use ::image;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::image::{encode_rgba8, load_rgba8};
+use crate::graphics::{Color, FilterMode, Image, ImageFormat};
+
+/// Pixels in main memory rather than on the graphics card, as RGBA
+/// with 8 bits per channel, for editing them directly.  Turn it into an
+/// [`Image`](type.Image.html) to draw it with
+/// [`to_image()`](#method.to_image).
+///
+/// Operations that keep the size of the image change it in place, while
+/// ones that change its size, like [`crop()`](#method.crop) or
+/// [`rotate_90()`](#method.rotate_90), return a new `ImageData`.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// let mut terrain = ImageData::load(ctx, "/terrain.png")?;
+/// // Blow a hole in it.
+/// terrain.map(|x, y, color| {
+///     let (dx, dy) = (f32::from(x) - 100.0, f32::from(y) - 80.0);
+///     if dx * dx + dy * dy < 400.0 {
+///         Color::new(0.0, 0.0, 0.0, 0.0)
+///     } else {
+///         color
+///     }
+/// });
+/// let image = terrain.to_image(ctx)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageData {
+    width: u16,
+    height: u16,
+    rgba: Vec<u8>,
+}
+
+impl ImageData {
+    /// Creates a new `ImageData` of the given size, with every pixel
+    /// transparent black.
+    pub fn new(width: u16, height: u16) -> Self {
+        ImageData {
+            width,
+            height,
+            rgba: vec![0; usize::from(width) * usize::from(height) * 4],
+        }
+    }
+
+    /// Creates a new `ImageData` from the given buffer of `u8` RGBA
+    /// values, laid out the same way as for
+    /// [`Image::from_rgba8()`](type.Image.html#method.from_rgba8).
+    pub fn from_rgba8(width: u16, height: u16, rgba: Vec<u8>) -> GameResult<Self> {
+        let expected_bytes = usize::from(width) * usize::from(height) * 4;
+        if rgba.len() != expected_bytes {
+            let msg = format!(
+                "Tried to create image data of size {}x{}, but gave {} bytes of data (expected {})",
+                width,
+                height,
+                rgba.len(),
+                expected_bytes
+            );
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        Ok(ImageData {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    /// Loads the image file at the given path. The documentation for the
+    /// [`filesystem`](../filesystem/index.html) module explains how the path must be specified.
+    pub fn load<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let img = load_rgba8(ctx, path)?;
+        let (width, height) = img.dimensions();
+        Self::from_rgba8(width as u16, height as u16, img.into_raw())
+    }
+
+    /// Reads back the pixels of an `Image` from the graphics card.
+    pub fn from_image(ctx: &mut Context, image: &Image) -> GameResult<Self> {
+        let rgba = image.to_rgba8(ctx)?;
+        Self::from_rgba8(image.width(), image.height(), rgba)
+    }
+
+    /// Creates a new `Image` with these pixels.
+    pub fn to_image(&self, ctx: &mut Context) -> GameResult<Image> {
+        Image::from_rgba8(ctx, self.width, self.height, &self.rgba)
+    }
+
+    /// Encode the pixels to the given file format and
+    /// write them out to the given path.
+    ///
+    /// See the [`filesystem`](../filesystem/index.html) module docs for where exactly
+    /// the file will end up.
+    pub fn encode<P: AsRef<path::Path>>(
+        &self,
+        ctx: &mut Context,
+        format: ImageFormat,
+        path: P,
+    ) -> GameResult {
+        encode_rgba8(ctx, format, path, self.width, self.height, &self.rgba)
+    }
+
+    /// Return the width of the image.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Return the height of the image.
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Gets the pixels as `u8` RGBA values.
+    pub fn as_rgba8(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    /// Gets the pixels as `u8` RGBA values, for changing them.
+    pub fn as_rgba8_mut(&mut self) -> &mut [u8] {
+        &mut self.rgba
+    }
+
+    /// Destroys the `ImageData` and returns its pixels as `u8` RGBA values.
+    pub fn into_rgba8(self) -> Vec<u8> {
+        self.rgba
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some((usize::from(y) * usize::from(self.width) + usize::from(x)) * 4)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the color of the pixel at the given position, or `None` if
+    /// it is outside the image.
+    pub fn get_pixel(&self, x: u16, y: u16) -> Option<Color> {
+        let i = self.index(x, y)?;
+        let p = &self.rgba[i..i + 4];
+        Some(Color::from_rgba(p[0], p[1], p[2], p[3]))
+    }
+
+    /// Sets the color of the pixel at the given position.
+    pub fn set_pixel(&mut self, x: u16, y: u16, color: Color) -> GameResult {
+        let i = self.index(x, y).ok_or_else(|| {
+            GameError::RenderError(format!(
+                "Pixel ({}, {}) is outside of the {}x{} image",
+                x, y, self.width, self.height
+            ))
+        })?;
+        let (r, g, b, a) = color.to_rgba();
+        self.rgba[i..i + 4].copy_from_slice(&[r, g, b, a]);
+        Ok(())
+    }
+
+    /// Sets every pixel to the given color.
+    pub fn fill(&mut self, color: Color) {
+        let (r, g, b, a) = color.to_rgba();
+        for pixel in self.rgba.chunks_mut(4) {
+            pixel.copy_from_slice(&[r, g, b, a]);
+        }
+    }
+
+    /// Sets every pixel in the given rectangle to the given color.  Any
+    /// part of the rectangle outside the image is ignored.
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u16, height: u16, color: Color) {
+        let (r, g, b, a) = color.to_rgba();
+        let (x0, x1) = clip(x, width, self.width);
+        let (y0, y1) = clip(y, height, self.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let i = self
+                    .index(x, y)
+                    .expect("Clipped to the image; should never happen");
+                self.rgba[i..i + 4].copy_from_slice(&[r, g, b, a]);
+            }
+        }
+    }
+
+    /// Copies all of `src` into this image with its top left corner at
+    /// the given position, replacing the pixels that were there.  Any
+    /// part of it that ends up outside the image is left out.
+    pub fn blit(&mut self, src: &ImageData, x: i32, y: i32) {
+        let (x0, x1) = clip(x, src.width, self.width);
+        let (y0, y1) = clip(y, src.height, self.height);
+        if x0 >= x1 {
+            return;
+        }
+        for dst_y in y0..y1 {
+            let src_x = (i32::from(x0) - x) as u16;
+            let src_y = (i32::from(dst_y) - y) as u16;
+            let from = src
+                .index(src_x, src_y)
+                .expect("Clipped to the source; should never happen");
+            let to = self
+                .index(x0, dst_y)
+                .expect("Clipped to the image; should never happen");
+            let len = usize::from(x1 - x0) * 4;
+            self.rgba[to..to + len].copy_from_slice(&src.rgba[from..from + len]);
+        }
+    }
+
+    /// Changes every pixel to what the given function returns, given
+    /// its position and current color.
+    pub fn map<F>(&mut self, mut f: F)
+    where
+        F: FnMut(u16, u16, Color) -> Color,
+    {
+        let width = usize::from(self.width);
+        for (i, pixel) in self.rgba.chunks_mut(4).enumerate() {
+            let (x, y) = ((i % width) as u16, (i / width) as u16);
+            let color = Color::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3]);
+            let (r, g, b, a) = f(x, y, color).to_rgba();
+            pixel.copy_from_slice(&[r, g, b, a]);
+        }
+    }
+
+    /// Mirrors the image left to right.
+    pub fn flip_horizontal(&mut self) {
+        let row_len = usize::from(self.width) * 4;
+        if row_len == 0 {
+            return;
+        }
+        for row in self.rgba.chunks_mut(row_len) {
+            let width = row.len() / 4;
+            for x in 0..width / 2 {
+                for c in 0..4 {
+                    row.swap(x * 4 + c, (width - 1 - x) * 4 + c);
+                }
+            }
+        }
+    }
+
+    /// Mirrors the image top to bottom.
+    pub fn flip_vertical(&mut self) {
+        let row_len = usize::from(self.width) * 4;
+        let height = usize::from(self.height);
+        for y in 0..height / 2 {
+            let (top, bottom) = self.rgba.split_at_mut((height - 1 - y) * row_len);
+            top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+        }
+    }
+
+    /// Turns the image upside down.
+    pub fn rotate_180(&mut self) {
+        self.flip_horizontal();
+        self.flip_vertical();
+    }
+
+    /// Returns a copy of the image turned a quarter turn clockwise.
+    pub fn rotate_90(&self) -> ImageData {
+        let mut rotated = ImageData::new(self.height, self.width);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let from = self
+                    .index(x, y)
+                    .expect("Inside the image; should never happen");
+                let to = rotated
+                    .index(self.height - 1 - y, x)
+                    .expect("Inside the rotated image; should never happen");
+                rotated.rgba[to..to + 4].copy_from_slice(&self.rgba[from..from + 4]);
+            }
+        }
+        rotated
+    }
+
+    /// Returns a copy of the image turned a quarter turn anticlockwise.
+    pub fn rotate_270(&self) -> ImageData {
+        let mut rotated = self.rotate_90();
+        rotated.rotate_180();
+        rotated
+    }
+
+    /// Returns a copy of the given rectangle of the image, which has
+    /// to be entirely inside it.
+    pub fn crop(&self, x: u16, y: u16, width: u16, height: u16) -> GameResult<ImageData> {
+        let fits = u32::from(x) + u32::from(width) <= u32::from(self.width)
+            && u32::from(y) + u32::from(height) <= u32::from(self.height);
+        if !fits {
+            return Err(GameError::RenderError(format!(
+                "Tried to crop {}x{} pixels at ({}, {}) out of a {}x{} image",
+                width, height, x, y, self.width, self.height
+            )));
+        }
+        let mut cropped = ImageData::new(width, height);
+        cropped.blit(self, -i32::from(x), -i32::from(y));
+        Ok(cropped)
+    }
+
+    /// Returns a copy of the image scaled to the given size, smoothly
+    /// or pixelated depending on the `FilterMode`.
+    pub fn resize(&self, width: u16, height: u16, filter: FilterMode) -> ImageData {
+        let filter = match filter {
+            FilterMode::Linear => image::FilterType::Triangle,
+            FilterMode::Nearest => image::FilterType::Nearest,
+        };
+        let buffer = image::RgbaImage::from_raw(
+            u32::from(self.width),
+            u32::from(self.height),
+            self.rgba.clone(),
+        )
+        .expect("Buffer is always the right size; should never happen");
+        let resized = image::imageops::resize(&buffer, u32::from(width), u32::from(height), filter);
+        ImageData {
+            width,
+            height,
+            rgba: resized.into_raw(),
+        }
+    }
+}
+
+/// Clips a span of `len` pixels starting at `start` to `0..size`,
+/// returning where it starts and ends within that.
+fn clip(start: i32, len: u16, size: u16) -> (u16, u16) {
+    let clamp = |v: i32| v.max(0).min(i32::from(size)) as u16;
+    (clamp(start), clamp(start.saturating_add(i32::from(len))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color {
+        r: 1.0,
+        g: 0.0,
+        b: 0.0,
+        a: 1.0,
+    };
+    const BLUE: Color = Color {
+        r: 0.0,
+        g: 0.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    /// A 3x2 image where each pixel's red channel is its index.
+    fn numbered() -> ImageData {
+        let rgba = (0..6).flat_map(|i| vec![i, 0, 0, 255]).collect();
+        ImageData::from_rgba8(3, 2, rgba).unwrap()
+    }
+
+    fn reds(data: &ImageData) -> Vec<u8> {
+        data.as_rgba8().chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn headless_test_from_rgba8_checks_size() {
+        assert!(ImageData::from_rgba8(2, 2, vec![0; 15]).is_err());
+        assert_eq!(ImageData::new(2, 3).as_rgba8().len(), 24);
+    }
+
+    #[test]
+    fn headless_test_get_set_pixel() {
+        let mut data = ImageData::new(4, 4);
+        data.set_pixel(3, 1, RED).unwrap();
+        assert_eq!(data.get_pixel(3, 1), Some(RED));
+        assert_eq!(&data.as_rgba8()[28..32], &[255, 0, 0, 255]);
+        assert_eq!(data.get_pixel(4, 0), None);
+        assert!(data.set_pixel(0, 4, RED).is_err());
+    }
+
+    #[test]
+    fn headless_test_fill_and_blit_clip() {
+        let mut data = ImageData::new(4, 4);
+        data.fill(BLUE);
+        data.fill_rect(-1, 2, 2, 5, RED);
+        assert_eq!(data.get_pixel(0, 3), Some(RED));
+        assert_eq!(data.get_pixel(1, 3), Some(BLUE));
+        assert_eq!(data.get_pixel(0, 1), Some(BLUE));
+
+        let mut data = ImageData::new(4, 3);
+        data.blit(&numbered(), 2, -1);
+        assert_eq!(reds(&data), vec![0, 0, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.blit(&numbered(), 10, 0);
+        data.blit(&numbered(), -3, 0);
+        assert_eq!(reds(&data), vec![0, 0, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn headless_test_flip_and_rotate() {
+        let mut data = numbered();
+        data.flip_horizontal();
+        assert_eq!(reds(&data), vec![2, 1, 0, 5, 4, 3]);
+        let mut data = numbered();
+        data.flip_vertical();
+        assert_eq!(reds(&data), vec![3, 4, 5, 0, 1, 2]);
+        let mut data = numbered();
+        data.rotate_180();
+        assert_eq!(reds(&data), vec![5, 4, 3, 2, 1, 0]);
+
+        let rotated = numbered().rotate_90();
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+        assert_eq!(reds(&rotated), vec![3, 0, 4, 1, 5, 2]);
+        assert_eq!(reds(&numbered().rotate_270()), vec![2, 5, 1, 4, 0, 3]);
+    }
+
+    #[test]
+    fn headless_test_crop_map_resize() {
+        let cropped = numbered().crop(1, 0, 2, 2).unwrap();
+        assert_eq!(reds(&cropped), vec![1, 2, 4, 5]);
+        assert!(numbered().crop(2, 0, 2, 1).is_err());
+
+        let mut data = numbered();
+        data.map(|x, y, color| if x == 1 && y == 1 { RED } else { color });
+        assert_eq!(data.get_pixel(1, 1), Some(RED));
+        assert_eq!(data.get_pixel(0, 1).unwrap().to_rgba(), (3, 0, 0, 255));
+
+        let resized = numbered().resize(6, 4, FilterMode::Nearest);
+        assert_eq!((resized.width(), resized.height()), (6, 4));
+        assert_eq!(resized.get_pixel(5, 3).unwrap().to_rgba(), (5, 0, 0, 255));
+    }
+}
@@ -0,0 +1,374 @@
+//! 2D lights with shadows.
+//!
+//! A [`Lighting`](struct.Lighting.html) renders [`Light`](struct.Light.html)s,
+//! with shadows cast by [`Occluder`](struct.Occluder.html)s, into a light
+//! canvas that is then drawn over the scene with `BlendMode::Multiply`,
+//! darkening whatever isn't lit.  Lights and occluders are in the same
+//! coordinates as everything else that gets drawn, transform included.
+//!
+//! Shadows are done with shadow maps: for each light, rays are cast
+//! outwards in every direction through a canvas with all the occluders
+//! drawn to it, and where each ray first hits something is recorded in a
+//! one pixel high canvas.  The light is then drawn into the light canvas
+//! anywhere closer to the light than that, blurred at the edges for soft
+//! shadows.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::*;
+//! # use ggez::graphics::lighting::*;
+//! # fn main() -> GameResult {
+//! # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+//! let mut lighting = Lighting::new(ctx)?;
+//! lighting.ambient = Color::new(0.1, 0.1, 0.2, 1.0);
+//! let wall = Occluder::polygon(ctx, &[[300.0, 200.0], [340.0, 200.0], [340.0, 400.0]])?;
+//! let torch = Light::point([200.0, 300.0], Color::new(1.0, 0.8, 0.5, 1.0), 250.0);
+//!
+//! // Every frame, after drawing the scene:
+//! lighting.render(ctx, &[torch], &[wall])?;
+//! draw(ctx, &lighting, DrawParam::default())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use mint;
+
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::postprocess::{draw_in_pixels, effect_shader, pass, shader_color};
+use crate::graphics::*;
+
+/// How many rays are cast for each light, which is how wide the
+/// shadow map is.
+const SHADOW_MAP_SIZE: u16 = 1024;
+
+mod consts {
+    gfx_defines! {
+        constant ShadowMap {
+            position: [f32; 2] = "u_Position",
+            radius: [f32; 2] = "u_Radius",
+            size: [f32; 2] = "u_Size",
+        }
+
+        constant Light {
+            color: [f32; 4] = "u_Color",
+            direction: f32 = "u_Direction",
+            angle: f32 = "u_Angle",
+            falloff: f32 = "u_Falloff",
+            softness: f32 = "u_Softness",
+        }
+    }
+}
+
+/// Which way a [`Light`](struct.Light.html) shines.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    /// Shines in all directions.
+    Point,
+    /// Shines in a cone.
+    Spot {
+        /// The direction the cone points in, in radians, clockwise
+        /// from the right like `DrawParam::rotation`.
+        direction: f32,
+        /// How wide the cone is, in radians.
+        angle: f32,
+    },
+}
+
+/// A light, see the [module docs](index.html).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Light {
+    /// Where the light is.
+    pub position: mint::Point2<f32>,
+    /// The color of the light.  Its alpha is how bright it is.
+    pub color: Color,
+    /// How far the light reaches.
+    pub radius: f32,
+    /// How quickly the light fades out towards its radius.  1.0 fades
+    /// evenly, higher values fade faster close to the light.
+    pub falloff: f32,
+    /// How soft the edges of shadows are.  0.0 makes them sharp.
+    pub softness: f32,
+    /// Whether this is a point light or a spot light.
+    pub kind: LightKind,
+}
+
+impl Light {
+    /// Creates a new point light with a falloff of 2.0 and a
+    /// softness of 1.0.
+    pub fn point<P>(position: P, color: Color, radius: f32) -> Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        Self {
+            position: position.into(),
+            color,
+            radius,
+            falloff: 2.0,
+            softness: 1.0,
+            kind: LightKind::Point,
+        }
+    }
+
+    /// Creates a new spot light shining in the given direction with a
+    /// cone of the given angle, both in radians, with a falloff of 2.0
+    /// and a softness of 1.0.
+    pub fn spot<P>(position: P, color: Color, radius: f32, direction: f32, angle: f32) -> Self
+    where
+        P: Into<mint::Point2<f32>>,
+    {
+        Self {
+            kind: LightKind::Spot { direction, angle },
+            ..Self::point(position, color, radius)
+        }
+    }
+}
+
+/// Something that casts shadows: a `Mesh`, drawn with a `DrawParam`.
+/// Anything the mesh draws that is more than half opaque blocks light.
+#[derive(Debug, Clone)]
+pub struct Occluder {
+    mesh: Mesh,
+    /// How the mesh is drawn.
+    pub param: DrawParam,
+}
+
+impl Occluder {
+    /// Creates an occluder from a mesh.
+    pub fn from_mesh(mesh: Mesh) -> Self {
+        Self {
+            mesh,
+            param: DrawParam::default(),
+        }
+    }
+
+    /// Creates an occluder shaped like the given polygon.
+    pub fn polygon<P>(ctx: &mut Context, points: &[P]) -> GameResult<Self>
+    where
+        P: Into<mint::Point2<f32>> + Clone,
+    {
+        let mesh = Mesh::new_polygon(ctx, DrawMode::fill(), points, WHITE)?;
+        Ok(Self::from_mesh(mesh))
+    }
+
+    /// Gets the mesh of the occluder.
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+}
+
+/// Renders lights and shadows, see the [module docs](index.html).
+///
+/// Drawing a `Lighting` draws its light canvas, which is the size of
+/// the screen, with `BlendMode::Multiply` unless set otherwise.
+#[derive(Debug)]
+pub struct Lighting {
+    /// The color everything that isn't lit gets, which the
+    /// lights are added on top of.
+    pub ambient: Color,
+    occluders: Canvas,
+    shadow_map: Canvas,
+    lights: Canvas,
+    shadow_map_shader: Shader<consts::ShadowMap>,
+    light_shader: Shader<consts::Light>,
+}
+
+impl Lighting {
+    /// Creates a new `Lighting` for the screen at its current size,
+    /// with a black ambient color.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let (width, height) = screen_pixels(ctx);
+        let (occluders, lights) = Self::create_canvases(ctx, width, height)?;
+        let shadow_map = CanvasBuilder::new(SHADOW_MAP_SIZE, 1)
+            .format(CanvasFormat::R8)
+            .depth_stencil(false)
+            .build(ctx)?;
+        let shadow_map_shader = effect_shader(
+            ctx,
+            "ShadowMap",
+            include_bytes!("shader/shadow_map_150.glslf"),
+            include_bytes!("shader/shadow_map_es300.glslf"),
+            consts::ShadowMap {
+                position: [0.0, 0.0],
+                radius: [0.0, 0.0],
+                size: [1.0, 1.0],
+            },
+            BlendMode::Replace,
+        )?;
+        let light_shader = effect_shader(
+            ctx,
+            "Light",
+            include_bytes!("shader/light_150.glslf"),
+            include_bytes!("shader/light_es300.glslf"),
+            consts::Light {
+                color: [0.0, 0.0, 0.0, 0.0],
+                direction: 0.0,
+                angle: 0.0,
+                falloff: 1.0,
+                softness: 0.0,
+            },
+            BlendMode::Add,
+        )?;
+        Ok(Self {
+            ambient: BLACK,
+            occluders,
+            shadow_map,
+            lights,
+            shadow_map_shader,
+            light_shader,
+        })
+    }
+
+    fn create_canvases(ctx: &mut Context, width: u16, height: u16) -> GameResult<(Canvas, Canvas)> {
+        let occluders = CanvasBuilder::new(width, height)
+            .depth_stencil(false)
+            .build(ctx)?;
+        let mut lights = CanvasBuilder::new(width, height)
+            .depth_stencil(false)
+            .build(ctx)?;
+        lights.set_blend_mode(Some(BlendMode::Multiply));
+        Ok((occluders, lights))
+    }
+
+    /// Renders the given lights, with shadows cast by the given occluders,
+    /// into the light canvas, using the current projection and transform.
+    ///
+    /// Afterwards drawing goes wherever it went before, screen or canvas.
+    pub fn render(
+        &mut self,
+        ctx: &mut Context,
+        lights: &[Light],
+        occluders: &[Occluder],
+    ) -> GameResult {
+        let (width, height) = screen_pixels(ctx);
+        if (width, height) != (self.lights.image().width(), self.lights.image().height()) {
+            let blend_mode = self.lights.blend_mode();
+            let (occluders, mut lights) = Self::create_canvases(ctx, width, height)?;
+            lights.set_blend_mode(blend_mode);
+            self.occluders = occluders;
+            self.lights = lights;
+        }
+
+        let previous = canvas::save_target(&ctx.gfx_context);
+        let result = self.render_canvases(ctx, lights, occluders);
+        canvas::restore_target(ctx, previous);
+        result
+    }
+
+    fn render_canvases(
+        &mut self,
+        ctx: &mut Context,
+        lights: &[Light],
+        occluders: &[Occluder],
+    ) -> GameResult {
+        set_canvas(ctx, Some(&self.occluders));
+        clear(ctx, Color::new(0.0, 0.0, 0.0, 0.0));
+        for occluder in occluders {
+            occluder.mesh.draw(ctx, occluder.param)?;
+        }
+        let mvp = ctx.gfx_context.projection() * ctx.gfx_context.transform();
+
+        set_canvas(ctx, Some(&self.lights));
+        clear(ctx, self.ambient);
+        for light in lights {
+            self.render_light(ctx, light, mvp)?;
+        }
+        Ok(())
+    }
+
+    fn render_light(&mut self, ctx: &mut Context, light: &Light, mvp: Matrix4) -> GameResult {
+        let width = f32::from(self.lights.image().width());
+        let height = f32::from(self.lights.image().height());
+        // Pixels of the light canvas, with y going up like OpenGL's.
+        let to_pixels = |x: f32, y: f32, w: f32| {
+            let ndc = mvp * na::Vector4::new(x, y, 0.0, w);
+            let (x, y) = (ndc.x * width / 2.0, ndc.y * height / 2.0);
+            if w == 0.0 {
+                (x, y)
+            } else {
+                (x + width / 2.0, y + height / 2.0)
+            }
+        };
+        let position = to_pixels(light.position.x, light.position.y, 1.0);
+        let length = |(x, y): (f32, f32)| (x * x + y * y).sqrt();
+        let radius = [
+            length(to_pixels(light.radius, 0.0, 0.0)),
+            length(to_pixels(0.0, light.radius, 0.0)),
+        ];
+        if radius[0] < 1.0 || radius[1] < 1.0 {
+            return Ok(());
+        }
+        let (direction, angle) = match light.kind {
+            LightKind::Point => (0.0, 2.0 * std::f32::consts::PI),
+            LightKind::Spot { direction, angle } => {
+                let (x, y) = to_pixels(direction.cos(), direction.sin(), 0.0);
+                ((y / radius[1]).atan2(x / radius[0]), angle / 2.0)
+            }
+        };
+
+        self.shadow_map_shader.send(
+            ctx,
+            consts::ShadowMap {
+                position: [position.0, position.1],
+                radius,
+                size: [width, height],
+            },
+        )?;
+        pass(
+            ctx,
+            &self.occluders,
+            Some(&self.shadow_map),
+            Some(self.shadow_map_shader.shader_id()),
+        )?;
+
+        let consts = consts::Light {
+            color: shader_color(ctx, light.color),
+            direction,
+            angle,
+            falloff: light.falloff,
+            softness: light.softness,
+        };
+        self.light_shader.send(ctx, consts)?;
+        let mut image = self.shadow_map.image().clone();
+        image.set_blend_mode(Some(BlendMode::Add));
+        let shader = Some(self.light_shader.shader_id());
+        draw_in_pixels(ctx, Some(&self.lights), shader, |ctx, (_, h)| {
+            // Stretch the shadow map over the light's circle; the
+            // shader works out which part of it to look at.
+            let param = DrawParam::new()
+                .dest([position.0 - radius[0], h - position.1 - radius[1]])
+                .scale([
+                    radius[0] * 2.0 / f32::from(SHADOW_MAP_SIZE),
+                    radius[1] * 2.0,
+                ]);
+            image.draw(ctx, param)
+        })
+    }
+
+    /// Gets the light canvas, as of the last
+    /// [`render()`](#method.render).
+    pub fn canvas(&self) -> &Canvas {
+        &self.lights
+    }
+}
+
+impl Drawable for Lighting {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.lights.draw(ctx, param)
+    }
+    fn dimensions(&self, ctx: &mut Context) -> Option<Rect> {
+        self.lights.dimensions(ctx)
+    }
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.lights.set_blend_mode(mode);
+    }
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.lights.blend_mode()
+    }
+}
+
+/// The size of whatever is drawn to when no canvas is set.
+fn screen_pixels(ctx: &Context) -> (u16, u16) {
+    let (width, height, _, _) = ctx.gfx_context.default_render_target().0.get_dimensions();
+    (width, height)
+}
@@ -629,21 +629,13 @@ impl Mesh {
 impl Drawable for Mesh {
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
         self.debug_id.assert(ctx);
-        let gfx = &mut ctx.gfx_context;
-        gfx.update_instance_properties(param.into())?;
-
-        gfx.data.vbuf = self.buffer.clone();
-        let texture = self.image.texture.clone();
-        let sampler = gfx
-            .samplers
-            .get_or_insert(self.image.sampler_info, gfx.factory.as_mut());
-
-        let typed_thingy = gfx.backend_spec.raw_to_typed_shader_resource(texture);
-        gfx.data.tex = (typed_thingy, sampler);
-
-        gfx.draw(Some(&self.slice))?;
-
-        Ok(())
+        ctx.gfx_context.queue_mesh(
+            self.buffer.clone(),
+            self.slice.clone(),
+            self.image.texture.clone(),
+            self.image.sampler_info,
+            param.into(),
+        )
     }
     fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
         Some(self.rect)
@@ -10,6 +10,10 @@
 //! `LÖVE` library that it is based on. Many operations that are batched by default
 //! in love (e.g. drawing primitives like rectangles or circles) are *not* batched
 //! in `ggez`, so render loops with a large number of draw calls can be very slow.
+//! The one exception is drawing the same `Image` or `Mesh` several times in a
+//! row with nothing else in between: those draws get merged into a single draw
+//! call automatically, until something like drawing something else, changing
+//! the shader, blend mode or transform, or switching canvases flushes them.
 //! The primary solution to efficiently rendering a large number of primitives is
 //! a [`SpriteBatch`](spritebatch/struct.SpriteBatch.html), which can be orders
 //! of magnitude more efficient than individual
@@ -38,25 +42,50 @@ use crate::context::DebugId;
 use crate::GameError;
 use crate::GameResult;
 
+pub(crate) mod atlas;
+pub(crate) mod batch;
+pub(crate) mod camera;
 pub(crate) mod canvas;
 pub(crate) mod context;
 pub(crate) mod drawparam;
+pub(crate) mod drawqueue;
 pub(crate) mod image;
+pub(crate) mod imagedata;
 pub(crate) mod mesh;
+pub(crate) mod multisample;
+pub(crate) mod nineslice;
+pub(crate) mod scaling;
 pub(crate) mod shader;
+pub(crate) mod stencil;
 pub(crate) mod text;
 pub(crate) mod types;
 
 pub use mint;
 pub(crate) use nalgebra as na;
 
+pub mod animation;
+pub mod lighting;
+pub mod particles;
+pub mod postprocess;
 pub mod spritebatch;
+pub mod spritesheet;
+pub mod tilemap;
 
+pub use crate::graphics::atlas::*;
+pub use crate::graphics::camera::*;
 pub use crate::graphics::canvas::*;
 pub use crate::graphics::drawparam::*;
+pub use crate::graphics::drawqueue::*;
 pub use crate::graphics::image::*;
+pub use crate::graphics::imagedata::*;
 pub use crate::graphics::mesh::*;
+pub use crate::graphics::nineslice::*;
+pub use crate::graphics::scaling::*;
 pub use crate::graphics::shader::*;
+pub use crate::graphics::stencil::{
+    clear_stencil, draw_stencil, set_stencil_test, stencil_test, StencilAction, StencilMode,
+    StencilTest,
+};
 pub use crate::graphics::text::*;
 pub use crate::graphics::types::*;
 
@@ -214,6 +243,18 @@ impl From<conf::Backend> for GlBackendSpec {
     }
 }
 
+impl GlBackendSpec {
+    /// Returns the text of the fragment shader `draw_stencil()` uses
+    /// with the default vertex shader, which skips transparent pixels.
+    fn stencil_shader(&self) -> &'static [u8] {
+        match self.api {
+            glutin::Api::OpenGl => include_bytes!("shader/stencil_150.glslf"),
+            glutin::Api::OpenGlEs => include_bytes!("shader/stencil_es300.glslf"),
+            a => panic!("Unsupported API: {:?}, should never happen", a),
+        }
+    }
+}
+
 impl BackendSpec for GlBackendSpec {
     type Resources = gfx_device_gl::Resources;
     type Factory = gfx_device_gl::Factory;
@@ -369,6 +410,7 @@ gfx_defines! {
         tex: gfx::TextureSampler<[f32; 4]> = "t_Texture",
         globals: gfx::ConstantBuffer<Globals> = "Globals",
         rect_instance_properties: gfx::InstanceBuffer<InstanceProperties> = (),
+        scissor: gfx::Scissor = (),
         // The default values here are overwritten by the
         // pipeline init values in `shader::create_shader()`.
         out: gfx::RawRenderTarget =
@@ -376,6 +418,11 @@ gfx_defines! {
            gfx::format::Format(gfx::format::SurfaceType::R8_G8_B8_A8, gfx::format::ChannelType::Srgb),
            gfx::state::ColorMask::all(), Some(gfx::preset::blend::ALPHA)
           ),
+        stencil: gfx::StencilTarget<gfx::format::DepthStencil> =
+          gfx::state::Stencil::new(
+            gfx::state::Comparison::Always, 0,
+            (gfx::state::StencilOp::Keep, gfx::state::StencilOp::Keep, gfx::state::StencilOp::Keep)
+          ),
     }
 }
 
@@ -467,6 +514,7 @@ impl From<gfx::buffer::CreationError> for GameError {
 /// Clear the screen to the background color.
 pub fn clear(ctx: &mut Context, color: Color) {
     let gfx = &mut ctx.gfx_context;
+    gfx.prepare_clear();
     let linear_color: types::LinearColor = color.into();
     let c: [f32; 4] = linear_color.into();
     gfx.encoder.clear_raw(&gfx.data.out, c.into());
@@ -489,35 +537,38 @@ where
 ///
 /// Unsets any active canvas.
 pub fn present(ctx: &mut Context) -> GameResult<()> {
+    ctx.gfx_context.flush_batch()?;
+    canvas::resolve_canvas(&mut ctx.gfx_context)?;
+    ctx.gfx_context.canvas_resolve = None;
+    scaling::present_virtual_screen(ctx)?;
     let gfx = &mut ctx.gfx_context;
-    gfx.data.out = gfx.screen_render_target.clone();
+    gfx.use_default_render_target();
     // We might want to give the user more control over when the
     // encoder gets flushed eventually, if we want them to be able
     // to do their own gfx drawing.  HOWEVER, the whole pipeline type
     // thing is a bigger hurdle, so this is fine for now.
     gfx.encoder.flush(&mut *gfx.device);
+    gfx.scissor_applied = false;
     gfx.window.swap_buffers()?;
     gfx.device.cleanup();
     Ok(())
 }
 
 /// Take a screenshot by outputting the current render surface
-/// (screen or selected canvas) to an `Image`.
+/// (screen or selected canvas) to an `Image`.  Anti-aliased surfaces
+/// are resolved into a plain image, and canvases in other formats than
+/// the screen are converted to the screen's format.
 pub fn screenshot(ctx: &mut Context) -> GameResult<Image> {
     use gfx::memory::Bind;
     let debug_id = DebugId::get(ctx);
 
     let gfx = &mut ctx.gfx_context;
-    let (w, h, _depth, aa) = gfx.data.out.get_dimensions();
-    if aa != gfx_core::texture::AaMode::Single {
-        // Details see https://github.com/ggez/ggez/issues/751
-        return Err(GameError::RenderError("Can't take screenshots of anti aliased textures.\n(since neither copying or resolving them is supported right now)".to_string()));
-    }
+    let (w, h, _depth, _aa) = gfx.data.out.get_dimensions();
 
     let surface_format = gfx.color_format();
     let gfx::format::Format(surface_type, channel_type) = surface_format;
 
-    let texture_kind = gfx::texture::Kind::D2(w, h, aa);
+    let texture_kind = gfx::texture::Kind::D2(w, h, gfx::texture::AaMode::Single);
     let texture_info = gfx::texture::Info {
         kind: texture_kind,
         levels: 1,
@@ -528,30 +579,21 @@ pub fn screenshot(ctx: &mut Context) -> GameResult<Image> {
     let target_texture = gfx
         .factory
         .create_texture_raw(texture_info, Some(channel_type), None)?;
-    let image_info = gfx::texture::ImageInfoCommon {
-        xoffset: 0,
-        yoffset: 0,
-        zoffset: 0,
-        width: w,
-        height: h,
-        depth: 0,
-        format: surface_format,
-        mipmap: 0,
-    };
-
-    let mut local_encoder: gfx::Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer> =
-        gfx.factory.create_command_buffer().into();
-
-    local_encoder.copy_texture_to_texture_raw(
-        gfx.data.out.get_texture(),
-        None,
-        image_info,
-        &target_texture,
-        None,
-        image_info,
-    )?;
 
-    local_encoder.flush(&mut *gfx.device);
+    // Copying can't handle anti-aliased targets or canvases in other
+    // formats, but resolving converts them all to the screen format.
+    // Except anti-aliased canvases in other formats, which GL can only
+    // resolve into the same format, so those go through their image.
+    match gfx.canvas_resolve.clone() {
+        Some(canvas_texture) => {
+            canvas::resolve_canvas(gfx)?;
+            multisample::convert(gfx, &canvas_texture, &target_texture)?;
+        }
+        None => {
+            let out = gfx.data.out.clone();
+            multisample::resolve(gfx, &out, &target_texture)?;
+        }
+    }
 
     let resource_desc = gfx::texture::ResourceDesc {
         channel: channel_type,
@@ -810,6 +852,50 @@ pub fn apply_transformations(context: &mut Context) -> GameResult {
     gfx.update_globals()
 }
 
+/// Clips everything drawn from now on to the given rectangle, or with
+/// `None`, stops clipping.  This replaces any rectangle set before,
+/// including ones pushed with [`push_scissor()`](fn.push_scissor.html).
+///
+/// The rectangle is in screen coordinates, see
+/// [`set_screen_coordinates()`](fn.set_screen_coordinates.html); the
+/// transform stack doesn't apply to it.  It works the same whether
+/// drawing to the screen or to a `Canvas`, and doesn't affect
+/// [`clear()`](fn.clear.html).
+pub fn set_scissor(context: &mut Context, rect: Option<Rect>) {
+    context.gfx_context.scissor = rect;
+}
+
+/// Returns the rectangle drawing is currently clipped to, if any.
+pub fn scissor(context: &Context) -> Option<Rect> {
+    context.gfx_context.scissor
+}
+
+/// Clips everything drawn from now on to the part of the given rectangle
+/// that's inside the current one, if any, until the matching
+/// [`pop_scissor()`](fn.pop_scissor.html).  Handy for nested things,
+/// like a scrolling list inside a window.
+///
+/// See [`set_scissor()`](fn.set_scissor.html) for details.
+pub fn push_scissor(context: &mut Context, rect: Rect) {
+    let gfx = &mut context.gfx_context;
+    let clipped = match gfx.scissor {
+        Some(current) => current
+            .intersection(rect)
+            .unwrap_or_else(|| Rect::new(rect.x, rect.y, 0.0, 0.0)),
+        None => rect,
+    };
+    gfx.scissor_stack.push(gfx.scissor);
+    gfx.scissor = Some(clipped);
+}
+
+/// Goes back to clipping to whatever rectangle was used before the
+/// last [`push_scissor()`](fn.push_scissor.html).  Stops clipping if
+/// there are no pushed rectangles left.
+pub fn pop_scissor(context: &mut Context) {
+    let gfx = &mut context.gfx_context;
+    gfx.scissor = gfx.scissor_stack.pop().unwrap_or(None);
+}
+
 /// Sets the blend mode of the currently active shader program
 pub fn set_blend_mode(ctx: &mut Context, mode: BlendMode) -> GameResult {
     ctx.gfx_context.set_blend_mode(mode)
@@ -884,24 +970,50 @@ pub fn window(context: &Context) -> &glutin::Window {
 /// Returns the size of the window in pixels as (width, height),
 /// including borders, titlebar, etc.
 /// Returns zeros if the window doesn't exist.
+///
+/// The pixels are logical or physical ones depending on
+/// [`WindowMode::dpi_mode`](../conf/struct.WindowMode.html#structfield.dpi_mode).
 pub fn size(context: &Context) -> (f32, f32) {
     let gfx = &context.gfx_context;
+    let scale = gfx.logical_scale();
     gfx.window
         .get_outer_size()
-        .map(|logical_size| (logical_size.width as f32, logical_size.height as f32))
+        .map(|logical_size| {
+            (
+                logical_size.width as f32 * scale,
+                logical_size.height as f32 * scale,
+            )
+        })
         .unwrap_or((0.0, 0.0))
 }
 
 /// Returns the size of the window's underlying drawable in pixels as (width, height).
 /// Returns zeros if window doesn't exist.
+///
+/// The pixels are logical or physical ones depending on
+/// [`WindowMode::dpi_mode`](../conf/struct.WindowMode.html#structfield.dpi_mode).
 pub fn drawable_size(context: &Context) -> (f32, f32) {
     let gfx = &context.gfx_context;
+    let scale = gfx.logical_scale();
     gfx.window
         .get_inner_size()
-        .map(|logical_size| (logical_size.width as f32, logical_size.height as f32))
+        .map(|logical_size| {
+            (
+                logical_size.width as f32 * scale,
+                logical_size.height as f32 * scale,
+            )
+        })
         .unwrap_or((0.0, 0.0))
 }
 
+/// Returns the hidpi factor of the monitor the window is on: how many
+/// physical pixels there are to a logical pixel.  This changes when
+/// the window is moved to a different monitor, see
+/// [`EventHandler::dpi_changed_event()`](../event/trait.EventHandler.html#method.dpi_changed_event).
+pub fn hidpi_factor(context: &Context) -> f32 {
+    context.gfx_context.hidpi_factor
+}
+
 /// Returns raw `gfx-rs` state objects, if you want to use `gfx-rs` to write
 /// your own graphics pipeline then this gets you the interfaces you need
 /// to do so.
@@ -922,10 +1034,15 @@ pub fn gfx_objects(
     gfx::handle::RawRenderTargetView<<GlBackendSpec as BackendSpec>::Resources>,
 ) {
     let gfx = &mut context.gfx_context;
+    // Whatever the caller does with these has to happen after
+    // everything that has been drawn so far.
+    if let Err(e) = gfx.flush_batch() {
+        warn!("Could not flush batched draws: {}", e);
+    }
     let f = &mut gfx.factory;
     let d = gfx.device.as_mut();
     let e = &mut gfx.encoder;
-    let dv = gfx.depth_view.clone();
+    let dv = gfx::memory::Typed::raw(&gfx.data.stencil.0).clone();
     let cv = gfx.data.out.clone();
     (f, d, e, dv, cv)
 }
@@ -0,0 +1,180 @@
+//! Resolving multisampled render targets into plain textures.
+//!
+//! gfx can copy between textures, but not out of a multisampled one,
+//! and its blit isn't finished, so this talks to OpenGL directly.  That
+//! can only be done through `gfx_device_gl`'s unsafe `with_gl()`, with
+//! raw GL calls that are unsafe themselves, hence the exception to the
+//! crate's `deny(unsafe_code)`.  Nothing here hands out pointers or
+//! touches memory gfx owns; it only makes framebuffers of its own,
+//! checks them and the blit for GL errors, and deletes them again.
+#![allow(unsafe_code)]
+
+use gfx::handle::{RawRenderTargetView, RawTexture};
+use gfx::texture::AaMode;
+use gfx_device_gl::{NewTexture, Resources};
+use gfx_gl as gl;
+
+use crate::error::{GameError, GameResult};
+use crate::graphics::context::GraphicsContext;
+
+/// Averages the samples of each pixel of the render target `src` into
+/// the texture `dst`, which has to be a single-sampled texture the same
+/// size.  Works for single-sampled render targets as well, and converts
+/// between color formats if `src` and `dst` differ, but only if `src`
+/// isn't multisampled: GL can't resolve and convert in one go.  Resolve
+/// into a texture of the same format and [`convert()`](fn.convert.html)
+/// that for those.
+///
+/// Everything drawn so far gets sent to the graphics card first.
+pub(crate) fn resolve(
+    gfx: &mut GraphicsContext,
+    src: &RawRenderTargetView<Resources>,
+    dst: &RawTexture<Resources>,
+) -> GameResult {
+    let (width, height, _, aa) = src.get_dimensions();
+    let source_kind = match aa {
+        AaMode::Single => gl::TEXTURE_2D,
+        _ => gl::TEXTURE_2D_MULTISAMPLE,
+    };
+    run_blit(
+        gfx,
+        *src.get_texture().resource(),
+        source_kind,
+        dst,
+        width,
+        height,
+    )
+}
+
+/// Copies all of the single-sampled texture `src` into the texture `dst`,
+/// which has to be the same size, converting between color formats if
+/// they differ.
+///
+/// Everything drawn so far gets sent to the graphics card first.
+pub(crate) fn convert(
+    gfx: &mut GraphicsContext,
+    src: &RawTexture<Resources>,
+    dst: &RawTexture<Resources>,
+) -> GameResult {
+    let (width, height, _, _) = src.get_info().kind.get_dimensions();
+    run_blit(gfx, *src.resource(), gl::TEXTURE_2D, dst, width, height)
+}
+
+fn run_blit(
+    gfx: &mut GraphicsContext,
+    source: NewTexture,
+    source_kind: gl::types::GLenum,
+    dst: &RawTexture<Resources>,
+    width: u16,
+    height: u16,
+) -> GameResult {
+    let target = match *dst.resource() {
+        NewTexture::Texture(target) => target,
+        NewTexture::Surface(_) => {
+            return Err(GameError::RenderError(
+                "Can't resolve a render target into a renderbuffer".to_owned(),
+            ));
+        }
+    };
+
+    gfx.flush_batch()?;
+    gfx.encoder.flush(&mut *gfx.device);
+    // Flushing turns the scissor test off, which would clip the blit.
+    gfx.scissor_applied = false;
+    let mut result = Ok(());
+    unsafe {
+        // `with_gl()` resets the GL state gfx keeps track of, and gfx
+        // sets up whatever it needs again for the next draw.
+        gfx.device.with_gl(|gl| {
+            result = blit(gl, source, source_kind, target, width, height);
+        });
+    }
+    result
+}
+
+/// Blits all of `source` into the texture `target` with framebuffers
+/// made just for it.
+unsafe fn blit(
+    gl: &gl::Gl,
+    source: NewTexture,
+    source_kind: gl::types::GLenum,
+    target: gl::types::GLuint,
+    width: u16,
+    height: u16,
+) -> GameResult {
+    // Don't blame the blit for errors left over from earlier; GL keeps
+    // one error flag per kind of error, so this ends quickly.
+    for _ in 0..16 {
+        if gl.GetError() == gl::NO_ERROR {
+            break;
+        }
+    }
+
+    let mut framebuffers = [0; 2];
+    gl.GenFramebuffers(2, framebuffers.as_mut_ptr());
+    let [read_framebuffer, draw_framebuffer] = framebuffers;
+    match source {
+        // The window's back buffer, see `gfx_window_glutin`.
+        NewTexture::Surface(0) => {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+            gl.ReadBuffer(gl::BACK);
+        }
+        NewTexture::Surface(renderbuffer) => {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_framebuffer);
+            gl.FramebufferRenderbuffer(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                renderbuffer,
+            );
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+        NewTexture::Texture(texture) => {
+            gl.BindFramebuffer(gl::READ_FRAMEBUFFER, read_framebuffer);
+            gl.FramebufferTexture2D(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                source_kind,
+                texture,
+                0,
+            );
+            gl.ReadBuffer(gl::COLOR_ATTACHMENT0);
+        }
+    }
+    gl.BindFramebuffer(gl::DRAW_FRAMEBUFFER, draw_framebuffer);
+    gl.FramebufferTexture2D(
+        gl::DRAW_FRAMEBUFFER,
+        gl::COLOR_ATTACHMENT0,
+        gl::TEXTURE_2D,
+        target,
+        0,
+    );
+
+    let read_status = gl.CheckFramebufferStatus(gl::READ_FRAMEBUFFER);
+    let draw_status = gl.CheckFramebufferStatus(gl::DRAW_FRAMEBUFFER);
+    let result = if read_status != gl::FRAMEBUFFER_COMPLETE {
+        Err(GameError::RenderError(format!(
+            "Can't resolve render target, its framebuffer is incomplete (status 0x{:x})",
+            read_status
+        )))
+    } else if draw_status != gl::FRAMEBUFFER_COMPLETE {
+        Err(GameError::RenderError(format!(
+            "Can't resolve into texture, its framebuffer is incomplete (status 0x{:x})",
+            draw_status
+        )))
+    } else {
+        let (w, h) = (i32::from(width), i32::from(height));
+        gl.BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        match gl.GetError() {
+            gl::NO_ERROR => Ok(()),
+            error => Err(GameError::RenderError(format!(
+                "Could not resolve render target (GL error 0x{:x})",
+                error
+            ))),
+        }
+    };
+
+    gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+    gl.DeleteFramebuffers(2, framebuffers.as_ptr());
+    result
+}
@@ -0,0 +1,404 @@
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::*;
+
+/// How the parts of a [`NineSlice`](struct.NineSlice.html) between its
+/// corners fill the space they are given.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SmartDefault)]
+pub enum SliceMode {
+    /// Stretch the part of the image to fit.
+    #[default]
+    Stretch,
+    /// Repeat the part of the image at its original size, cutting the
+    /// last repeat short if it doesn't fit.  Parts less than a pixel
+    /// long are stretched instead.
+    Tile,
+}
+
+/// How far in from each edge of the image the borders of a
+/// [`NineSlice`](struct.NineSlice.html) go, in pixels of the image.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Insets {
+    /// Width of the left border.
+    pub left: f32,
+    /// Height of the top border.
+    pub top: f32,
+    /// Width of the right border.
+    pub right: f32,
+    /// Height of the bottom border.
+    pub bottom: f32,
+}
+
+impl Insets {
+    /// Creates new `Insets`.
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Insets {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Creates `Insets` that are the same on every side.
+    pub fn uniform(inset: f32) -> Self {
+        Insets::new(inset, inset, inset, inset)
+    }
+}
+
+/// An image drawn as a panel of any size by cutting it into nine parts,
+/// also known as a nine-patch: the corners are drawn as they are, the
+/// edges are stretched or tiled along their length and the center is
+/// stretched or tiled to fill the rest.  Handy for dialog boxes and
+/// buttons.
+///
+/// The image can also be a region of a bigger image, such as a
+/// [`TextureAtlas`](struct.TextureAtlas.html) page, by setting
+/// [`src()`](#method.src).  All the parts are drawn as one batch.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// let image = Image::new(ctx, "/panel.png")?;
+/// let panel = NineSlice::new(image, Insets::uniform(8.0), 300.0, 120.0)
+///     .edges(SliceMode::Tile);
+/// graphics::draw(ctx, &panel, DrawParam::new().dest([20.0, 20.0]))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct NineSlice {
+    image: Image,
+    src: Rect,
+    insets: Insets,
+    width: f32,
+    height: f32,
+    edges: SliceMode,
+    center: SliceMode,
+}
+
+impl NineSlice {
+    /// Creates a new `NineSlice` of the whole image, `width` by `height`
+    /// pixels in size, with stretched edges and center.
+    pub fn new(image: Image, insets: Insets, width: f32, height: f32) -> Self {
+        NineSlice {
+            image,
+            src: Rect::one(),
+            insets,
+            width,
+            height,
+            edges: SliceMode::Stretch,
+            center: SliceMode::Stretch,
+        }
+    }
+
+    /// Uses only the given region of the image, in the same 0.0-1.0
+    /// coordinates as [`DrawParam::src`](struct.DrawParam.html#structfield.src)
+    /// and [`AtlasRegion::src`](struct.AtlasRegion.html#structfield.src).
+    /// The insets are measured from the edges of the region.
+    pub fn src(mut self, src: Rect) -> Self {
+        self.src = src;
+        self
+    }
+
+    /// Sets how the edges fill their length.
+    pub fn edges(mut self, mode: SliceMode) -> Self {
+        self.edges = mode;
+        self
+    }
+
+    /// Sets how the center fills the middle.
+    pub fn center(mut self, mode: SliceMode) -> Self {
+        self.center = mode;
+        self
+    }
+
+    /// Gets the size the panel is drawn at, as (width, height).
+    pub fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    /// Sets the size the panel is drawn at.
+    pub fn set_size(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Gets the insets.
+    pub fn insets(&self) -> Insets {
+        self.insets
+    }
+
+    /// Sets the insets.
+    pub fn set_insets(&mut self, insets: Insets) {
+        self.insets = insets;
+    }
+
+    /// Gets the image.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Works out every part to draw, as where it goes in pixels of the
+    /// panel and its `src` in the image.
+    fn parts(&self) -> Vec<(Rect, Rect)> {
+        let image_w = f32::from(self.image.width());
+        let image_h = f32::from(self.image.height());
+        let columns = |mode| {
+            segments(
+                self.src.w * image_w,
+                self.width,
+                self.insets.left,
+                self.insets.right,
+                mode,
+            )
+        };
+        let rows = |mode| {
+            segments(
+                self.src.h * image_h,
+                self.height,
+                self.insets.top,
+                self.insets.bottom,
+                mode,
+            )
+        };
+        // The edges tile along their length but not across it.
+        let edge_columns = columns(self.edges);
+        let center_columns = columns(self.center);
+        let edge_rows = rows(self.edges);
+        let center_rows = rows(self.center);
+
+        let mut parts = Vec::new();
+        for part_row in 0..3 {
+            let cols = if part_row == 1 {
+                &center_columns[1]
+            } else {
+                &edge_columns[1]
+            };
+            let cols = [&edge_columns[0], cols, &edge_columns[2]];
+            for (part_col, cols) in cols.iter().enumerate() {
+                let rows = if part_col == 1 {
+                    &center_rows[part_row]
+                } else {
+                    &edge_rows[part_row]
+                };
+                for col in cols.iter() {
+                    for row in rows.iter() {
+                        let dest = Rect::new(col.dest, row.dest, col.len, row.len);
+                        let src = Rect::new(
+                            self.src.x + col.src / image_w,
+                            self.src.y + row.src / image_h,
+                            col.src_len / image_w,
+                            row.src_len / image_h,
+                        );
+                        parts.push((dest, src));
+                    }
+                }
+            }
+        }
+        parts
+    }
+}
+
+/// Part of a row or column of a `NineSlice`, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Segment {
+    dest: f32,
+    len: f32,
+    src: f32,
+    src_len: f32,
+}
+
+/// The shortest part of the image that gets tiled, in pixels; anything
+/// shorter would take far too many tiles, so it is stretched instead.
+const MIN_TILE: f32 = 1.0;
+
+/// Splits one axis of a `NineSlice` into the segments of its first
+/// border, middle and second border.  Borders that don't fit get
+/// squashed.
+fn segments(
+    src_size: f32,
+    size: f32,
+    start: f32,
+    end: f32,
+    middle_mode: SliceMode,
+) -> [Vec<Segment>; 3] {
+    let squash = if start + end > size && start + end > 0.0 {
+        size / (start + end)
+    } else {
+        1.0
+    };
+    let middle_src = src_size - start - end;
+    let middle = size - (start + end) * squash;
+    let middle_mode = if middle_src < MIN_TILE {
+        SliceMode::Stretch
+    } else {
+        middle_mode
+    };
+    let mut middles = Vec::new();
+    match middle_mode {
+        _ if middle <= 0.0 || middle_src <= 0.0 => (),
+        SliceMode::Stretch => middles.push(Segment {
+            dest: start * squash,
+            len: middle,
+            src: start,
+            src_len: middle_src,
+        }),
+        SliceMode::Tile => {
+            let mut done = 0.0;
+            while done < middle {
+                let len = middle_src.min(middle - done);
+                middles.push(Segment {
+                    dest: start * squash + done,
+                    len,
+                    src: start,
+                    src_len: len,
+                });
+                done += len;
+            }
+        }
+    }
+    let border = |dest, len, src| {
+        if len > 0.0 {
+            vec![Segment {
+                dest,
+                len: len * squash,
+                src,
+                src_len: len,
+            }]
+        } else {
+            Vec::new()
+        }
+    };
+    [
+        border(0.0, start, 0.0),
+        middles,
+        border(size - end * squash, end, src_size - end),
+    ]
+}
+
+impl Drawable for NineSlice {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.image.debug_id.assert(ctx);
+        if self.width <= 0.0 || self.height <= 0.0 {
+            return Ok(());
+        }
+        // Transform a unit square the same way drawing an image of
+        // this size would, then each part within that.
+        let mut panel_param = param;
+        panel_param.scale.x *= self.width;
+        panel_param.scale.y *= self.height;
+        let panel = DrawTransform::from(panel_param).matrix;
+        for (dest, src) in self.parts() {
+            let part = Matrix4::new_translation(&na::Vector3::new(
+                dest.x / self.width,
+                dest.y / self.height,
+                0.0,
+            )) * Matrix4::new_nonuniform_scaling(&na::Vector3::new(
+                dest.w / self.width,
+                dest.h / self.height,
+                1.0,
+            ));
+            let transform = DrawTransform {
+                matrix: panel * part,
+                src,
+                color: param.color,
+            };
+            ctx.gfx_context.queue_quad(
+                self.image.texture.clone(),
+                self.image.sampler_info,
+                self.image.blend_mode,
+                transform,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self, _: &mut Context) -> Option<Rect> {
+        Some(Rect::new(0.0, 0.0, self.width, self.height))
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.image.blend_mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.image.blend_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lens(segments: &[Segment]) -> Vec<f32> {
+        segments.iter().map(|s| s.len).collect()
+    }
+
+    #[test]
+    fn headless_test_stretched_segments() {
+        let [start, middle, end] = segments(30.0, 100.0, 10.0, 5.0, SliceMode::Stretch);
+        assert_eq!(
+            start,
+            vec![Segment {
+                dest: 0.0,
+                len: 10.0,
+                src: 0.0,
+                src_len: 10.0
+            }]
+        );
+        assert_eq!(
+            middle,
+            vec![Segment {
+                dest: 10.0,
+                len: 85.0,
+                src: 10.0,
+                src_len: 15.0
+            }]
+        );
+        assert_eq!(
+            end,
+            vec![Segment {
+                dest: 95.0,
+                len: 5.0,
+                src: 25.0,
+                src_len: 5.0
+            }]
+        );
+    }
+
+    #[test]
+    fn headless_test_tiled_segments() {
+        let [_, middle, _] = segments(30.0, 60.0, 10.0, 10.0, SliceMode::Tile);
+        assert_eq!(lens(&middle), vec![10.0, 10.0, 10.0, 10.0]);
+        assert_eq!(middle[3].dest, 40.0);
+        let [_, middle, _] = segments(30.0, 45.0, 10.0, 10.0, SliceMode::Tile);
+        assert_eq!(lens(&middle), vec![10.0, 10.0, 5.0]);
+        assert_eq!(middle[2].src_len, 5.0);
+        assert!(middle.iter().all(|s| s.src == 10.0));
+    }
+
+    #[test]
+    fn headless_test_thin_tiled_segments() {
+        // Less than a pixel of the image is stretched rather than tiled.
+        let [_, middle, _] = segments(20.0001, 300.0, 10.0, 10.0, SliceMode::Tile);
+        assert_eq!(lens(&middle), vec![280.0]);
+    }
+
+    #[test]
+    fn headless_test_squashed_segments() {
+        // Too small for the borders: they shrink and the middle vanishes.
+        let [start, middle, end] = segments(30.0, 10.0, 10.0, 10.0, SliceMode::Tile);
+        assert!(middle.is_empty());
+        assert_eq!(lens(&start), vec![5.0]);
+        assert_eq!(start[0].src_len, 10.0);
+        assert_eq!(end[0].dest, 5.0);
+        assert_eq!(lens(&end), vec![5.0]);
+        // No borders at all.
+        let [start, middle, end] = segments(30.0, 50.0, 0.0, 0.0, SliceMode::Stretch);
+        assert!(start.is_empty() && end.is_empty());
+        assert_eq!(lens(&middle), vec![50.0]);
+    }
+}
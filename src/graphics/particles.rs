@@ -0,0 +1,629 @@
+//! Particle systems.
+//!
+//! An [`Emitter`](struct.Emitter.html) spawns particles at a steady rate
+//! and in [bursts](struct.Emitter.html#method.burst), moves them along
+//! under gravity and draws them all with one `SpriteBatch`.  How it does
+//! that is described by an [`EmitterConfig`](struct.EmitterConfig.html):
+//! how long particles live, how fast and in which direction they start out,
+//! and [`Curve`](struct.Curve.html)s for how their color, size and
+//! rotation change over their lifetime.
+//!
+//! Configs can be written in code, or loaded from TOML files with
+//! [`EmitterConfig::load()`](struct.EmitterConfig.html#method.load).  Every
+//! field is optional, for instance:
+//!
+//! ```toml
+//! rate = 200.0
+//! lifetime = { min = 0.5, max = 1.5 }
+//! speed = { min = 40.0, max = 80.0 }
+//! # Straight up, give or take 20 degrees.
+//! angle = { min = -1.92, max = -1.22 }
+//! gravity = [0.0, 100.0]
+//! blend_mode = "Add"
+//!
+//! [[color]]
+//! at = 0.0
+//! value = { r = 1.0, g = 0.9, b = 0.3, a = 1.0 }
+//!
+//! [[color]]
+//! at = 1.0
+//! value = { r = 1.0, g = 0.1, b = 0.0, a = 0.0 }
+//!
+//! [[size]]
+//! at = 0.0
+//! value = 1.0
+//!
+//! [[size]]
+//! at = 1.0
+//! value = 0.2
+//! ```
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::*;
+//! # use ggez::graphics::particles::*;
+//! # fn main() -> GameResult {
+//! # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+//! let config = EmitterConfig::load(ctx, "/fire.toml")?;
+//! let mut fire = Emitter::new(Image::new(ctx, "/spark.png")?, config);
+//! fire.position = [400.0, 500.0].into();
+//!
+//! // Every frame:
+//! fire.update(ctx);
+//! graphics::draw(ctx, &fire, DrawParam::new())?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::f32::consts::PI;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::{Deserialize, Deserializer, Error as _};
+use serde::ser::{Serialize, Serializer};
+
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::spritebatch::SpriteBatch;
+use crate::graphics::{BlendMode, Color, DrawParam, Drawable, Image, Rect, WHITE};
+use crate::timer;
+
+/// A range of values that one is picked from at random, evenly.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    /// The smallest value.
+    pub min: f32,
+    /// The largest value.
+    pub max: f32,
+}
+
+impl Range {
+    /// Creates a new `Range`.
+    pub fn new(min: f32, max: f32) -> Self {
+        Range { min, max }
+    }
+
+    /// Creates a `Range` that only contains the one value.
+    pub fn constant(value: f32) -> Self {
+        Range::new(value, value)
+    }
+
+    fn sample(self, rng: &mut Rng) -> f32 {
+        self.min + (self.max - self.min) * rng.next_f32()
+    }
+}
+
+/// A value at one point of a [`Curve`](struct.Curve.html).
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Key<T> {
+    /// How far through a particle's life the value is reached, from 0.0
+    /// when it spawns to 1.0 when it dies.
+    pub at: f32,
+    /// The value.
+    pub value: T,
+}
+
+impl<T> Key<T> {
+    /// Creates a new `Key`.
+    pub fn new(at: f32, value: T) -> Self {
+        Key { at, value }
+    }
+}
+
+/// How a value changes over a particle's lifetime, going in a straight
+/// line from one [`Key`](struct.Key.html) to the next.  Before the first
+/// key and after the last the value stays the same.
+///
+/// In TOML a curve is an array of keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T> Curve<T> {
+    /// Creates a curve through the given keys, which don't need to
+    /// be in order.  Keys at NaN are left out, since they have no place
+    /// on the curve.  A curve without any keys doesn't do anything.
+    pub fn new(mut keys: Vec<Key<T>>) -> Self {
+        keys.retain(|key| !key.at.is_nan());
+        keys.sort_by(|a, b| a.at.partial_cmp(&b.at).unwrap_or(std::cmp::Ordering::Equal));
+        Curve { keys }
+    }
+
+    /// Creates a curve that is the same value all the way.
+    pub fn constant(value: T) -> Self {
+        Curve::new(vec![Key::new(0.0, value)])
+    }
+
+    /// Creates a curve going from one value when a particle spawns to
+    /// another when it dies.
+    pub fn linear(from: T, to: T) -> Self {
+        Curve::new(vec![Key::new(0.0, from), Key::new(1.0, to)])
+    }
+
+    /// Gets the keys of the curve, in order.
+    pub fn keys(&self) -> &[Key<T>] {
+        &self.keys
+    }
+}
+
+impl Curve<f32> {
+    /// Gets the value of the curve at the given point of a particle's
+    /// life.  A curve without any keys is 0.0.
+    pub fn sample(&self, at: f32) -> f32 {
+        sample(&self.keys, at).unwrap_or(0.0)
+    }
+}
+
+impl Curve<Color> {
+    /// Gets the value of the curve at the given point of a particle's
+    /// life.  A curve without any keys is white.
+    pub fn sample(&self, at: f32) -> Color {
+        sample(&self.keys, at).unwrap_or(WHITE)
+    }
+}
+
+impl<T: Serialize> Serialize for Curve<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.keys.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Curve<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keys: Vec<Key<T>> = Vec::deserialize(deserializer)?;
+        if let Some(key) = keys.iter().find(|key| !key.at.is_finite()) {
+            return Err(D::Error::custom(format!(
+                "curve key at {}, keys have to be at a finite point",
+                key.at
+            )));
+        }
+        Ok(Curve::new(keys))
+    }
+}
+
+/// Values a [`Curve`](struct.Curve.html) can blend between.
+trait Lerp: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Color {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Color::new(
+            self.r.lerp(other.r, t),
+            self.g.lerp(other.g, t),
+            self.b.lerp(other.b, t),
+            self.a.lerp(other.a, t),
+        )
+    }
+}
+
+fn sample<T: Lerp>(keys: &[Key<T>], at: f32) -> Option<T> {
+    let first = keys.first()?;
+    let next = match keys.iter().position(|key| key.at > at) {
+        Some(0) => return Some(first.value),
+        Some(next) => next,
+        None => return keys.last().map(|key| key.value),
+    };
+    let (a, b) = (&keys[next - 1], &keys[next]);
+    Some(a.value.lerp(b.value, (at - a.at) / (b.at - a.at)))
+}
+
+/// Describes how an [`Emitter`](struct.Emitter.html) spawns particles
+/// and what happens to them.  See the [module docs](index.html) for
+/// loading one from TOML.
+///
+/// Distances are in the same units as the emitter is drawn in, times are
+/// in seconds and angles are in radians, clockwise from the right like
+/// `DrawParam::rotation`.
+#[derive(Debug, Clone, PartialEq, SmartDefault, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmitterConfig {
+    /// How many particles are spawned per second while the emitter is
+    /// emitting.
+    #[default = 50.0]
+    pub rate: f32,
+    /// The most particles there can be alive at once; no more get
+    /// spawned until some die.
+    #[default = 1000]
+    pub max_particles: usize,
+    /// The width and height of the rectangle, centered on the emitter,
+    /// that particles spawn at random points in.
+    pub area: [f32; 2],
+    /// How much the velocity of particles changes by every second.
+    pub gravity: [f32; 2],
+    /// How particles are blended with what's under them.
+    #[default(BlendMode::Alpha)]
+    pub blend_mode: BlendMode,
+    // Everything from here on is a table in TOML, which has to come
+    // after all the plain values for the config to be written out.
+    /// How long each particle lives.
+    #[default(Range::constant(1.0))]
+    pub lifetime: Range,
+    /// How fast particles start out moving.
+    #[default(Range::new(50.0, 100.0))]
+    pub speed: Range,
+    /// Which direction particles start out moving in.
+    #[default(Range::new(0.0, 2.0 * PI))]
+    pub angle: Range,
+    /// The rotation particles start out with.
+    #[default(Range::constant(0.0))]
+    pub start_rotation: Range,
+    /// How the color of particles changes, which is multiplied with the
+    /// image.
+    #[default(Curve::constant(WHITE))]
+    pub color: Curve<Color>,
+    /// How the size of particles changes, as a scale of the image.
+    #[default(Curve::constant(1.0))]
+    pub size: Curve<f32>,
+    /// How the rotation of particles changes, on top of their
+    /// starting rotation.
+    #[default(Curve::constant(0.0))]
+    pub rotation: Curve<f32>,
+}
+
+impl EmitterConfig {
+    /// Loads a config from the TOML file at the given path.
+    pub fn load<P: AsRef<Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let mut file = ctx.filesystem.open(path)?;
+        Self::from_toml_file(&mut file)
+    }
+
+    /// Reads a config from TOML.
+    pub fn from_toml_file<R: Read>(file: &mut R) -> GameResult<Self> {
+        let mut s = String::new();
+        let _ = file.read_to_string(&mut s)?;
+        Ok(toml::from_str(&s)?)
+    }
+}
+
+/// A small xorshift random number generator, which is all particles need.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    /// Seeds a new generator from the same randomness `HashMap`s use.
+    fn new() -> Self {
+        Rng::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    fn with_seed(seed: u64) -> Self {
+        // Xorshift gets stuck at zero.
+        Rng(seed | 1)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        // The top 24 bits, which is as many as an f32 can hold exactly.
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    rotation: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    /// How far through its life the particle is, from 0.0 to 1.0.
+    fn life(&self) -> f32 {
+        if self.lifetime > 0.0 {
+            self.age / self.lifetime
+        } else {
+            1.0
+        }
+    }
+}
+
+/// The particles of an emitter, without anything needed to draw them.
+#[derive(Debug, Clone)]
+struct Particles {
+    particles: Vec<Particle>,
+    /// Fractions of a particle left over from spawning at a steady rate.
+    pending: f32,
+    rng: Rng,
+}
+
+impl Particles {
+    fn new(rng: Rng) -> Self {
+        Particles {
+            particles: Vec::new(),
+            pending: 0.0,
+            rng,
+        }
+    }
+
+    fn advance(&mut self, config: &EmitterConfig, origin: [f32; 2], emitting: bool, dt: f32) {
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity[0] += config.gravity[0] * dt;
+            particle.velocity[1] += config.gravity[1] * dt;
+            particle.position[0] += particle.velocity[0] * dt;
+            particle.position[1] += particle.velocity[1] * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        if emitting {
+            self.pending += config.rate * dt;
+            let count = self.pending.floor();
+            self.pending -= count;
+            self.spawn(config, origin, count as usize);
+        } else {
+            self.pending = 0.0;
+        }
+    }
+
+    fn spawn(&mut self, config: &EmitterConfig, origin: [f32; 2], count: usize) {
+        let room = config.max_particles.saturating_sub(self.particles.len());
+        for _ in 0..count.min(room) {
+            let rng = &mut self.rng;
+            let x = origin[0] + (rng.next_f32() - 0.5) * config.area[0];
+            let y = origin[1] + (rng.next_f32() - 0.5) * config.area[1];
+            let speed = config.speed.sample(rng);
+            let angle = config.angle.sample(rng);
+            let particle = Particle {
+                position: [x, y],
+                velocity: [speed * angle.cos(), speed * angle.sin()],
+                rotation: config.start_rotation.sample(rng),
+                age: 0.0,
+                lifetime: config.lifetime.sample(rng),
+            };
+            self.particles.push(particle);
+        }
+    }
+
+    fn param(config: &EmitterConfig, particle: &Particle) -> DrawParam {
+        let life = particle.life();
+        let size = config.size.sample(life);
+        DrawParam::new()
+            .dest(particle.position)
+            .offset([0.5, 0.5])
+            .scale([size, size])
+            .rotation(particle.rotation + config.rotation.sample(life))
+            .color(config.color.sample(life))
+    }
+}
+
+/// Spawns, moves and draws particles, see the [module docs](index.html).
+///
+/// Particles are spawned at wherever the emitter is at the time and then
+/// move on their own, so moving the emitter leaves a trail.  Drawing the
+/// emitter with a `DrawParam` transforms all the particles together, like
+/// drawing a `SpriteBatch`.
+#[derive(Debug)]
+pub struct Emitter {
+    /// How particles are spawned and behave.  Changes apply from the
+    /// next update on, including to particles that are already alive.
+    pub config: EmitterConfig,
+    /// Where new particles spawn.
+    pub position: mint::Point2<f32>,
+    /// Whether particles are spawned at `config.rate`.  Bursts happen
+    /// either way.
+    pub emitting: bool,
+    particles: Particles,
+    batch: SpriteBatch,
+}
+
+impl Emitter {
+    /// Creates a new emitter at the origin that draws each particle
+    /// with the given image, centered on the particle.
+    pub fn new(image: Image, config: EmitterConfig) -> Self {
+        let mut batch = SpriteBatch::new(image);
+        batch.set_blend_mode(Some(config.blend_mode));
+        Emitter {
+            config,
+            position: mint::Point2 { x: 0.0, y: 0.0 },
+            emitting: true,
+            particles: Particles::new(Rng::new()),
+            batch,
+        }
+    }
+
+    /// Advances the particles by the length of the last frame, as
+    /// returned by [`timer::delta()`](../../timer/fn.delta.html).
+    pub fn update(&mut self, ctx: &Context) {
+        self.advance(timer::delta(ctx));
+    }
+
+    /// Advances the particles by the given amount of time, spawning
+    /// new ones if the emitter is emitting.
+    pub fn advance(&mut self, dt: Duration) {
+        let dt = timer::duration_to_f64(dt) as f32;
+        let origin = [self.position.x, self.position.y];
+        self.particles
+            .advance(&self.config, origin, self.emitting, dt);
+        self.rebuild();
+    }
+
+    /// Spawns the given number of particles at once, as long as there
+    /// is room for them under `config.max_particles`.
+    pub fn burst(&mut self, count: usize) {
+        let origin = [self.position.x, self.position.y];
+        self.particles.spawn(&self.config, origin, count);
+        self.rebuild();
+    }
+
+    /// Removes all particles.
+    pub fn clear(&mut self) {
+        self.particles.particles.clear();
+        self.particles.pending = 0.0;
+        self.batch.clear();
+    }
+
+    /// Returns how many particles are alive.
+    pub fn particle_count(&self) -> usize {
+        self.particles.particles.len()
+    }
+
+    /// Puts the particles into the sprite batch, which is redone
+    /// every time since they all move anyway.
+    fn rebuild(&mut self) {
+        self.batch.clear();
+        for particle in &self.particles.particles {
+            let _ = self.batch.add(Particles::param(&self.config, particle));
+        }
+        self.batch.set_blend_mode(Some(self.config.blend_mode));
+    }
+}
+
+impl Drawable for Emitter {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        self.batch.draw(ctx, param)
+    }
+
+    fn dimensions(&self, ctx: &mut Context) -> Option<Rect> {
+        self.batch.dimensions(ctx)
+    }
+
+    /// Sets `config.blend_mode`; `None` goes back to `BlendMode::Alpha`.
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.config.blend_mode = mode.unwrap_or(BlendMode::Alpha);
+        self.batch.set_blend_mode(Some(self.config.blend_mode));
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        Some(self.config.blend_mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32;
+
+    fn particles() -> Particles {
+        Particles::new(Rng::with_seed(12345))
+    }
+
+    #[test]
+    fn headless_test_curve_sample() {
+        let curve = Curve::new(vec![
+            Key::new(1.0, 0.0),
+            Key::new(0.0, 10.0),
+            Key::new(0.5, 20.0),
+        ]);
+        assert_eq!(curve.keys()[1].at, 0.5);
+        assert_eq!(curve.sample(-1.0), 10.0);
+        assert_eq!(curve.sample(0.25), 15.0);
+        assert_eq!(curve.sample(0.75), 10.0);
+        assert_eq!(curve.sample(2.0), 0.0);
+        assert_eq!(Curve::<f32>::new(vec![]).sample(0.5), 0.0);
+
+        let skipped = Curve::new(vec![Key::new(f32::NAN, 5.0), Key::new(0.0, 1.0)]);
+        assert_eq!(skipped, Curve::constant(1.0));
+
+        let fade = Curve::linear(WHITE, Color::new(1.0, 1.0, 1.0, 0.0));
+        assert_eq!(fade.sample(0.5), Color::new(1.0, 1.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn headless_test_spawn_rate() {
+        let config = EmitterConfig {
+            rate: 10.0,
+            lifetime: Range::constant(10.0),
+            ..EmitterConfig::default()
+        };
+        let mut particles = particles();
+        // Leftover fractions of a particle carry over to the next update.
+        for _ in 0..4 {
+            particles.advance(&config, [0.0, 0.0], true, 0.25);
+        }
+        assert_eq!(particles.particles.len(), 10);
+        particles.advance(&config, [0.0, 0.0], false, 1.0);
+        assert_eq!(particles.particles.len(), 10);
+    }
+
+    #[test]
+    fn headless_test_lifetime_and_limit() {
+        let config = EmitterConfig {
+            max_particles: 5,
+            lifetime: Range::constant(1.0),
+            ..EmitterConfig::default()
+        };
+        let mut particles = particles();
+        particles.spawn(&config, [0.0, 0.0], 8);
+        assert_eq!(particles.particles.len(), 5);
+        particles.advance(&config, [0.0, 0.0], false, 0.5);
+        assert_eq!(particles.particles.len(), 5);
+        particles.advance(&config, [0.0, 0.0], false, 0.5);
+        assert!(particles.particles.is_empty());
+    }
+
+    #[test]
+    fn headless_test_motion() {
+        let config = EmitterConfig {
+            speed: Range::constant(10.0),
+            angle: Range::constant(0.0),
+            gravity: [0.0, 4.0],
+            lifetime: Range::constant(10.0),
+            ..EmitterConfig::default()
+        };
+        let mut particles = particles();
+        particles.spawn(&config, [100.0, 50.0], 1);
+        particles.advance(&config, [0.0, 0.0], false, 1.0);
+        let particle = particles.particles[0];
+        assert_eq!(particle.velocity, [10.0, 4.0]);
+        assert_eq!(particle.position, [110.0, 54.0]);
+        assert_eq!(particle.life(), 0.1);
+    }
+
+    #[test]
+    fn headless_test_config_from_toml() {
+        let toml = r#"
+            rate = 200.0
+            angle = { min = -1.0, max = 1.0 }
+            blend_mode = "Add"
+
+            [[size]]
+            at = 1.0
+            value = 0.0
+
+            [[size]]
+            at = 0.0
+            value = 2.0
+        "#;
+        let config = EmitterConfig::from_toml_file(&mut toml.as_bytes()).unwrap();
+        assert_eq!(config.rate, 200.0);
+        assert_eq!(config.angle, Range::new(-1.0, 1.0));
+        assert_eq!(config.blend_mode, BlendMode::Add);
+        assert_eq!(config.size, Curve::linear(2.0, 0.0));
+        assert_eq!(config.lifetime, EmitterConfig::default().lifetime);
+        assert_eq!(config.color, Curve::constant(WHITE));
+
+        let written = toml::to_string(&config).unwrap();
+        let read = EmitterConfig::from_toml_file(&mut written.as_bytes()).unwrap();
+        assert_eq!(read, config);
+
+        for at in &["nan", "inf", "-inf"] {
+            let toml = format!("[[size]]\nat = {}\nvalue = 1.0\n", at);
+            match EmitterConfig::from_toml_file(&mut toml.as_bytes()) {
+                Err(crate::GameError::ConfigError(_)) => (),
+                other => panic!("Expected a config error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn headless_test_rng_range() {
+        let mut rng = Rng::with_seed(0);
+        for _ in 0..1000 {
+            let x = Range::new(-2.0, 3.0).sample(&mut rng);
+            assert!((-2.0..3.0).contains(&x));
+        }
+    }
+}
@@ -0,0 +1,726 @@
+//! Post-processing: running the finished frame, or any
+//! [`Canvas`](../type.Canvas.html), through a chain of full-screen
+//! shader passes.
+//!
+//! Anything implementing [`Effect`](trait.Effect.html) can be part of
+//! the chain, which includes any [`Shader`](../type.Shader.html), and
+//! there are built-in effects for the usual suspects: [`Blur`](struct.Blur.html),
+//! [`Bloom`](struct.Bloom.html), [`Crt`](struct.Crt.html),
+//! [`Vignette`](struct.Vignette.html) and [`ColorGrade`](struct.ColorGrade.html).
+//! The effects are kept by the game rather than by the
+//! [`PostProcess`](struct.PostProcess.html), so their settings can be
+//! changed from frame to frame, and the chain takes care of the canvases
+//! that each effect's result gets passed on in.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::*;
+//! # use ggez::graphics::postprocess::*;
+//! # fn main() -> GameResult {
+//! # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+//! let mut post = PostProcess::new(CanvasFormat::Rgba16Float);
+//! let mut bloom = Bloom::new(ctx)?;
+//! let mut vignette = Vignette::new(ctx)?;
+//!
+//! // Every frame:
+//! post.begin_frame(ctx)?;
+//! clear(ctx, BLACK);
+//! // ...draw everything as usual...
+//! vignette.radius = 1.1;
+//! post.end_frame(ctx, &mut [&mut bloom, &mut vignette])?;
+//! present(ctx)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::mem;
+
+use gfx::pso::buffer::Structure;
+use gfx::shade::ConstFormat;
+use gfx::traits::Pod;
+
+use crate::conf::NumSamples;
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::*;
+
+mod consts {
+    gfx_defines! {
+        constant Blur {
+            step: [f32; 2] = "u_Step",
+        }
+
+        constant BloomThreshold {
+            threshold: f32 = "u_Threshold",
+        }
+
+        constant Bloom {
+            intensity: f32 = "u_Intensity",
+        }
+
+        constant Crt {
+            curvature: f32 = "u_Curvature",
+            scanline_count: f32 = "u_ScanlineCount",
+            scanline_intensity: f32 = "u_ScanlineIntensity",
+        }
+
+        constant Vignette {
+            color: [f32; 4] = "u_Color",
+            radius: f32 = "u_Radius",
+            softness: f32 = "u_Softness",
+        }
+
+        constant ColorGrade {
+            size: f32 = "u_Size",
+            intensity: f32 = "u_Intensity",
+            srgb: f32 = "u_Srgb",
+        }
+    }
+}
+
+/// Something that can be part of a [`PostProcess`](struct.PostProcess.html)
+/// chain.
+pub trait Effect: fmt::Debug {
+    /// Draws `input` with the effect applied, covering the whole of
+    /// `output`, or of the screen if `output` is `None`.
+    ///
+    /// Most effects are a single [`draw_pass()`](fn.draw_pass.html);
+    /// ones that need more can draw to canvases of their own in between.
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult;
+}
+
+/// A shader is an effect that draws its input once with the shader.
+/// It has to be created with `BlendMode::Replace` among its blend modes.
+impl<C> Effect for Shader<C>
+where
+    C: 'static + Pod + Structure<ConstFormat> + Clone + Copy,
+{
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+        pass(ctx, input, output, Some(self.shader_id()))
+    }
+}
+
+/// Runs the frame, or any canvas, through a chain of
+/// [`Effect`](trait.Effect.html)s.  See the [module docs](index.html).
+#[derive(Debug)]
+pub struct PostProcess {
+    format: CanvasFormat,
+    frame: Option<Canvas>,
+    buffers: Scratch,
+}
+
+impl PostProcess {
+    /// Creates a new `PostProcess` which draws the frame to a canvas
+    /// in the given format.  `CanvasFormat::Rgba16Float` keeps colors
+    /// brighter than white around for effects like `Bloom`.
+    pub fn new(format: CanvasFormat) -> Self {
+        Self {
+            format,
+            frame: None,
+            buffers: Scratch::default(),
+        }
+    }
+
+    /// Gets the format of the canvas the frame is drawn to.
+    pub fn format(&self) -> CanvasFormat {
+        self.format
+    }
+
+    /// Makes everything drawn from here on go to a canvas the size of
+    /// the screen instead, for [`end_frame()`](#method.end_frame) to
+    /// process.  The canvas is kept from frame to frame, so it has to be
+    /// cleared like the screen would be.
+    pub fn begin_frame(&mut self, ctx: &mut Context) -> GameResult {
+        let (width, height, _, _) = ctx.gfx_context.default_render_target().0.get_dimensions();
+        let samples = NumSamples::from_u32(u32::from(ctx.gfx_context.multisample_samples))
+            .unwrap_or(NumSamples::One);
+        let fits = match self.frame {
+            Some(ref frame) => {
+                frame.image().width() == width
+                    && frame.image().height() == height
+                    && frame.format() == self.format
+            }
+            None => false,
+        };
+        if !fits {
+            let frame = CanvasBuilder::new(width, height)
+                .samples(samples)
+                .format(self.format)
+                .build(ctx)?;
+            self.frame = Some(frame);
+        }
+        set_canvas(ctx, self.frame.as_ref());
+        Ok(())
+    }
+
+    /// Draws the frame started with [`begin_frame()`](#method.begin_frame)
+    /// to the screen through the given effects, in order.
+    pub fn end_frame(&mut self, ctx: &mut Context, effects: &mut [&mut dyn Effect]) -> GameResult {
+        let frame = match self.frame.take() {
+            Some(frame) => frame,
+            None => {
+                return Err(GameError::RenderError(
+                    "PostProcess::end_frame() called without begin_frame()".to_owned(),
+                ));
+            }
+        };
+        set_canvas(ctx, None);
+        let result = self.apply(ctx, &frame, None, effects);
+        self.frame = Some(frame);
+        result
+    }
+
+    /// Draws `input` through the given effects, in order, to `output`,
+    /// or to the screen if that is `None`.  The effects in between draw
+    /// to canvases in the same format as `input`.
+    ///
+    /// Afterwards drawing goes to `output`.
+    pub fn apply(
+        &mut self,
+        ctx: &mut Context,
+        input: &Canvas,
+        output: Option<&Canvas>,
+        effects: &mut [&mut dyn Effect],
+    ) -> GameResult {
+        let last = match effects.len() {
+            0 => return pass(ctx, input, output, None),
+            n => n - 1,
+        };
+        let (width, height) = (input.image().width(), input.image().height());
+        let buffers = self
+            .buffers
+            .get(ctx, last.min(2), width, height, input.format())?;
+        let mut source = input;
+        for (i, effect) in effects.iter_mut().enumerate() {
+            if i == last {
+                effect.apply(ctx, source, output)?;
+            } else {
+                let target = &buffers[i % 2];
+                effect.apply(ctx, source, Some(target))?;
+                source = target;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws `input` stretched over the whole of `output`, or of the screen
+/// if `output` is `None`, with the current shader, replacing whatever
+/// was there.  This is the building block of most effects.
+///
+/// The current transform, scissor rectangle and stencil test are all
+/// ignored, and afterwards drawing goes to `output`.  The shader has to
+/// be created with `BlendMode::Replace` among its blend modes.
+pub fn draw_pass(ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+    let shader = *ctx.gfx_context.current_shader.borrow();
+    pass(ctx, input, output, shader)
+}
+
+/// `draw_pass()` with the given shader, or the default one for `None`.
+pub(crate) fn pass(
+    ctx: &mut Context,
+    input: &Canvas,
+    output: Option<&Canvas>,
+    shader: Option<ShaderId>,
+) -> GameResult {
+    draw_in_pixels(ctx, output, shader, |ctx, (w, h)| {
+        let mut image = input.image().clone();
+        image.set_blend_mode(Some(BlendMode::Replace));
+        // Canvases are upside down, see `Canvas::draw()`.
+        let scale = [w / f32::from(image.width()), -h / f32::from(image.height())];
+        image.draw(ctx, DrawParam::new().dest([0.0, h]).scale(scale))
+    })
+}
+
+/// Switches drawing to `output`, or the screen for `None`, and calls
+/// `draw` with its size in pixels.  While `draw` runs everything is drawn
+/// in those pixels with the given shader, whatever the game has set up
+/// for itself, without a scissor rectangle or stencil test.
+pub(crate) fn draw_in_pixels<F>(
+    ctx: &mut Context,
+    output: Option<&Canvas>,
+    shader: Option<ShaderId>,
+    draw: F,
+) -> GameResult
+where
+    F: FnOnce(&mut Context, (f32, f32)) -> GameResult,
+{
+    set_canvas(ctx, output);
+    let (w, h, _, _) = ctx.gfx_context.data.out.get_dimensions();
+    let (w, h) = (f32::from(w), f32::from(h));
+    let gfx = &mut ctx.gfx_context;
+
+    let projection = gfx.projection();
+    let screen_rect = gfx.screen_rect;
+    let scissor = gfx.scissor.take();
+    let stencil = mem::replace(&mut gfx.stencil, (StencilMode::Off, 0));
+    let previous_shader = mem::replace(&mut *gfx.current_shader.borrow_mut(), shader);
+    gfx.set_projection_rect(Rect::new(0.0, 0.0, w, h));
+    gfx.push_transform(Matrix4::identity());
+    gfx.calculate_transform_matrix();
+
+    let result = gfx
+        .update_globals()
+        .and_then(|_| draw(ctx, (w, h)))
+        .and_then(|_| ctx.gfx_context.flush_batch());
+
+    let gfx = &mut ctx.gfx_context;
+    *gfx.current_shader.borrow_mut() = previous_shader;
+    gfx.stencil = stencil;
+    gfx.scissor = scissor;
+    gfx.pop_transform();
+    gfx.set_projection(projection);
+    gfx.screen_rect = screen_rect;
+    gfx.calculate_transform_matrix();
+    result.and_then(|_| gfx.update_globals())
+}
+
+/// Turns a color into what a shader works with, which is a linear
+/// color if the screen is sRGB, same as `DrawParam::color`.
+pub(crate) fn shader_color(ctx: &Context, color: Color) -> [f32; 4] {
+    if ctx.gfx_context.is_srgb() {
+        LinearColor::from(color).into()
+    } else {
+        color.into()
+    }
+}
+
+/// Canvases for effects to draw their intermediate results to, which
+/// get replaced whenever they are needed in another size or format.
+#[derive(Debug, Default)]
+struct Scratch {
+    canvases: Vec<Canvas>,
+}
+
+impl Scratch {
+    fn get(
+        &mut self,
+        ctx: &mut Context,
+        count: usize,
+        width: u16,
+        height: u16,
+        format: CanvasFormat,
+    ) -> GameResult<&[Canvas]> {
+        let fits = |canvas: &Canvas| {
+            canvas.image().width() == width
+                && canvas.image().height() == height
+                && canvas.format() == format
+        };
+        if !self.canvases.iter().all(fits) {
+            self.canvases.clear();
+        }
+        while self.canvases.len() < count {
+            let mut canvas = CanvasBuilder::new(width, height)
+                .format(format)
+                .depth_stencil(false)
+                .build(ctx)?;
+            // Effects that scale or bend the picture want it smooth.
+            canvas.set_filter(FilterMode::Linear);
+            self.canvases.push(canvas);
+        }
+        Ok(&self.canvases[..count])
+    }
+}
+
+/// Creates the shader for a built-in effect, picking the GLSL or GLSL ES
+/// source depending on the backend.  It draws with the given blend mode,
+/// which is `Replace` for passes.
+pub(crate) fn effect_shader<C>(
+    ctx: &mut Context,
+    name: &str,
+    gl_source: &[u8],
+    gles_source: &[u8],
+    consts: C,
+    blend_mode: BlendMode,
+) -> GameResult<Shader<C>>
+where
+    C: 'static + Pod + Structure<ConstFormat> + Clone + Copy,
+{
+    let backend = &ctx.gfx_context.backend_spec;
+    let (vertex_source, _) = backend.shaders();
+    let pixel_source = match backend.api() {
+        glutin::Api::OpenGlEs => gles_source,
+        _ => gl_source,
+    };
+    Shader::from_u8(
+        ctx,
+        vertex_source,
+        pixel_source,
+        consts,
+        name,
+        Some(&[blend_mode]),
+    )
+}
+
+/// Draws `input` blurred to `output`, once across into `scratch` and
+/// once down from there.
+fn blur(
+    ctx: &mut Context,
+    shader: &Shader<consts::Blur>,
+    radius: f32,
+    input: &Canvas,
+    scratch: &Canvas,
+    output: Option<&Canvas>,
+) -> GameResult {
+    // The kernel reaches four steps out to either side.
+    let step = radius / 4.0;
+    let width = f32::from(input.image().width());
+    let height = f32::from(scratch.image().height());
+    shader.send(
+        ctx,
+        consts::Blur {
+            step: [step / width, 0.0],
+        },
+    )?;
+    pass(ctx, input, Some(scratch), Some(shader.shader_id()))?;
+    shader.send(
+        ctx,
+        consts::Blur {
+            step: [0.0, step / height],
+        },
+    )?;
+    pass(ctx, scratch, output, Some(shader.shader_id()))
+}
+
+/// A gaussian blur.
+#[derive(Debug)]
+pub struct Blur {
+    /// How far the blur reaches, in pixels.
+    pub radius: f32,
+    shader: Shader<consts::Blur>,
+    scratch: Scratch,
+}
+
+impl Blur {
+    /// Creates a new blur that reaches the given number of pixels.
+    pub fn new(ctx: &mut Context, radius: f32) -> GameResult<Self> {
+        let shader = effect_shader(
+            ctx,
+            "Blur",
+            include_bytes!("shader/blur_150.glslf"),
+            include_bytes!("shader/blur_es300.glslf"),
+            consts::Blur { step: [0.0, 0.0] },
+            BlendMode::Replace,
+        )?;
+        Ok(Self {
+            radius,
+            shader,
+            scratch: Scratch::default(),
+        })
+    }
+}
+
+impl Effect for Blur {
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+        let (width, height) = (input.image().width(), input.image().height());
+        let scratch = self.scratch.get(ctx, 1, width, height, input.format())?;
+        blur(ctx, &self.shader, self.radius, input, &scratch[0], output)
+    }
+}
+
+/// Makes bright parts of the picture glow, by blurring everything
+/// brighter than a threshold and adding it back on top.
+///
+/// This works best on a `CanvasFormat::Rgba16Float` input, where
+/// things can be brighter than white.
+#[derive(Debug)]
+pub struct Bloom {
+    /// How bright something has to be to glow, where 1.0 is white.
+    pub threshold: f32,
+    /// How strong the glow is.
+    pub intensity: f32,
+    /// How far the glow reaches, in pixels of the half-size
+    /// canvases the glow is made in.
+    pub radius: f32,
+    threshold_shader: Shader<consts::BloomThreshold>,
+    blur_shader: Shader<consts::Blur>,
+    bloom_shader: Shader<consts::Bloom>,
+    scratch: Scratch,
+}
+
+impl Bloom {
+    /// Creates a new bloom with a threshold of 0.8, an intensity of 1.0
+    /// and a radius of 4 pixels.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let threshold_shader = effect_shader(
+            ctx,
+            "BloomThreshold",
+            include_bytes!("shader/bloom_threshold_150.glslf"),
+            include_bytes!("shader/bloom_threshold_es300.glslf"),
+            consts::BloomThreshold { threshold: 0.8 },
+            BlendMode::Replace,
+        )?;
+        let blur_shader = effect_shader(
+            ctx,
+            "Blur",
+            include_bytes!("shader/blur_150.glslf"),
+            include_bytes!("shader/blur_es300.glslf"),
+            consts::Blur { step: [0.0, 0.0] },
+            BlendMode::Replace,
+        )?;
+        let bloom_shader = effect_shader(
+            ctx,
+            "Bloom",
+            include_bytes!("shader/bloom_150.glslf"),
+            include_bytes!("shader/bloom_es300.glslf"),
+            consts::Bloom { intensity: 1.0 },
+            BlendMode::Replace,
+        )?;
+        Ok(Self {
+            threshold: 0.8,
+            intensity: 1.0,
+            radius: 4.0,
+            threshold_shader,
+            blur_shader,
+            bloom_shader,
+            scratch: Scratch::default(),
+        })
+    }
+}
+
+impl Effect for Bloom {
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+        let width = (input.image().width() / 2).max(1);
+        let height = (input.image().height() / 2).max(1);
+        let scratch = self.scratch.get(ctx, 2, width, height, input.format())?;
+        let (glow, blurred) = (&scratch[0], &scratch[1]);
+
+        self.threshold_shader.send(
+            ctx,
+            consts::BloomThreshold {
+                threshold: self.threshold,
+            },
+        )?;
+        pass(
+            ctx,
+            input,
+            Some(glow),
+            Some(self.threshold_shader.shader_id()),
+        )?;
+        blur(
+            ctx,
+            &self.blur_shader,
+            self.radius,
+            glow,
+            blurred,
+            Some(glow),
+        )?;
+
+        self.bloom_shader.send(
+            ctx,
+            consts::Bloom {
+                intensity: self.intensity,
+            },
+        )?;
+        self.bloom_shader.send_image(ctx, glow.image())?;
+        pass(ctx, input, output, Some(self.bloom_shader.shader_id()))
+    }
+}
+
+/// Makes the picture look like it is on an old CRT screen, with
+/// scanlines and bulging glass.
+#[derive(Debug)]
+pub struct Crt {
+    /// How much the picture bulges out; 0.0 keeps it flat.
+    pub curvature: f32,
+    /// How many scanlines there are from top to bottom.
+    pub scanline_count: f32,
+    /// How dark the scanlines are, from 0.0 to 1.0.
+    pub scanline_intensity: f32,
+    shader: Shader<consts::Crt>,
+}
+
+impl Crt {
+    /// Creates a new CRT effect with a curvature of 0.1 and 240
+    /// scanlines at an intensity of 0.3.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let shader = effect_shader(
+            ctx,
+            "Crt",
+            include_bytes!("shader/crt_150.glslf"),
+            include_bytes!("shader/crt_es300.glslf"),
+            consts::Crt {
+                curvature: 0.1,
+                scanline_count: 240.0,
+                scanline_intensity: 0.3,
+            },
+            BlendMode::Replace,
+        )?;
+        Ok(Self {
+            curvature: 0.1,
+            scanline_count: 240.0,
+            scanline_intensity: 0.3,
+            shader,
+        })
+    }
+}
+
+impl Effect for Crt {
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+        let consts = consts::Crt {
+            curvature: self.curvature,
+            scanline_count: self.scanline_count,
+            scanline_intensity: self.scanline_intensity,
+        };
+        self.shader.send(ctx, consts)?;
+        pass(ctx, input, output, Some(self.shader.shader_id()))
+    }
+}
+
+/// Fades the edges of the picture into a color.
+#[derive(Debug)]
+pub struct Vignette {
+    /// The color the edges fade into.  Its alpha is how far they fade.
+    pub color: Color,
+    /// How far out from the center the fade ends, where 1.0 is the
+    /// middle of the edges and about 1.41 the corners.
+    pub radius: f32,
+    /// How far in from `radius` the fade starts.
+    pub softness: f32,
+    shader: Shader<consts::Vignette>,
+}
+
+impl Vignette {
+    /// Creates a new vignette that fades to black, with a radius of 1.2
+    /// and a softness of 0.8.
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let shader = effect_shader(
+            ctx,
+            "Vignette",
+            include_bytes!("shader/vignette_150.glslf"),
+            include_bytes!("shader/vignette_es300.glslf"),
+            consts::Vignette {
+                color: BLACK.into(),
+                radius: 1.2,
+                softness: 0.8,
+            },
+            BlendMode::Replace,
+        )?;
+        Ok(Self {
+            color: BLACK,
+            radius: 1.2,
+            softness: 0.8,
+            shader,
+        })
+    }
+}
+
+impl Effect for Vignette {
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+        let consts = consts::Vignette {
+            color: shader_color(ctx, self.color),
+            radius: self.radius,
+            softness: self.softness,
+        };
+        self.shader.send(ctx, consts)?;
+        pass(ctx, input, output, Some(self.shader.shader_id()))
+    }
+}
+
+/// Changes the colors of the picture with a lookup table, which is
+/// the usual way of color grading: take a screenshot with the
+/// [`neutral_lut()`](#method.neutral_lut) pasted into it, adjust its
+/// colors in an image editor, then cut the lookup table back out and
+/// use that.
+///
+/// A lookup table of size `n` is an image of `n * n` by `n` pixels, made
+/// of `n` squares side by side.  Red goes from left to right within each
+/// square, green from top to bottom and blue from the first square to
+/// the last.
+#[derive(Debug)]
+pub struct ColorGrade {
+    /// How much of the graded colors to use, from 0.0 for none to 1.0.
+    pub intensity: f32,
+    lut: Image,
+    shader: Shader<consts::ColorGrade>,
+}
+
+impl ColorGrade {
+    /// Creates a new color grading effect from the given lookup table.
+    pub fn new(ctx: &mut Context, mut lut: Image) -> GameResult<Self> {
+        let size = lut.height();
+        if size < 2 || u32::from(lut.width()) != u32::from(size) * u32::from(size) {
+            return Err(GameError::RenderError(format!(
+                "A color grading lookup table has to be n * n by n pixels, not {} by {}",
+                lut.width(),
+                lut.height()
+            )));
+        }
+        lut.set_filter(FilterMode::Linear);
+        let shader = effect_shader(
+            ctx,
+            "ColorGrade",
+            include_bytes!("shader/color_grade_150.glslf"),
+            include_bytes!("shader/color_grade_es300.glslf"),
+            consts::ColorGrade {
+                size: f32::from(size),
+                intensity: 1.0,
+                srgb: 0.0,
+            },
+            BlendMode::Replace,
+        )?;
+        Ok(Self {
+            intensity: 1.0,
+            lut,
+            shader,
+        })
+    }
+
+    /// Returns the RGBA pixels of a lookup table that leaves all colors
+    /// as they are, `size * size` by `size` pixels big.
+    pub fn neutral_lut(size: u8) -> Vec<u8> {
+        let size = usize::from(size.max(2));
+        let last = (size - 1) as f32;
+        let level = |i: usize| (i as f32 / last * 255.0).round() as u8;
+        let mut rgba = Vec::with_capacity(size * size * size * 4);
+        for green in 0..size {
+            for blue in 0..size {
+                for red in 0..size {
+                    rgba.extend_from_slice(&[level(red), level(green), level(blue), 255]);
+                }
+            }
+        }
+        rgba
+    }
+
+    /// Gets the lookup table.
+    pub fn lut(&self) -> &Image {
+        &self.lut
+    }
+}
+
+impl Effect for ColorGrade {
+    fn apply(&mut self, ctx: &mut Context, input: &Canvas, output: Option<&Canvas>) -> GameResult {
+        let consts = consts::ColorGrade {
+            size: f32::from(self.lut.height()),
+            intensity: self.intensity,
+            srgb: if ctx.gfx_context.is_srgb() { 1.0 } else { 0.0 },
+        };
+        self.shader.send(ctx, consts)?;
+        self.shader.send_image(ctx, &self.lut)?;
+        pass(ctx, input, output, Some(self.shader.shader_id()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_test_neutral_lut() {
+        let size = 4;
+        let lut = ColorGrade::neutral_lut(size as u8);
+        assert_eq!(lut.len(), size * size * size * 4);
+        let pixel = |x: usize, y: usize| {
+            let i = (y * size * size + x) * 4;
+            &lut[i..i + 4]
+        };
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 255]);
+        // Red within a square, blue across squares, green downwards.
+        assert_eq!(pixel(3, 0), &[255, 0, 0, 255]);
+        assert_eq!(pixel(4, 0), &[0, 0, 85, 255]);
+        assert_eq!(pixel(size * size - 1, 1), &[255, 85, 255, 255]);
+        assert_eq!(pixel(5, 2), &[85, 170, 85, 255]);
+    }
+}
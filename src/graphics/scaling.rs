@@ -0,0 +1,320 @@
+//! Rendering at a fixed virtual resolution that gets scaled to fit the
+//! window, however big it is.
+
+use crate::conf;
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::*;
+
+/// How the virtual screen is fit into the window.
+/// See [`set_virtual_resolution()`](fn.set_virtual_resolution.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ScalingMode {
+    /// Stretches the virtual screen over the whole window, even if that
+    /// means its aspect ratio changes.
+    Stretch,
+    /// Scales the virtual screen as far as it fits into the window
+    /// while keeping its aspect ratio, and fills the rest of the window
+    /// with bars of the given color, at the top and bottom or on either side.
+    Letterbox(Color),
+    /// Like `Letterbox`, but only scales by whole numbers, so that every
+    /// pixel of the virtual screen covers exactly the same number of pixels
+    /// of the window, and without any filtering.  This is what you want
+    /// for pixel art.  If the window is smaller than the virtual screen,
+    /// it gets scaled down like `Letterbox` does.
+    PixelPerfect(Color),
+}
+
+/// A fixed resolution to render the game at, and how to scale it to fit
+/// the window.  See [`set_virtual_resolution()`](fn.set_virtual_resolution.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VirtualResolution {
+    /// The width of the virtual screen, in pixels.
+    pub width: u16,
+    /// The height of the virtual screen, in pixels.
+    pub height: u16,
+    /// How the virtual screen is fit into the window.
+    pub mode: ScalingMode,
+}
+
+impl VirtualResolution {
+    /// Creates a new `VirtualResolution`.
+    pub fn new(width: u16, height: u16, mode: ScalingMode) -> Self {
+        VirtualResolution {
+            width,
+            height,
+            mode,
+        }
+    }
+}
+
+/// The canvas everything gets drawn on while a virtual resolution is set.
+#[derive(Debug)]
+pub(crate) struct VirtualScreen<B>
+where
+    B: BackendSpec,
+{
+    pub(crate) settings: VirtualResolution,
+    pub(crate) canvas: CanvasGeneric<B>,
+}
+
+impl<B> VirtualScreen<B>
+where
+    B: BackendSpec,
+{
+    /// Returns where the virtual screen ends up in the window,
+    /// in logical pixels.
+    pub(crate) fn viewport(&self, window: &glutin::WindowedContext, hidpi_factor: f32) -> Rect {
+        let size = window
+            .get_inner_size()
+            .map(|size| (size.width as f32, size.height as f32))
+            .unwrap_or((0.0, 0.0));
+        fit(self.settings, size, hidpi_factor)
+    }
+
+    /// Converts a position in the window, in logical pixels,
+    /// into one on the virtual screen.
+    pub(crate) fn window_to_virtual(
+        &self,
+        window: &glutin::WindowedContext,
+        hidpi_factor: f32,
+        point: Point2,
+    ) -> Point2 {
+        let viewport = self.viewport(window, hidpi_factor);
+        if viewport.w <= 0.0 || viewport.h <= 0.0 {
+            return point;
+        }
+        Point2::new(
+            (point.x - viewport.x) * f32::from(self.settings.width) / viewport.w,
+            (point.y - viewport.y) * f32::from(self.settings.height) / viewport.h,
+        )
+    }
+
+    /// Converts a position on the virtual screen into one in the
+    /// window, in logical pixels.
+    pub(crate) fn virtual_to_window(
+        &self,
+        window: &glutin::WindowedContext,
+        hidpi_factor: f32,
+        point: Point2,
+    ) -> Point2 {
+        let viewport = self.viewport(window, hidpi_factor);
+        Point2::new(
+            viewport.x + point.x * viewport.w / f32::from(self.settings.width),
+            viewport.y + point.y * viewport.h / f32::from(self.settings.height),
+        )
+    }
+}
+
+/// Works out where a virtual screen goes in a window of the given size.
+///
+/// Everything is lined up with physical pixels, which matters for
+/// `PixelPerfect` on high DPI displays, then converted back to the
+/// logical pixels everything else uses.
+fn fit(settings: VirtualResolution, (width, height): (f32, f32), hidpi_factor: f32) -> Rect {
+    let (w, h) = (width * hidpi_factor, height * hidpi_factor);
+    let (vw, vh) = (f32::from(settings.width), f32::from(settings.height));
+    let fitting_scale = (w / vw).min(h / vh);
+    let scale = match settings.mode {
+        ScalingMode::Stretch => return Rect::new(0.0, 0.0, width, height),
+        ScalingMode::Letterbox(_) => fitting_scale,
+        ScalingMode::PixelPerfect(_) if fitting_scale >= 1.0 => fitting_scale.floor(),
+        ScalingMode::PixelPerfect(_) => fitting_scale,
+    };
+    let (sw, sh) = (vw * scale, vh * scale);
+    Rect::new(
+        ((w - sw) / 2.0).floor() / hidpi_factor,
+        ((h - sh) / 2.0).floor() / hidpi_factor,
+        sw / hidpi_factor,
+        sh / hidpi_factor,
+    )
+}
+
+/// Makes the game render at a fixed resolution, which ggez scales to fit
+/// the window when presenting it, or with `None`, goes back to rendering
+/// straight to the window.
+///
+/// Everything that would be drawn to the screen is drawn to a `Canvas` of
+/// the given size instead, and the screen coordinates are set to cover it
+/// exactly, with (0, 0) at the top left and (width, height) at the bottom
+/// right, no matter how the window is resized.  Mouse positions, both from
+/// [`mouse::position()`](../input/mouse/fn.position.html) and in mouse
+/// events, are converted to the same coordinates.  [`resize_event()`]
+/// (../event/trait.EventHandler.html#method.resize_event) still gets the
+/// size of the window.
+///
+/// This also switches back to drawing to the screen if a `Canvas`
+/// was set, like `set_canvas(ctx, None)` does.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// let resolution = VirtualResolution::new(320, 180, ScalingMode::PixelPerfect(BLACK));
+/// graphics::set_virtual_resolution(ctx, Some(resolution))?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn set_virtual_resolution(
+    ctx: &mut Context,
+    resolution: Option<VirtualResolution>,
+) -> GameResult {
+    ctx.gfx_context.flush_batch()?;
+    let screen_rect = match resolution {
+        Some(settings) => {
+            let mut canvas =
+                Canvas::new(ctx, settings.width, settings.height, conf::NumSamples::One)?;
+            if let ScalingMode::PixelPerfect(_) = settings.mode {
+                canvas.set_filter(FilterMode::Nearest);
+            }
+            // Whatever is drawn goes through as-is; alpha blending
+            // it with the bars would be a surprise.
+            canvas.set_blend_mode(Some(BlendMode::Replace));
+            ctx.gfx_context.virtual_screen = Some(VirtualScreen { settings, canvas });
+            Rect::new(
+                0.0,
+                0.0,
+                f32::from(settings.width),
+                f32::from(settings.height),
+            )
+        }
+        None => {
+            ctx.gfx_context.virtual_screen = None;
+            let (w, h) = drawable_size(ctx);
+            Rect::new(0.0, 0.0, w, h)
+        }
+    };
+    set_canvas(ctx, None);
+    set_screen_coordinates(ctx, screen_rect)
+}
+
+/// Returns the virtual resolution the game is rendered at, if any.
+/// See [`set_virtual_resolution()`](fn.set_virtual_resolution.html).
+pub fn virtual_resolution(ctx: &Context) -> Option<VirtualResolution> {
+    ctx.gfx_context
+        .virtual_screen
+        .as_ref()
+        .map(|screen| screen.settings)
+}
+
+/// Returns the part of the window that the virtual screen is shown in,
+/// in the same units as [`drawable_size()`](fn.drawable_size.html),
+/// or the whole window if there is no virtual resolution set.
+pub fn virtual_viewport(ctx: &Context) -> Rect {
+    let gfx = &ctx.gfx_context;
+    match gfx.virtual_screen {
+        Some(ref screen) => {
+            let viewport = screen.viewport(&gfx.window, gfx.hidpi_factor);
+            let scale = gfx.logical_scale();
+            Rect::new(
+                viewport.x * scale,
+                viewport.y * scale,
+                viewport.w * scale,
+                viewport.h * scale,
+            )
+        }
+        None => {
+            let (w, h) = drawable_size(ctx);
+            Rect::new(0.0, 0.0, w, h)
+        }
+    }
+}
+
+/// Draws the virtual screen, if there is one, onto the real one.
+pub(crate) fn present_virtual_screen(ctx: &mut Context) -> GameResult {
+    let screen = match ctx.gfx_context.virtual_screen.take() {
+        Some(screen) => screen,
+        None => return Ok(()),
+    };
+    let result = draw_virtual_screen(ctx, &screen);
+    ctx.gfx_context.virtual_screen = Some(screen);
+    result
+}
+
+fn draw_virtual_screen(ctx: &mut Context, screen: &VirtualScreen<GlBackendSpec>) -> GameResult {
+    let viewport = virtual_viewport(ctx);
+    let (w, h) = drawable_size(ctx);
+    let gfx = &mut ctx.gfx_context;
+    gfx.flush_batch()?;
+    gfx.set_render_target(gfx.screen_render_target.clone(), gfx.depth_view.clone());
+
+    // Draw in window pixels with the default shader, whatever
+    // the game has set up for itself.
+    let projection = gfx.projection();
+    let screen_rect = gfx.screen_rect;
+    let scissor = gfx.scissor.take();
+    let shader = gfx.current_shader.borrow_mut().take();
+    gfx.set_projection_rect(Rect::new(0.0, 0.0, w, h));
+    gfx.push_transform(Matrix4::identity());
+    gfx.calculate_transform_matrix();
+
+    let result = gfx.update_globals().and_then(|_| {
+        match screen.settings.mode {
+            ScalingMode::Letterbox(color) | ScalingMode::PixelPerfect(color) => clear(ctx, color),
+            ScalingMode::Stretch => (),
+        }
+        let scale = [
+            viewport.w / f32::from(screen.settings.width),
+            viewport.h / f32::from(screen.settings.height),
+        ];
+        let param = DrawParam::new().dest([viewport.x, viewport.y]).scale(scale);
+        screen.canvas.draw(ctx, param)?;
+        ctx.gfx_context.flush_batch()
+    });
+
+    let gfx = &mut ctx.gfx_context;
+    *gfx.current_shader.borrow_mut() = shader;
+    gfx.scissor = scissor;
+    gfx.pop_transform();
+    gfx.set_projection(projection);
+    gfx.screen_rect = screen_rect;
+    gfx.calculate_transform_matrix();
+    result.and_then(|_| gfx.update_globals())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_test_fit_virtual_screen() {
+        let letterbox = VirtualResolution::new(320, 180, ScalingMode::Letterbox(BLACK));
+        // Bars at the top and bottom...
+        assert_eq!(
+            fit(letterbox, (800.0, 600.0), 1.0),
+            Rect::new(0.0, 75.0, 800.0, 450.0)
+        );
+        // ...or on the sides.
+        assert_eq!(
+            fit(letterbox, (1000.0, 360.0), 1.0),
+            Rect::new(180.0, 0.0, 640.0, 360.0)
+        );
+
+        let pixel_perfect = VirtualResolution::new(320, 180, ScalingMode::PixelPerfect(BLACK));
+        assert_eq!(
+            fit(pixel_perfect, (800.0, 600.0), 1.0),
+            Rect::new(80.0, 120.0, 640.0, 360.0)
+        );
+        // On a high DPI display, whole physical pixels count.
+        assert_eq!(
+            fit(pixel_perfect, (800.0, 600.0), 1.5),
+            Rect::new(80.0, 120.0, 640.0, 360.0)
+        );
+        assert_eq!(
+            fit(pixel_perfect, (800.0, 600.0), 2.0),
+            Rect::new(0.0, 75.0, 800.0, 450.0)
+        );
+        // Too small a window still shows everything.
+        assert_eq!(
+            fit(pixel_perfect, (160.0, 180.0), 1.0),
+            Rect::new(0.0, 45.0, 160.0, 90.0)
+        );
+
+        let stretch = VirtualResolution::new(320, 180, ScalingMode::Stretch);
+        assert_eq!(
+            fit(stretch, (800.0, 600.0), 2.0),
+            Rect::new(0.0, 0.0, 800.0, 600.0)
+        );
+    }
+}
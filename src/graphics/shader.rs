@@ -24,6 +24,8 @@ use std::rc::Rc;
 use crate::context::DebugId;
 use crate::error::*;
 use crate::graphics;
+use crate::graphics::stencil::StencilMode;
+use crate::graphics::BackendSpec;
 use crate::Context;
 
 /// A type for empty shader data for shaders that do not require any additional
@@ -40,7 +42,7 @@ impl<F> Structure<F> for EmptyConst {
 unsafe impl Pod for EmptyConst {}
 
 /// An enum for specifying default and custom blend modes
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BlendMode {
     /// When combining two fragments, add their values together, saturating
     /// at 1.0
@@ -128,13 +130,14 @@ impl From<BlendMode> for Blend {
 /// modes is to just make multiple PSOs with respective blend modes baked in.
 /// The `PsoSet` struct is basically just a hash map for easily
 /// storing each shader set's PSOs and then retrieving them based
-/// on a [`BlendMode`](enum.BlendMode.html).
+/// on a [`BlendMode`](enum.BlendMode.html).  The same goes for the
+/// stencil state, so there is a PSO for every combination of the two.
 struct PsoSet<Spec, C>
 where
     Spec: graphics::BackendSpec,
     C: Structure<ConstFormat>,
 {
-    psos: HashMap<BlendMode, PipelineState<Spec::Resources, ConstMeta<C>>>,
+    psos: HashMap<(BlendMode, StencilMode), PipelineState<Spec::Resources, ConstMeta<C>>>,
 }
 
 impl<Spec, C> PsoSet<Spec, C>
@@ -151,16 +154,18 @@ where
     pub fn insert_mode(
         &mut self,
         mode: BlendMode,
+        stencil: StencilMode,
         pso: PipelineState<Spec::Resources, ConstMeta<C>>,
     ) {
-        let _ = self.psos.insert(mode, pso);
+        let _ = self.psos.insert((mode, stencil), pso);
     }
 
     pub fn mode(
         &self,
         mode: BlendMode,
+        stencil: StencilMode,
     ) -> GameResult<&PipelineState<Spec::Resources, ConstMeta<C>>> {
-        match self.psos.get(&mode) {
+        match self.psos.get(&(mode, stencil)) {
             Some(pso) => Ok(pso),
             None => Err(GameError::RenderError(
                 "Could not find a pipeline for the specified shader and BlendMode".into(),
@@ -212,44 +217,65 @@ where
     let default_mode = vec![BlendMode::Alpha];
     let blend_modes = blend_modes.unwrap_or(&default_mode[..]);
 
-    let mut psos = PsoSet::new(blend_modes.len());
+    // Linking the program once and making every PSO from it is a lot
+    // cheaper than `create_pipeline_state()` linking it for each one.
+    let set = factory.create_shader_set(vertex_source, pixel_source)?;
+    let shader_program = factory.create_program(&set).map_err(ProgramError::Link)?;
+    let sample = if multisample_samples > 1 {
+        Some(MultiSample)
+    } else {
+        None
+    };
+    let rasterizer = Rasterizer {
+        front_face: FrontFace::CounterClockwise,
+        cull_face: CullFace::Nothing,
+        method: RasterMethod::Fill,
+        offset: None,
+        samples: sample,
+    };
+
+    let mut psos = PsoSet::new(blend_modes.len() * StencilMode::ALL.len());
     let name: String = name.into();
     for mode in blend_modes {
-        let init = ConstInit::<C>(
-            graphics::pipe::Init {
-                out: (
-                    "Target0",
-                    color_format,
-                    ColorMask::all(),
-                    Some((*mode).into()),
-                ),
-                ..graphics::pipe::new()
-            },
-            name.clone(),
-            PhantomData,
-        );
-        let set = factory.create_shader_set(vertex_source, pixel_source)?;
-        let sample = if multisample_samples > 1 {
-            Some(MultiSample)
-        } else {
-            None
-        };
-        let rasterizer = Rasterizer {
-            front_face: FrontFace::CounterClockwise,
-            cull_face: CullFace::Nothing,
-            method: RasterMethod::Fill,
-            offset: None,
-            samples: sample,
-        };
-
-        let pso = factory.create_pipeline_state(&set, Primitive::TriangleList, rasterizer, init)?;
-        psos.insert_mode(*mode, pso);
+        for stencil in &StencilMode::ALL {
+            let mask = if stencil.writes_color() {
+                ColorMask::all()
+            } else {
+                ColorMask::empty()
+            };
+            let init = ConstInit::<C>(
+                graphics::pipe::Init {
+                    out: ("Target0", color_format, mask, Some((*mode).into())),
+                    stencil: (*stencil).into(),
+                    ..graphics::pipe::new()
+                },
+                name.clone(),
+                PhantomData,
+            );
+            let pso = factory
+                .create_pipeline_from_program(
+                    &shader_program,
+                    Primitive::TriangleList,
+                    rasterizer,
+                    init,
+                )
+                .map_err(|error| match error {
+                    PipelineStateError::Program(e) => PipelineStateError::Program(e),
+                    PipelineStateError::DescriptorInit(e) => {
+                        PipelineStateError::DescriptorInit(e.into())
+                    }
+                    PipelineStateError::DeviceCreate(e) => PipelineStateError::DeviceCreate(e),
+                })?;
+            psos.insert_mode(*mode, *stencil, pso);
+        }
     }
 
     let program = ShaderProgram {
         buffer: buffer.clone(),
         psos,
         active_blend_mode: blend_modes[0],
+        active_stencil_mode: StencilMode::Off,
+        image: None,
     };
     let draw: Box<dyn ShaderHandle<Spec>> = Box::new(program);
 
@@ -345,12 +371,33 @@ where
 {
     /// Send data to the GPU for use with the `Shader`
     pub fn send(&self, ctx: &mut Context, consts: C) -> GameResult {
+        // Batched draws have to see the old values.
+        ctx.gfx_context.flush_batch()?;
         ctx.gfx_context
             .encoder
             .update_buffer(&self.buffer, &[consts], 0)?;
         Ok(())
     }
 
+    /// Sends an image for the `Shader` to sample besides the one being
+    /// drawn, for things like lookup tables or masks.  The shader gets it
+    /// as `uniform sampler2D t_Image;`, and until an image is sent that is
+    /// the same as `t_Texture`.
+    pub fn send_image(&self, ctx: &mut Context, image: &graphics::Image) -> GameResult {
+        image.debug_id.assert(ctx);
+        let gfx = &mut ctx.gfx_context;
+        // Batched draws have to see the old image.
+        gfx.flush_batch()?;
+        let sampler = gfx
+            .samplers
+            .get_or_insert(image.sampler_info, &mut *gfx.factory);
+        let view = gfx
+            .backend_spec
+            .raw_to_typed_shader_resource(image.texture.clone());
+        gfx.shaders[self.id].set_image(Some((view, sampler)));
+        Ok(())
+    }
+
     /// Gets the shader ID for the `Shader` which is used by the
     /// graphics context for identifying shaders in its cache
     pub fn shader_id(&self) -> ShaderId {
@@ -372,6 +419,8 @@ struct ShaderProgram<Spec: graphics::BackendSpec, C: Structure<ConstFormat>> {
     buffer: Buffer<Spec::Resources, C>,
     psos: PsoSet<Spec, C>,
     active_blend_mode: BlendMode,
+    active_stencil_mode: StencilMode,
+    image: Option<TextureSampler<Spec::Resources>>,
 }
 
 impl<Spec, C> fmt::Debug for ShaderProgram<Spec, C>
@@ -400,6 +449,15 @@ pub trait ShaderHandle<Spec: graphics::BackendSpec>: fmt::Debug {
 
     /// Gets the shader program's current blend mode
     fn blend_mode(&self) -> BlendMode;
+
+    /// Sets the shader program's stencil mode
+    fn set_stencil_mode(&mut self, mode: StencilMode) -> GameResult;
+
+    /// Gets the shader program's current stencil mode
+    fn stencil_mode(&self) -> StencilMode;
+
+    /// Sets the image the shader program samples as `t_Image`
+    fn set_image(&mut self, image: Option<TextureSampler<Spec::Resources>>);
 }
 
 impl<Spec, C> ShaderHandle<Spec> for ShaderProgram<Spec, C>
@@ -413,13 +471,19 @@ where
         slice: &Slice<Spec::Resources>,
         data: &graphics::pipe::Data<Spec::Resources>,
     ) -> GameResult {
-        let pso = self.psos.mode(self.active_blend_mode)?;
-        encoder.draw(slice, pso, &ConstData(data, &self.buffer));
+        let pso = self
+            .psos
+            .mode(self.active_blend_mode, self.active_stencil_mode)?;
+        encoder.draw(
+            slice,
+            pso,
+            &ConstData(data, &self.buffer, self.image.as_ref()),
+        );
         Ok(())
     }
 
     fn set_blend_mode(&mut self, mode: BlendMode) -> GameResult {
-        let _ = self.psos.mode(mode)?;
+        let _ = self.psos.mode(mode, self.active_stencil_mode)?;
         self.active_blend_mode = mode;
         Ok(())
     }
@@ -427,6 +491,20 @@ where
     fn blend_mode(&self) -> BlendMode {
         self.active_blend_mode
     }
+
+    fn set_stencil_mode(&mut self, mode: StencilMode) -> GameResult {
+        let _ = self.psos.mode(self.active_blend_mode, mode)?;
+        self.active_stencil_mode = mode;
+        Ok(())
+    }
+
+    fn stencil_mode(&self) -> StencilMode {
+        self.active_stencil_mode
+    }
+
+    fn set_image(&mut self, image: Option<TextureSampler<Spec::Resources>>) {
+        self.image = image;
+    }
 }
 
 /// A lock for RAII shader regions. The shader automatically gets cleared once
@@ -479,11 +557,26 @@ pub fn clear_shader(ctx: &mut Context) {
     *ctx.gfx_context.current_shader.borrow_mut() = None;
 }
 
+/// The name of the second texture a shader can sample, see
+/// [`Shader::send_image()`](type.Shader.html#method.send_image).
+const IMAGE_NAME: &str = "t_Image";
+
+/// A texture view plus the sampler to sample it with.
+type TextureSampler<R> = (ShaderResourceView<R, [f32; 4]>, handle::Sampler<R>);
+
 #[derive(Debug)]
-struct ConstMeta<C: Structure<ConstFormat>>(graphics::pipe::Meta, ConstantBuffer<C>);
+struct ConstMeta<C: Structure<ConstFormat>>(
+    graphics::pipe::Meta,
+    ConstantBuffer<C>,
+    resource::TextureSampler<[f32; 4]>,
+);
 
 #[derive(Debug)]
-struct ConstData<'a, R: Resources, C: 'a>(&'a graphics::pipe::Data<R>, &'a Buffer<R, C>);
+struct ConstData<'a, R: Resources, C: 'a>(
+    &'a graphics::pipe::Data<R>,
+    &'a Buffer<R, C>,
+    Option<&'a TextureSampler<R>>,
+);
 
 impl<'a, R, C> PipelineData<R> for ConstData<'a, R, C>
 where
@@ -501,6 +594,9 @@ where
     ) {
         self.0.bake_to(out, &meta.0, man, access);
         meta.1.bind_to(out, self.1, man, access);
+        // Shaders that sample an image nobody sent get the one being drawn.
+        meta.2
+            .bind_to(out, self.2.unwrap_or(&self.0.tex), man, access);
     }
 }
 
@@ -519,63 +615,76 @@ where
         info: &'s ProgramInfo,
     ) -> Result<Self::Meta, InitError<&'s str>> {
         let mut meta1 = ConstantBuffer::<C>::new();
+        let mut meta2 = resource::TextureSampler::<[f32; 4]>::new();
+        // Whatever gets linked here has to be taken out of the program
+        // info the rest of the pipeline links against, or it complains
+        // about variables it doesn't know.
+        let mut program_info = info.clone();
 
-        let mut index = None;
-        for (i, cb) in info.constant_buffers.iter().enumerate() {
+        for cb in &info.constant_buffers {
             match meta1.link_constant_buffer(cb, &self.1.as_str()) {
                 Some(Ok(d)) => {
                     assert!(meta1.is_active());
                     desc.constant_buffers[cb.slot as usize] = Some(d);
-                    index = Some(i);
+                    program_info.constant_buffers.retain(|c| c.name != cb.name);
                     break;
                 }
                 Some(Err(e)) => return Err(InitError::ConstantBuffer(&cb.name, Some(e))),
                 None => (),
             }
         }
+        for texture in &info.textures {
+            match meta2.link_resource_view(texture, &IMAGE_NAME) {
+                Some(Ok(d)) => {
+                    desc.resource_views[texture.slot as usize] = Some(d);
+                    program_info.textures.retain(|t| t.name != texture.name);
+                    break;
+                }
+                Some(Err(_)) => return Err(InitError::ResourceView(&texture.name, Some(()))),
+                None => (),
+            }
+        }
+        for sampler in &info.samplers {
+            if let Some(d) = meta2.link_sampler(sampler, &IMAGE_NAME) {
+                desc.samplers[sampler.slot as usize] = Some(d);
+                program_info.samplers.retain(|s| s.name != sampler.name);
+                break;
+            }
+        }
 
-        if let Some(index) = index {
-            // create a local clone of the program info so that we can remove
-            // the var we found from the `constant_buffer`
-            let mut program_info = info.clone();
-            let _ = program_info.constant_buffers.remove(index);
-
-            let meta0 = match self.0.link_to(desc, &program_info) {
-                Ok(m) => m,
-                Err(e) => {
-                    // unfortunately... the error lifetime is bound to the
-                    // lifetime of our cloned program info which is bad since it
-                    // will go out of scope at the end of the function, so lets
-                    // convert the error to one that is bound to the lifetime of
-                    // the program info that was passed in!
-                    macro_rules! fixlifetimes {
-                        ($e:ident {
-                            $( $ty:path => $a:ident, )*
-                        }) => {{
-                            match $e {
-                                $( $ty(name, _) => {
-                                    let var = info.$a.iter().find(|v| v.name == name).unwrap();
-                                    // We can do better with the error data...
-                                    return Err($ty(&var.name, None));
-                                } )*
-                            }
-                        }}
-                    }
-                    fixlifetimes!(e {
-                        InitError::VertexImport => vertex_attributes,
-                        InitError::ConstantBuffer => constant_buffers,
-                        InitError::GlobalConstant => globals,
-                        InitError::ResourceView => textures,
-                        InitError::UnorderedView => unordereds,
-                        InitError::Sampler => samplers,
-                        InitError::PixelExport => outputs,
-                    })
+        let meta0 = match self.0.link_to(desc, &program_info) {
+            Ok(m) => m,
+            Err(e) => {
+                // unfortunately... the error lifetime is bound to the
+                // lifetime of our cloned program info which is bad since it
+                // will go out of scope at the end of the function, so lets
+                // convert the error to one that is bound to the lifetime of
+                // the program info that was passed in!
+                macro_rules! fixlifetimes {
+                    ($e:ident {
+                        $( $ty:path => $a:ident, )*
+                    }) => {{
+                        match $e {
+                            $( $ty(name, _) => {
+                                let var = info.$a.iter().find(|v| v.name == name).unwrap();
+                                // We can do better with the error data...
+                                return Err($ty(&var.name, None));
+                            } )*
+                        }
+                    }}
                 }
-            };
+                fixlifetimes!(e {
+                    InitError::VertexImport => vertex_attributes,
+                    InitError::ConstantBuffer => constant_buffers,
+                    InitError::GlobalConstant => globals,
+                    InitError::ResourceView => textures,
+                    InitError::UnorderedView => unordereds,
+                    InitError::Sampler => samplers,
+                    InitError::PixelExport => outputs,
+                })
+            }
+        };
 
-            Ok(ConstMeta(meta0, meta1))
-        } else {
-            Ok(ConstMeta(self.0.link_to(desc, info)?, meta1))
-        }
+        Ok(ConstMeta(meta0, meta1, meta2))
     }
 }
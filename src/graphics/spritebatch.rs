@@ -12,15 +12,26 @@
 //! If you use it, it's recommended to crank up the `opt-level` for
 //! debug mode in your game's `Cargo.toml`.
 
+use std::cell::RefCell;
+use std::cmp;
+use std::mem;
+use std::ops::Range;
+
 use crate::context::Context;
 use crate::error;
 use crate::error::GameResult;
 use crate::graphics::shader::BlendMode;
 use crate::graphics::types::FilterMode;
-use crate::graphics::{self, transform_rect, BackendSpec, DrawParam, DrawTransform, Rect};
+use crate::graphics::{
+    self, transform_rect, BackendSpec, DrawParam, DrawTransform, GlBackendSpec, InstanceProperties,
+    Rect,
+};
 use gfx;
 use gfx::Factory;
 
+type InstanceBuffer =
+    gfx::handle::Buffer<<GlBackendSpec as BackendSpec>::Resources, InstanceProperties>;
+
 /// A `SpriteBatch` draws a number of copies of the same image, using a single draw call.
 ///
 /// This is generally faster than drawing the same sprite with many
@@ -29,17 +40,80 @@ use gfx::Factory;
 /// slowly in `debug` mode because it spends a lot of time on array
 /// bounds checking and un-optimized math; you need to build with
 /// optimizations enabled to really get the speed boost.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// The sprites are kept in a buffer on the graphics card between
+/// draws, and only the ones that have been added, changed or removed
+/// since the last draw get uploaded again, so a batch that rarely
+/// changes (a big tilemap, for instance) costs next to nothing to draw.
+#[derive(Debug)]
 pub struct SpriteBatch {
     image: graphics::Image,
-    sprites: Vec<graphics::DrawParam>,
+    sprites: Vec<Option<graphics::DrawParam>>,
+    free: Vec<usize>,
     blend_mode: Option<BlendMode>,
+    instances: RefCell<InstanceCache>,
 }
 
 /// An index of a particular sprite in a `SpriteBatch`.
+///
+/// Handles stay valid until the sprite they point to is removed,
+/// no matter what happens to other sprites in the batch.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SpriteIdx(usize);
 
+/// The GPU-side copy of a `SpriteBatch`'s sprites, and which parts
+/// of it are out of date.
+#[derive(Debug, Default)]
+struct InstanceCache {
+    buffer: Option<InstanceBuffer>,
+    capacity: usize,
+    /// Sorted, non-overlapping, non-adjacent ranges of sprites that
+    /// need to be uploaded again.
+    dirty: Vec<Range<usize>>,
+}
+
+impl InstanceCache {
+    /// The smallest buffer we bother creating.
+    const MIN_CAPACITY: usize = 16;
+    /// Past this many separate dirty ranges we just upload everything
+    /// between the first and the last one in one go.
+    const MAX_DIRTY_RANGES: usize = 16;
+
+    /// Marks the sprite at the given index as needing to be uploaded.
+    fn mark_dirty(&mut self, idx: usize) {
+        let mut new = idx..idx + 1;
+        // Find the first range that ends at or after the new one starts
+        // and merge in everything that overlaps or touches it.
+        let first = self
+            .dirty
+            .iter()
+            .position(|r| r.end >= new.start)
+            .unwrap_or(self.dirty.len());
+        let mut last = first;
+        while last < self.dirty.len() && self.dirty[last].start <= new.end {
+            new.start = cmp::min(new.start, self.dirty[last].start);
+            new.end = cmp::max(new.end, self.dirty[last].end);
+            last += 1;
+        }
+        let _ = self.dirty.splice(first..last, Some(new));
+
+        if self.dirty.len() > Self::MAX_DIRTY_RANGES {
+            let start = self.dirty[0].start;
+            let end = self.dirty[self.dirty.len() - 1].end;
+            self.dirty.clear();
+            self.dirty.push(start..end);
+        }
+    }
+
+    /// Marks everything up to `len` as needing to be uploaded.
+    fn mark_all_dirty(&mut self, len: usize) {
+        self.dirty.clear();
+        if len > 0 {
+            self.dirty.push(0..len);
+        }
+    }
+}
+
 impl SpriteBatch {
     /// Creates a new `SpriteBatch`, drawing with the given image.
     ///
@@ -50,20 +124,37 @@ impl SpriteBatch {
         Self {
             image,
             sprites: vec![],
+            free: vec![],
             blend_mode: None,
+            instances: RefCell::new(InstanceCache::default()),
         }
     }
 
     /// Adds a new sprite to the sprite batch.
     ///
     /// Returns a handle with which to modify the sprite using
-    /// [`set()`](#method.set)
+    /// [`set()`](#method.set) or [`remove()`](#method.remove).
+    ///
+    /// The spot of a previously removed sprite gets reused if there is
+    /// one, so the new sprite is not necessarily drawn on top of all
+    /// the others.
     pub fn add<P>(&mut self, param: P) -> SpriteIdx
     where
         P: Into<graphics::DrawParam>,
     {
-        self.sprites.push(param.into());
-        SpriteIdx(self.sprites.len() - 1)
+        let param = Some(param.into());
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.sprites[idx] = param;
+                idx
+            }
+            None => {
+                self.sprites.push(param);
+                self.sprites.len() - 1
+            }
+        };
+        self.instances.get_mut().mark_dirty(idx);
+        SpriteIdx(idx)
     }
 
     /// Alters a sprite in the batch to use the given draw params
@@ -71,64 +162,121 @@ impl SpriteBatch {
     where
         P: Into<graphics::DrawParam>,
     {
-        if handle.0 < self.sprites.len() {
-            self.sprites[handle.0] = param.into();
-            Ok(())
-        } else {
-            Err(error::GameError::RenderError(String::from(
+        match self.sprites.get_mut(handle.0) {
+            Some(sprite @ Some(_)) => {
+                *sprite = Some(param.into());
+                self.instances.get_mut().mark_dirty(handle.0);
+                Ok(())
+            }
+            _ => Err(error::GameError::RenderError(String::from(
                 "Provided index is out of bounds.",
-            )))
+            ))),
+        }
+    }
+
+    /// Removes a sprite from the batch, returning its draw params.
+    ///
+    /// Handles to all other sprites stay valid.  The removed sprite's
+    /// handle may be handed out again by a later [`add()`](#method.add).
+    pub fn remove(&mut self, handle: SpriteIdx) -> GameResult<graphics::DrawParam> {
+        let param = match self.sprites.get_mut(handle.0) {
+            Some(sprite) => sprite.take(),
+            None => None,
+        };
+        let param = param.ok_or_else(|| {
+            error::GameError::RenderError(String::from("Provided index is out of bounds."))
+        })?;
+
+        if handle.0 + 1 == self.sprites.len() {
+            // Nothing to draw at the end of the batch, so we can just
+            // stop drawing there.
+            while let Some(None) = self.sprites.last() {
+                let _ = self.sprites.pop();
+            }
+            let len = self.sprites.len();
+            self.free.retain(|&idx| idx < len);
+        } else {
+            self.free.push(handle.0);
+            self.instances.get_mut().mark_dirty(handle.0);
         }
+        Ok(param)
     }
 
-    /// Immediately sends all data in the batch to the graphics card.
+    /// Sends all sprites that changed since the last time to the
+    /// graphics card, creating or growing the instance buffer as needed,
+    /// and returns the buffer.
     ///
     /// Generally just calling [`graphics::draw()`](../fn.draw.html) on the `SpriteBatch`
     /// will do this automatically.
-    fn flush(&self, ctx: &mut Context, image: &graphics::Image) -> GameResult {
-        // This is a little awkward but this is the right place
-        // to do whatever transformations need to happen to DrawParam's.
-        // We have a Context, and *everything* must pass through this
-        // function to be drawn, so.
-        // Though we do awkwardly have to allocate a new vector.
-        // ...though upon benchmarking, the actual allocation is basically nothing,
-        // the cost in debug mode is alllll math.
-        let new_sprites = self
-            .sprites
-            .iter()
-            .map(|param| {
-                // Copy old params
-                let mut new_param = *param;
-                let src_width = param.src.w;
-                let src_height = param.src.h;
-                let real_scale = graphics::Vector2::new(
-                    src_width * param.scale.x * f32::from(image.width),
-                    src_height * param.scale.y * f32::from(image.height),
-                );
-                new_param.scale = real_scale.into();
-                new_param.color = new_param.color;
-                let primitive_param = graphics::DrawTransform::from(new_param);
-                primitive_param.to_instance_properties(ctx.gfx_context.is_srgb())
-            })
-            .collect::<Vec<_>>();
-
+    fn flush(&self, ctx: &mut Context) -> GameResult<InstanceBuffer> {
         let gfx = &mut ctx.gfx_context;
-        if gfx.data.rect_instance_properties.len() < self.sprites.len() {
-            gfx.data.rect_instance_properties = gfx.factory.create_buffer(
-                self.sprites.len(),
+        let cache = &mut *self.instances.borrow_mut();
+        let len = self.sprites.len();
+        if cache.buffer.is_none() || cache.capacity < len {
+            // Grow geometrically so adding sprites one at a time
+            // doesn't recreate the buffer every time.
+            let capacity = cmp::max(len.next_power_of_two(), InstanceCache::MIN_CAPACITY);
+            cache.buffer = Some(gfx.factory.create_buffer(
+                capacity,
                 gfx::buffer::Role::Vertex,
                 gfx::memory::Usage::Dynamic,
                 gfx::memory::Bind::TRANSFER_DST,
-            )?;
+            )?);
+            cache.capacity = capacity;
+            cache.mark_all_dirty(len);
         }
-        gfx.encoder
-            .update_buffer(&gfx.data.rect_instance_properties, &new_sprites[..], 0)?;
-        Ok(())
+        let buffer = cache
+            .buffer
+            .clone()
+            .expect("Instance buffer was just created; should never happen");
+
+        let srgb = gfx.is_srgb();
+        let image = &self.image;
+        for range in cache.dirty.split_off(0) {
+            let range = range.start..cmp::min(range.end, len);
+            if range.start >= range.end {
+                continue;
+            }
+            // This is a little awkward but this is the right place
+            // to do whatever transformations need to happen to DrawParam's.
+            // We have a Context, and *everything* must pass through this
+            // function to be drawn, so.
+            // ...though upon benchmarking, the actual allocation is basically nothing,
+            // the cost in debug mode is alllll math.
+            let new_sprites = self.sprites[range.clone()]
+                .iter()
+                .map(|sprite| match sprite {
+                    Some(param) => {
+                        // Copy old params
+                        let mut new_param = *param;
+                        let real_scale = graphics::Vector2::new(
+                            param.src.w * param.scale.x * f32::from(image.width),
+                            param.src.h * param.scale.y * f32::from(image.height),
+                        );
+                        new_param.scale = real_scale.into();
+                        let primitive_param = graphics::DrawTransform::from(new_param);
+                        primitive_param.to_instance_properties(srgb)
+                    }
+                    None => removed_instance(),
+                })
+                .collect::<Vec<_>>();
+            if let Err(e) = gfx
+                .encoder
+                .update_buffer(&buffer, &new_sprites[..], range.start)
+            {
+                // Start over from scratch next time.
+                cache.buffer = None;
+                return Err(e.into());
+            }
+        }
+        Ok(buffer)
     }
 
     /// Removes all data from the sprite batch.
     pub fn clear(&mut self) {
         self.sprites.clear();
+        self.free.clear();
+        self.instances.get_mut().dirty.clear();
     }
 
     /// Unwraps and returns the contained `Image`
@@ -138,7 +286,8 @@ impl SpriteBatch {
 
     /// Replaces the contained `Image`, returning the old one.
     pub fn set_image(&mut self, image: graphics::Image) -> graphics::Image {
-        use std::mem;
+        // Sprites get scaled by the image size when they are uploaded.
+        self.instances.get_mut().mark_all_dirty(self.sprites.len());
         mem::replace(&mut self.image, image)
     }
 
@@ -153,11 +302,49 @@ impl SpriteBatch {
     }
 }
 
+/// What gets uploaded in place of a removed sprite: squashed down to
+/// nothing and fully transparent, so it never produces any pixels.
+fn removed_instance() -> InstanceProperties {
+    InstanceProperties {
+        col1: [0.0, 0.0, 0.0, 0.0],
+        col2: [0.0, 0.0, 0.0, 0.0],
+        col3: [0.0, 0.0, 0.0, 0.0],
+        col4: [0.0, 0.0, 0.0, 1.0],
+        src: [0.0, 0.0, 0.0, 0.0],
+        color: [0.0, 0.0, 0.0, 0.0],
+    }
+}
+
+impl Clone for SpriteBatch {
+    /// Clones the sprites, but not the buffer on the graphics card;
+    /// the clone gets its own the first time it is drawn.
+    fn clone(&self) -> Self {
+        Self {
+            image: self.image.clone(),
+            sprites: self.sprites.clone(),
+            free: self.free.clone(),
+            blend_mode: self.blend_mode,
+            instances: RefCell::new(InstanceCache::default()),
+        }
+    }
+}
+
+impl PartialEq for SpriteBatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.image == other.image
+            && self.sprites == other.sprites
+            && self.blend_mode == other.blend_mode
+    }
+}
+
 impl graphics::Drawable for SpriteBatch {
     fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
         // Awkwardly we must update values on all sprites and such.
         // Also awkwardly we have this chain of colors with differing priorities.
-        self.flush(ctx, &self.image)?;
+        if self.sprites.is_empty() {
+            return Ok(());
+        }
+        let buffer = self.flush(ctx)?;
         let gfx = &mut ctx.gfx_context;
         let sampler = gfx
             .samplers
@@ -167,6 +354,7 @@ impl graphics::Drawable for SpriteBatch {
             .backend_spec
             .raw_to_typed_shader_resource(self.image.texture.clone());
         gfx.data.tex = (typed_thingy, sampler);
+        let previous_buffer = mem::replace(&mut gfx.data.rect_instance_properties, buffer);
 
         let mut slice = gfx.quad_slice.clone();
         slice.instances = Some((self.sprites.len() as u32, 0));
@@ -186,7 +374,9 @@ impl graphics::Drawable for SpriteBatch {
         } else {
             None
         };
-        gfx.draw(Some(&slice))?;
+        let result = gfx.draw(Some(&slice));
+        gfx.data.rect_instance_properties = previous_buffer;
+        result?;
         if let Some(mode) = previous_mode {
             gfx.set_blend_mode(mode)?;
         }
@@ -202,7 +392,8 @@ impl graphics::Drawable for SpriteBatch {
         let dimensions = self.image.dimensions();
         self.sprites
             .iter()
-            .map(|&param| transform_rect(dimensions, param))
+            .filter_map(|&sprite| sprite)
+            .map(|param| transform_rect(dimensions, param))
             .fold(None, |acc: Option<Rect>, rect| {
                 Some(if let Some(acc) = acc {
                     acc.combine_with(rect)
@@ -218,3 +409,43 @@ impl graphics::Drawable for SpriteBatch {
         self.blend_mode
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::InstanceCache;
+
+    #[test]
+    fn headless_test_dirty_ranges() {
+        let mut cache = InstanceCache::default();
+        for i in 0..10 {
+            cache.mark_dirty(i);
+        }
+        assert_eq!(cache.dirty, vec![0..10]);
+
+        cache.dirty.clear();
+        cache.mark_dirty(5);
+        cache.mark_dirty(1);
+        cache.mark_dirty(8);
+        cache.mark_dirty(5);
+        assert_eq!(cache.dirty, vec![1..2, 5..6, 8..9]);
+        // Touching ranges get merged...
+        cache.mark_dirty(6);
+        cache.mark_dirty(7);
+        assert_eq!(cache.dirty, vec![1..2, 5..9]);
+        // ...and so does one that bridges the gap between two.
+        cache.mark_dirty(0);
+        cache.mark_dirty(3);
+        cache.mark_dirty(4);
+        assert_eq!(cache.dirty, vec![0..2, 3..9]);
+
+        // Too many ranges just collapse into one.
+        cache.dirty.clear();
+        for i in 0..=InstanceCache::MAX_DIRTY_RANGES {
+            cache.mark_dirty(i * 2);
+        }
+        assert_eq!(
+            cache.dirty,
+            vec![0..InstanceCache::MAX_DIRTY_RANGES * 2 + 1]
+        );
+    }
+}
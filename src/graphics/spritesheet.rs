@@ -0,0 +1,629 @@
+//! Loading sprite sheets exported by Aseprite and TexturePacker.
+//!
+//! Both tools (and plenty of others) can write out a JSON file alongside
+//! the packed image, with a `frames` section that's either an object
+//! keyed by frame name ("JSON hash") or a list of frames each with a
+//! `filename` ("JSON array"), and a `meta` section naming the image.
+//! [`SpriteSheet::new()`](struct.SpriteSheet.html#method.new) reads
+//! either flavor from the ggez [`filesystem`](../../filesystem/index.html),
+//! loads the image next to it, and gives you the `src` rect of every frame.
+//!
+//! On top of that it picks up:
+//!
+//!  * Aseprite's per-frame `duration`s, `frameTags` and `slices`; every
+//!    tag becomes an [`Animation`](../animation/struct.Animation.html)
+//!    named after it, playing in the tag's direction.
+//!  * TexturePacker's (well, Phaser's and PixiJS's) `animations` object,
+//!    mapping animation names to lists of frame names.
+//!
+//! The frames of those animations keep the offsets of trimmed sprites,
+//! so they line up with each other when played.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::*;
+//! # use ggez::graphics::animation::AnimatedSprite;
+//! # use ggez::graphics::spritesheet::SpriteSheet;
+//! # fn main() -> GameResult {
+//! # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+//! let sheet = SpriteSheet::new(ctx, "/sprites/player.json")?;
+//! let idle = sheet.src("idle 0").unwrap();
+//! graphics::draw(ctx, sheet.image(), DrawParam::new().src(idle))?;
+//! let mut walk = AnimatedSprite::new(sheet.image().clone(), sheet.animation("walk").unwrap().clone());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Frames that the packer rotated to fit them in are marked as such in
+//! [`SheetFrame::rotated`](struct.SheetFrame.html#structfield.rotated);
+//! their `src` and `pixels` cover the rotated region as it is in the
+//! image, with the width and height of `source_size` swapped, so they
+//! need to be drawn rotated back by a quarter turn.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::animation::{Animation, Frame, PlayMode};
+use crate::graphics::{Image, Rect};
+
+/// How long frames without a `duration` of their own last
+/// in animations: 100 milliseconds, same as Aseprite's default.
+const DEFAULT_FRAME_DURATION: u64 = 100;
+
+/// One frame of a [`SpriteSheet`](struct.SpriteSheet.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetFrame {
+    /// The frame's name; its key in a JSON hash export, or its
+    /// `filename` in a JSON array export.
+    pub name: String,
+    /// The region of the image the frame is in, as a fraction of the
+    /// whole image, the same as [`DrawParam::src`](../struct.DrawParam.html#structfield.src).
+    pub src: Rect,
+    /// The region of the image the frame is in, in pixels.
+    pub pixels: Rect,
+    /// Whether the packer rotated the frame 90 degrees clockwise.
+    pub rotated: bool,
+    /// Where the top left of the frame goes in the original sprite, if
+    /// transparent borders were trimmed off when packing it.  Zero otherwise.
+    pub offset: mint::Point2<f32>,
+    /// The size of the original sprite before any trimming.
+    pub source_size: mint::Vector2<f32>,
+    /// How long the frame lasts, if the export says.
+    pub duration: Option<Duration>,
+}
+
+/// A named region of a sprite, as set up with Aseprite's slice tool.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slice {
+    /// The slice's name.
+    pub name: String,
+    /// Where the slice is in each frame, in the order of the frames they
+    /// start at.  A key holds from its frame until the next one's.
+    pub keys: Vec<SliceKey>,
+}
+
+/// Where a [`Slice`](struct.Slice.html) is, from a particular frame on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SliceKey {
+    /// The index of the frame this key starts at.
+    pub frame: usize,
+    /// The slice's bounds in pixels, relative to the top left of the
+    /// (untrimmed) sprite.
+    pub bounds: Rect,
+    /// The center part of a 9-patch slice, relative to `bounds`.
+    pub center: Option<Rect>,
+    /// The slice's pivot point, relative to `bounds`.
+    pub pivot: Option<mint::Point2<f32>>,
+}
+
+impl Slice {
+    /// Returns the key that applies to the given frame, if any.
+    pub fn key(&self, frame: usize) -> Option<&SliceKey> {
+        self.keys.iter().rev().find(|k| k.frame <= frame)
+    }
+}
+
+/// An `Image` together with the frames, animations and slices
+/// described by an Aseprite or TexturePacker JSON file.
+///
+/// See the [module docs](index.html) for details.
+#[derive(Clone, Debug)]
+pub struct SpriteSheet {
+    image: Image,
+    frames: Vec<SheetFrame>,
+    names: HashMap<String, usize>,
+    animations: HashMap<String, Animation>,
+    slices: HashMap<String, Slice>,
+}
+
+impl SpriteSheet {
+    /// Loads the JSON file at the given path and the image it names,
+    /// which is looked for relative to the JSON file's directory.
+    pub fn new<P: AsRef<Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let mut buf = Vec::new();
+        let mut reader = ctx.filesystem.open(path)?;
+        let _ = reader.read_to_end(&mut buf)?;
+        let sheet = parse(&buf)?;
+        let image_path = path
+            .parent()
+            .unwrap_or_else(|| Path::new("/"))
+            .join(&sheet.meta.image);
+        let image = Image::new(ctx, image_path)?;
+        Self::build(sheet, image)
+    }
+
+    /// Reads a sprite sheet from JSON data, using the given image
+    /// rather than the one the JSON names.
+    pub fn from_json(json: &[u8], image: Image) -> GameResult<Self> {
+        Self::build(parse(json)?, image)
+    }
+
+    fn build(sheet: JsonSheet, image: Image) -> GameResult<Self> {
+        let contents = SheetContents::new(sheet, image.dimensions())?;
+        Ok(SpriteSheet {
+            image,
+            frames: contents.frames,
+            names: contents.names,
+            animations: contents.animations,
+            slices: contents.slices,
+        })
+    }
+
+    /// Returns the sheet's image.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Returns all the frames, in the order they appear in the JSON file.
+    pub fn frames(&self) -> &[SheetFrame] {
+        &self.frames
+    }
+
+    /// Returns the frame with the given name.
+    pub fn frame(&self, name: &str) -> Option<&SheetFrame> {
+        self.names.get(name).map(|&i| &self.frames[i])
+    }
+
+    /// Returns the `src` rect of the frame with the given name.
+    pub fn src(&self, name: &str) -> Option<Rect> {
+        self.frame(name).map(|f| f.src)
+    }
+
+    /// Returns the animation with the given name.
+    pub fn animation(&self, name: &str) -> Option<&Animation> {
+        self.animations.get(name)
+    }
+
+    /// Returns all the animations, by name.
+    pub fn animations(&self) -> &HashMap<String, Animation> {
+        &self.animations
+    }
+
+    /// Returns the slice with the given name.
+    pub fn slice(&self, name: &str) -> Option<&Slice> {
+        self.slices.get(name)
+    }
+
+    /// Returns all the slices, by name.
+    pub fn slices(&self) -> &HashMap<String, Slice> {
+        &self.slices
+    }
+}
+
+/// Everything a `SpriteSheet` holds apart from the image itself.
+#[derive(Debug)]
+struct SheetContents {
+    frames: Vec<SheetFrame>,
+    names: HashMap<String, usize>,
+    animations: HashMap<String, Animation>,
+    slices: HashMap<String, Slice>,
+}
+
+impl SheetContents {
+    fn new(sheet: JsonSheet, image_size: Rect) -> GameResult<Self> {
+        let frames: Vec<SheetFrame> = sheet
+            .frames
+            .0
+            .into_iter()
+            .map(|(name, f)| {
+                // Packers give the size of the sprite before it was rotated,
+                // so a rotated frame takes up its width and height swapped.
+                let (w, h) = if f.rotated {
+                    (f.frame.h, f.frame.w)
+                } else {
+                    (f.frame.w, f.frame.h)
+                };
+                let pixels = Rect::new(f.frame.x, f.frame.y, w, h);
+                let offset = f
+                    .sprite_source_size
+                    .map(|r| mint::Point2 { x: r.x, y: r.y })
+                    .unwrap_or(mint::Point2 { x: 0.0, y: 0.0 });
+                let source_size = f
+                    .source_size
+                    .map(|s| mint::Vector2 { x: s.w, y: s.h })
+                    .unwrap_or(mint::Vector2 {
+                        x: f.frame.w,
+                        y: f.frame.h,
+                    });
+                SheetFrame {
+                    name,
+                    src: Rect::fraction(f.frame.x, f.frame.y, w, h, &image_size),
+                    pixels,
+                    rotated: f.rotated,
+                    offset,
+                    source_size,
+                    duration: f.duration.map(Duration::from_millis),
+                }
+            })
+            .collect();
+        let names = frames
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (f.name.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut animations = HashMap::new();
+        for tag in sheet.meta.frame_tags {
+            if tag.from > tag.to || tag.to >= frames.len() {
+                let msg = format!(
+                    "Frame tag {} covers frames {} to {}, but there are only {} frames",
+                    tag.name,
+                    tag.from,
+                    tag.to,
+                    frames.len()
+                );
+                return Err(GameError::ResourceLoadError(msg));
+            }
+            let mut indices: Vec<usize> = (tag.from..=tag.to).collect();
+            let mode = match tag.direction.as_str() {
+                "" | "forward" => PlayMode::Loop,
+                "reverse" => {
+                    indices.reverse();
+                    PlayMode::Loop
+                }
+                "pingpong" => PlayMode::PingPong,
+                "pingpong_reverse" => {
+                    indices.reverse();
+                    PlayMode::PingPong
+                }
+                other => {
+                    let msg = format!("Frame tag {} has unknown direction {:?}", tag.name, other);
+                    return Err(GameError::ResourceLoadError(msg));
+                }
+            };
+            let animation = animation_from(&frames, &indices).mode(mode);
+            let _ = animations.insert(tag.name, animation);
+        }
+        for (name, frame_names) in sheet.animations {
+            let indices = frame_names
+                .iter()
+                .map(|frame| {
+                    names.get(frame).cloned().ok_or_else(|| {
+                        let msg = format!(
+                            "Animation {} uses frame {}, which doesn't exist",
+                            name, frame
+                        );
+                        GameError::ResourceLoadError(msg)
+                    })
+                })
+                .collect::<GameResult<Vec<_>>>()?;
+            let _ = animations.insert(name, animation_from(&frames, &indices));
+        }
+
+        let slices = sheet
+            .meta
+            .slices
+            .into_iter()
+            .map(|s| {
+                let keys = s
+                    .keys
+                    .into_iter()
+                    .map(|k| SliceKey {
+                        frame: k.frame,
+                        bounds: k.bounds.into(),
+                        center: k.center.map(Into::into),
+                        pivot: k.pivot.map(|p| mint::Point2 { x: p.x, y: p.y }),
+                    })
+                    .collect();
+                (s.name.clone(), Slice { name: s.name, keys })
+            })
+            .collect();
+
+        Ok(SheetContents {
+            frames,
+            names,
+            animations,
+            slices,
+        })
+    }
+}
+
+fn animation_from(frames: &[SheetFrame], indices: &[usize]) -> Animation {
+    let frames = indices
+        .iter()
+        .map(|&i| {
+            let duration = frames[i]
+                .duration
+                .unwrap_or_else(|| Duration::from_millis(DEFAULT_FRAME_DURATION));
+            Frame::new(frames[i].src, duration).offset(frames[i].offset)
+        })
+        .collect();
+    Animation::new(frames)
+}
+
+fn parse(json: &[u8]) -> GameResult<JsonSheet> {
+    serde_json::from_slice(json).map_err(|e| {
+        let msg = format!("Could not parse sprite sheet: {}", e);
+        GameError::ResourceLoadError(msg)
+    })
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+struct JsonRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+impl From<JsonRect> for Rect {
+    fn from(r: JsonRect) -> Rect {
+        Rect::new(r.x, r.y, r.w, r.h)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+struct JsonSize {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFrame {
+    #[serde(default)]
+    filename: Option<String>,
+    frame: JsonRect,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(rename = "spriteSourceSize")]
+    sprite_source_size: Option<JsonRect>,
+    #[serde(rename = "sourceSize")]
+    source_size: Option<JsonSize>,
+    duration: Option<u64>,
+}
+
+/// The frames of a sheet, in the order they appear in the file,
+/// whether they are stored as an object or as an array.
+#[derive(Debug)]
+struct JsonFrames(Vec<(String, JsonFrame)>);
+
+impl<'de> Deserialize<'de> for JsonFrames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = JsonFrames;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an object or array of frames")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                // Frame tags refer to frames by index, so we can't
+                // let the order get lost in a hash map.
+                let mut frames = Vec::new();
+                while let Some((name, frame)) = map.next_entry::<String, JsonFrame>()? {
+                    frames.push((name, frame));
+                }
+                Ok(JsonFrames(frames))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut frames = Vec::new();
+                while let Some(mut frame) = seq.next_element::<JsonFrame>()? {
+                    let name = frame.filename.take().ok_or_else(|| {
+                        de::Error::custom(format!("frame {} has no filename", frames.len()))
+                    })?;
+                    frames.push((name, frame));
+                }
+                Ok(JsonFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_any(FramesVisitor)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonSliceKey {
+    frame: usize,
+    bounds: JsonRect,
+    center: Option<JsonRect>,
+    pivot: Option<JsonPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonSlice {
+    name: String,
+    keys: Vec<JsonSliceKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonMeta {
+    image: String,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<JsonTag>,
+    #[serde(default)]
+    slices: Vec<JsonSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonSheet {
+    frames: JsonFrames,
+    meta: JsonMeta,
+    #[serde(default)]
+    animations: HashMap<String, Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASEPRITE: &str = r##"{
+        "frames": {
+            "player 0.aseprite": {
+                "frame": { "x": 0, "y": 0, "w": 16, "h": 16 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 },
+                "sourceSize": { "w": 16, "h": 16 },
+                "duration": 100
+            },
+            "player 2.aseprite": {
+                "frame": { "x": 32, "y": 0, "w": 16, "h": 16 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 16, "h": 16 },
+                "sourceSize": { "w": 16, "h": 16 },
+                "duration": 300
+            },
+            "player 10.aseprite": {
+                "frame": { "x": 16, "y": 0, "w": 12, "h": 14 },
+                "rotated": false,
+                "trimmed": true,
+                "spriteSourceSize": { "x": 2, "y": 1, "w": 12, "h": 14 },
+                "sourceSize": { "w": 16, "h": 16 },
+                "duration": 200
+            }
+        },
+        "meta": {
+            "app": "http://www.aseprite.org/",
+            "image": "player.png",
+            "size": { "w": 64, "h": 16 },
+            "frameTags": [
+                { "name": "walk", "from": 0, "to": 2, "direction": "pingpong" },
+                { "name": "back", "from": 1, "to": 2, "direction": "reverse" }
+            ],
+            "slices": [
+                { "name": "hitbox", "color": "#0000ffff", "keys": [
+                    { "frame": 0, "bounds": { "x": 4, "y": 2, "w": 8, "h": 12 } },
+                    { "frame": 2, "bounds": { "x": 5, "y": 2, "w": 6, "h": 12 },
+                      "pivot": { "x": 3, "y": 12 } }
+                ] }
+            ]
+        }
+    }"##;
+
+    const TEXTURE_PACKER: &str = r#"{
+        "frames": [
+            {
+                "filename": "coin_1.png",
+                "frame": { "x": 0, "y": 0, "w": 8, "h": 8 },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 8, "h": 8 },
+                "sourceSize": { "w": 8, "h": 8 }
+            },
+            {
+                "filename": "coin_2.png",
+                "frame": { "x": 8, "y": 0, "w": 4, "h": 8 },
+                "rotated": true,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": 4, "h": 8 },
+                "sourceSize": { "w": 4, "h": 8 }
+            }
+        ],
+        "animations": { "spin": ["coin_2.png", "coin_1.png"] },
+        "meta": { "image": "coins.png", "format": "RGBA8888", "size": { "w": 16, "h": 8 }, "scale": "1" }
+    }"#;
+
+    fn load(json: &str, w: f32, h: f32) -> SheetContents {
+        SheetContents::new(parse(json.as_bytes()).unwrap(), Rect::new(0.0, 0.0, w, h)).unwrap()
+    }
+
+    #[test]
+    fn headless_test_aseprite_sheet() {
+        let sheet = load(ASEPRITE, 64.0, 16.0);
+        // Frames keep the order of the file, even in hash form.
+        let names: Vec<_> = sheet.frames.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "player 0.aseprite",
+                "player 2.aseprite",
+                "player 10.aseprite"
+            ]
+        );
+        let trimmed = &sheet.frames[sheet.names["player 10.aseprite"]];
+        assert_eq!(trimmed.src, Rect::new(0.25, 0.0, 0.1875, 0.875));
+        assert_eq!(trimmed.pixels, Rect::new(16.0, 0.0, 12.0, 14.0));
+        assert_eq!(trimmed.offset, mint::Point2 { x: 2.0, y: 1.0 });
+        assert_eq!(trimmed.duration, Some(Duration::from_millis(200)));
+
+        let walk = &sheet.animations["walk"];
+        assert_eq!(walk.play_mode(), PlayMode::PingPong);
+        let durations: Vec<_> = walk.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(
+            durations,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(300),
+                Duration::from_millis(200)
+            ]
+        );
+        let back = &sheet.animations["back"];
+        assert_eq!(back.play_mode(), PlayMode::Loop);
+        assert_eq!(back.frames()[0].src, trimmed.src);
+        assert_eq!(back.frames()[0].offset, trimmed.offset);
+
+        let hitbox = &sheet.slices["hitbox"];
+        assert_eq!(
+            hitbox.key(1).unwrap().bounds,
+            Rect::new(4.0, 2.0, 8.0, 12.0)
+        );
+        let last = hitbox.key(2).unwrap();
+        assert_eq!(last.bounds, Rect::new(5.0, 2.0, 6.0, 12.0));
+        assert_eq!(last.pivot, Some(mint::Point2 { x: 3.0, y: 12.0 }));
+    }
+
+    #[test]
+    fn headless_test_texture_packer_sheet() {
+        let sheet = load(TEXTURE_PACKER, 16.0, 8.0);
+        assert_eq!(sheet.frames.len(), 2);
+        let coin = &sheet.frames[sheet.names["coin_2.png"]];
+        assert!(coin.rotated);
+        // Listed as 4x8, but lying on its side in the image.
+        assert_eq!(coin.pixels, Rect::new(8.0, 0.0, 8.0, 4.0));
+        assert_eq!(coin.src, Rect::new(0.5, 0.0, 0.5, 0.5));
+        assert_eq!(coin.source_size, mint::Vector2 { x: 4.0, y: 8.0 });
+        assert_eq!(coin.duration, None);
+
+        let spin = &sheet.animations["spin"];
+        assert_eq!(spin.frames()[0].src, coin.src);
+        assert_eq!(
+            spin.total_duration(),
+            Duration::from_millis(DEFAULT_FRAME_DURATION * 2)
+        );
+
+        // Animations referring to frames that don't exist are an error...
+        let bad = TEXTURE_PACKER.replace("\"coin_1.png\"]", "\"coin_3.png\"]");
+        let result = SheetContents::new(parse(bad.as_bytes()).unwrap(), Rect::one());
+        assert!(result.is_err());
+        // ...and so are array frames without names.
+        let bad = TEXTURE_PACKER.replace("\"filename\": \"coin_1.png\",", "");
+        assert!(parse(bad.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! Masking drawing with the stencil buffer.
+//!
+//! The screen and every `Canvas` have a stencil buffer, which holds a
+//! number from 0 to 255 for each pixel.  Drawing something with
+//! [`draw_stencil()`](fn.draw_stencil.html) changes those numbers
+//! wherever it covers instead of drawing it, and after calling
+//! [`set_stencil_test()`](fn.set_stencil_test.html) drawing only
+//! touches pixels whose numbers pass the test.  That makes for masks
+//! of any shape: fog of war, portals, round minimaps and so on.
+
+use gfx::state::{Comparison, StencilOp};
+
+use crate::context::Context;
+use crate::error::GameResult;
+use crate::graphics::{DrawParam, Drawable};
+
+/// What [`draw_stencil()`](fn.draw_stencil.html) does to the stencil
+/// buffer wherever the thing drawn covers it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StencilAction {
+    /// Sets the stencil buffer to the given value.
+    Replace(u8),
+    /// Flips all bits of the stencil buffer, so drawing over the same
+    /// place twice undoes it.
+    Invert,
+    /// Adds one to the stencil buffer, up to 255.
+    Increment,
+    /// Subtracts one from the stencil buffer, down to 0.
+    Decrement,
+}
+
+/// Which pixels drawing is allowed to touch, depending on what the
+/// stencil buffer holds there.
+/// See [`set_stencil_test()`](fn.set_stencil_test.html).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StencilTest {
+    /// Only where the stencil buffer holds the given value.
+    Equal(u8),
+    /// Only where the stencil buffer doesn't hold the given value.
+    NotEqual(u8),
+    /// Only where the stencil buffer holds less than the given value.
+    Less(u8),
+    /// Only where the stencil buffer holds the given value or less.
+    LessOrEqual(u8),
+    /// Only where the stencil buffer holds more than the given value.
+    Greater(u8),
+    /// Only where the stencil buffer holds the given value or more.
+    GreaterOrEqual(u8),
+}
+
+impl StencilTest {
+    /// Returns the test that passes exactly where this one fails,
+    /// for drawing everywhere outside of a mask rather than inside.
+    pub fn invert(self) -> Self {
+        match self {
+            StencilTest::Equal(value) => StencilTest::NotEqual(value),
+            StencilTest::NotEqual(value) => StencilTest::Equal(value),
+            StencilTest::Less(value) => StencilTest::GreaterOrEqual(value),
+            StencilTest::LessOrEqual(value) => StencilTest::Greater(value),
+            StencilTest::Greater(value) => StencilTest::LessOrEqual(value),
+            StencilTest::GreaterOrEqual(value) => StencilTest::Less(value),
+        }
+    }
+
+    /// The value the stencil buffer is compared to.
+    fn value(self) -> u8 {
+        match self {
+            StencilTest::Equal(value)
+            | StencilTest::NotEqual(value)
+            | StencilTest::Less(value)
+            | StencilTest::LessOrEqual(value)
+            | StencilTest::Greater(value)
+            | StencilTest::GreaterOrEqual(value) => value,
+        }
+    }
+
+    /// GL compares the reference value to the stencil buffer rather
+    /// than the other way around, so everything is flipped.
+    fn comparison(self) -> Comparison {
+        match self {
+            StencilTest::Equal(_) => Comparison::Equal,
+            StencilTest::NotEqual(_) => Comparison::NotEqual,
+            StencilTest::Less(_) => Comparison::Greater,
+            StencilTest::LessOrEqual(_) => Comparison::GreaterEqual,
+            StencilTest::Greater(_) => Comparison::Less,
+            StencilTest::GreaterOrEqual(_) => Comparison::LessEqual,
+        }
+    }
+}
+
+/// The part of the stencil state that's baked into pipeline state
+/// objects; the reference value is passed in separately when drawing.
+///
+/// As an end-user you shouldn't ever have to touch this.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StencilMode {
+    /// Draw normally.
+    Off,
+    /// Change the stencil buffer with the given operation instead of
+    /// drawing any color.
+    Write(StencilOp),
+    /// Draw only where the stencil buffer passes the comparison.
+    Test(Comparison),
+}
+
+impl StencilMode {
+    /// All the modes a shader needs pipeline state objects for.
+    pub(crate) const ALL: [StencilMode; 11] = [
+        StencilMode::Off,
+        StencilMode::Write(StencilOp::Replace),
+        StencilMode::Write(StencilOp::Invert),
+        StencilMode::Write(StencilOp::IncrementClamp),
+        StencilMode::Write(StencilOp::DecrementClamp),
+        StencilMode::Test(Comparison::Equal),
+        StencilMode::Test(Comparison::NotEqual),
+        StencilMode::Test(Comparison::Less),
+        StencilMode::Test(Comparison::LessEqual),
+        StencilMode::Test(Comparison::Greater),
+        StencilMode::Test(Comparison::GreaterEqual),
+    ];
+
+    /// Returns the mode and reference value for drawing into the
+    /// stencil buffer with the given action.
+    pub(crate) fn write(action: StencilAction) -> (Self, u8) {
+        match action {
+            StencilAction::Replace(value) => (StencilMode::Write(StencilOp::Replace), value),
+            StencilAction::Invert => (StencilMode::Write(StencilOp::Invert), 0),
+            StencilAction::Increment => (StencilMode::Write(StencilOp::IncrementClamp), 0),
+            StencilAction::Decrement => (StencilMode::Write(StencilOp::DecrementClamp), 0),
+        }
+    }
+
+    /// Returns the mode and reference value for drawing with
+    /// the given test, if any.
+    pub(crate) fn test(test: Option<StencilTest>) -> (Self, u8) {
+        match test {
+            Some(test) => (StencilMode::Test(test.comparison()), test.value()),
+            None => (StencilMode::Off, 0),
+        }
+    }
+
+    /// Whether drawing in this mode changes colors at all.
+    pub(crate) fn writes_color(self) -> bool {
+        match self {
+            StencilMode::Write(_) => false,
+            StencilMode::Off | StencilMode::Test(_) => true,
+        }
+    }
+}
+
+impl From<StencilMode> for gfx::state::Stencil {
+    fn from(mode: StencilMode) -> Self {
+        use gfx::state::Stencil;
+        match mode {
+            StencilMode::Off => Stencil::new(
+                Comparison::Always,
+                0,
+                (StencilOp::Keep, StencilOp::Keep, StencilOp::Keep),
+            ),
+            StencilMode::Write(op) => Stencil::new(
+                Comparison::Always,
+                0xFF,
+                (StencilOp::Keep, StencilOp::Keep, op),
+            ),
+            StencilMode::Test(comparison) => Stencil::new(
+                comparison,
+                0xFF,
+                (StencilOp::Keep, StencilOp::Keep, StencilOp::Keep),
+            ),
+        }
+    }
+}
+
+/// Draws the given `Drawable` into the stencil buffer of whatever is
+/// being drawn to instead of drawing its colors, changing the stencil
+/// buffer as the `action` says wherever it covers.
+///
+/// With the default shader, fully transparent pixels don't count, so
+/// images with holes in them make masks with holes in them.  Custom
+/// shaders can `discard` whatever pixels they like.
+///
+/// ```rust,no_run
+/// # use ggez::*;
+/// # use ggez::graphics::*;
+/// # fn main() -> GameResult {
+/// # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+/// # let minimap = Image::solid(ctx, 64, WHITE)?;
+/// let circle = Mesh::new_circle(ctx, DrawMode::fill(), [64.0, 64.0], 64.0, 0.5, WHITE)?;
+/// graphics::clear_stencil(ctx, 0);
+/// graphics::draw_stencil(ctx, &circle, DrawParam::default(), StencilAction::Replace(1))?;
+/// graphics::set_stencil_test(ctx, Some(StencilTest::Equal(1)));
+/// graphics::draw(ctx, &minimap, DrawParam::default())?;
+/// graphics::set_stencil_test(ctx, None);
+/// # Ok(())
+/// # }
+/// ```
+pub fn draw_stencil<D, T>(
+    ctx: &mut Context,
+    drawable: &D,
+    params: T,
+    action: StencilAction,
+) -> GameResult
+where
+    D: Drawable,
+    T: Into<DrawParam>,
+{
+    let gfx = &mut ctx.gfx_context;
+    let previous = gfx.stencil;
+    gfx.stencil = StencilMode::write(action);
+    let default_shader = gfx.current_shader.borrow().is_none();
+    if default_shader {
+        *gfx.current_shader.borrow_mut() = Some(gfx.stencil_shader);
+    }
+
+    let result = drawable.draw(ctx, params.into());
+
+    // Batched draws keep the state they were queued with,
+    // so there's no need to flush them.
+    let gfx = &mut ctx.gfx_context;
+    if default_shader {
+        *gfx.current_shader.borrow_mut() = None;
+    }
+    gfx.stencil = previous;
+    result
+}
+
+/// Makes everything drawn from now on only touch the pixels that pass
+/// the given test, or with `None`, all of them again.
+pub fn set_stencil_test(ctx: &mut Context, test: Option<StencilTest>) {
+    let gfx = &mut ctx.gfx_context;
+    gfx.stencil_test = test;
+    gfx.stencil = StencilMode::test(test);
+}
+
+/// Returns the stencil test drawing currently has to pass, if any.
+pub fn stencil_test(ctx: &Context) -> Option<StencilTest> {
+    ctx.gfx_context.stencil_test
+}
+
+/// Sets the whole stencil buffer of whatever is being drawn to
+/// to the given value.
+pub fn clear_stencil(ctx: &mut Context, value: u8) {
+    let gfx = &mut ctx.gfx_context;
+    gfx.prepare_clear();
+    gfx.encoder.clear_stencil(&gfx.data.stencil.0, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_test_stencil_test_modes() {
+        let tests = [
+            StencilTest::Equal(3),
+            StencilTest::NotEqual(3),
+            StencilTest::Less(3),
+            StencilTest::LessOrEqual(3),
+            StencilTest::Greater(3),
+            StencilTest::GreaterOrEqual(3),
+        ];
+        for test in &tests {
+            assert_eq!(test.invert().invert(), *test);
+            let (mode, value) = StencilMode::test(Some(*test));
+            assert_eq!(value, 3);
+            // Every mode has to have pipeline state objects made for it.
+            assert!(StencilMode::ALL.contains(&mode));
+        }
+        // GL puts the reference value on the left hand side.
+        assert_eq!(
+            StencilMode::test(Some(StencilTest::Less(3))),
+            (StencilMode::Test(Comparison::Greater), 3)
+        );
+        assert_eq!(StencilMode::test(None), (StencilMode::Off, 0));
+
+        let actions = [
+            StencilAction::Replace(7),
+            StencilAction::Invert,
+            StencilAction::Increment,
+            StencilAction::Decrement,
+        ];
+        for action in &actions {
+            let (mode, _) = StencilMode::write(*action);
+            assert!(!mode.writes_color());
+            assert!(StencilMode::ALL.contains(&mode));
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! Reading maps and tilesets in Tiled's JSON formats.
+
+use std::collections::HashMap;
+
+use serde_json::{self, Value};
+
+use super::{
+    decode_gid, decode_tile_data, map_error, parse_orientation, tile_layer, Group, Layer,
+    LayerData, MapData, MapObject, ObjectShape, TilesetData, TilesetDef, TilesetSource,
+};
+use crate::error::GameResult;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_orientation() -> String {
+    "orthogonal".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonMap {
+    #[serde(default = "default_orientation")]
+    orientation: String,
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    infinite: bool,
+    // Tilesets are either embedded or just a `source`, which is
+    // easier to tell apart before picking a struct to read them into.
+    #[serde(default)]
+    tilesets: Vec<Value>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: JsonProperties,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    name: String,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    columns: u32,
+    image: Option<String>,
+    #[serde(default)]
+    imagewidth: u32,
+    #[serde(default)]
+    imageheight: u32,
+    tileoffset: Option<JsonPoint>,
+    #[serde(default)]
+    properties: JsonProperties,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+struct JsonPoint {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "default_true")]
+    visible: bool,
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    #[serde(default)]
+    properties: JsonProperties,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    data: Option<JsonData>,
+    #[serde(default)]
+    encoding: String,
+    #[serde(default)]
+    compression: String,
+    chunks: Option<Value>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+/// Tile layer data is either an array of gids or an encoded string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonData {
+    Gids(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    x: f32,
+    #[serde(default)]
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "default_true")]
+    visible: bool,
+    gid: Option<u32>,
+    #[serde(default)]
+    ellipse: bool,
+    #[serde(default)]
+    point: bool,
+    polygon: Option<Vec<JsonPoint>>,
+    polyline: Option<Vec<JsonPoint>>,
+    #[serde(default)]
+    properties: JsonProperties,
+}
+
+/// Properties are a list of `{name, type, value}`s, or in
+/// files from older versions of Tiled, an object.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonProperties {
+    List(Vec<JsonProperty>),
+    Map(HashMap<String, Value>),
+}
+
+impl Default for JsonProperties {
+    fn default() -> Self {
+        JsonProperties::List(Vec::new())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: Value,
+}
+
+impl From<JsonProperties> for HashMap<String, String> {
+    fn from(properties: JsonProperties) -> Self {
+        fn to_string(value: Value) -> String {
+            match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            }
+        }
+        match properties {
+            JsonProperties::List(list) => list
+                .into_iter()
+                .map(|p| (p.name, to_string(p.value)))
+                .collect(),
+            JsonProperties::Map(map) => map
+                .into_iter()
+                .map(|(name, value)| (name, to_string(value)))
+                .collect(),
+        }
+    }
+}
+
+fn from_slice<'a, T: serde::Deserialize<'a>>(data: &'a [u8]) -> GameResult<T> {
+    serde_json::from_slice(data).map_err(|e| map_error(format!("Invalid JSON: {}", e)))
+}
+
+pub(super) fn parse_map(data: &[u8]) -> GameResult<MapData> {
+    let map: JsonMap = from_slice(data)?;
+    if map.infinite {
+        return Err(map_error("infinite maps are not supported"));
+    }
+    let tilesets = map
+        .tilesets
+        .into_iter()
+        .map(|tileset| {
+            let first_gid = tileset
+                .get("firstgid")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| map_error("Tileset has no firstgid"))?;
+            let source = match tileset.get("source").and_then(Value::as_str) {
+                Some(source) => TilesetSource::External(source.to_string()),
+                None => TilesetSource::Embedded(tileset_def(
+                    serde_json::from_value(tileset)
+                        .map_err(|e| map_error(format!("Invalid tileset: {}", e)))?,
+                )?),
+            };
+            Ok(TilesetData {
+                first_gid: first_gid as u32,
+                source,
+            })
+        })
+        .collect::<GameResult<Vec<_>>>()?;
+    let first_gids: Vec<u32> = tilesets.iter().map(|t| t.first_gid).collect();
+    let mut layers = Vec::new();
+    convert_layers(map.layers, &first_gids, Group::root(), &mut layers)?;
+    Ok(MapData {
+        orientation: parse_orientation(&map.orientation)?,
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        tilesets,
+        layers,
+        properties: map.properties.into(),
+    })
+}
+
+pub(super) fn parse_tileset(data: &[u8]) -> GameResult<TilesetDef> {
+    tileset_def(from_slice(data)?)
+}
+
+fn tileset_def(tileset: JsonTileset) -> GameResult<TilesetDef> {
+    let image = tileset
+        .image
+        .ok_or_else(|| map_error("tilesets made of a collection of images are not supported"))?;
+    let offset = tileset
+        .tileoffset
+        .map(|p| mint::Vector2 { x: p.x, y: p.y })
+        .unwrap_or(mint::Vector2 { x: 0.0, y: 0.0 });
+    let mut def = TilesetDef {
+        name: tileset.name,
+        tile_width: tileset.tilewidth,
+        tile_height: tileset.tileheight,
+        spacing: tileset.spacing,
+        margin: tileset.margin,
+        tile_count: tileset.tilecount,
+        columns: tileset.columns,
+        offset,
+        properties: tileset.properties.into(),
+        image,
+    };
+    def.fill_in_grid(tileset.imagewidth, tileset.imageheight);
+    Ok(def)
+}
+
+/// Converts the given layers, flattening group layers.
+fn convert_layers(
+    json_layers: Vec<JsonLayer>,
+    first_gids: &[u32],
+    group: Group,
+    layers: &mut Vec<Layer>,
+) -> GameResult {
+    for layer in json_layers {
+        let group = group.nest(layer.visible, layer.opacity, layer.offsetx, layer.offsety);
+        let data = match layer.kind.as_str() {
+            "tilelayer" => {
+                if layer.chunks.is_some() {
+                    return Err(map_error("infinite maps are not supported"));
+                }
+                let gids = match layer.data {
+                    Some(JsonData::Gids(gids)) => gids,
+                    Some(JsonData::Encoded(text)) => {
+                        decode_tile_data(&text, Some(&layer.encoding), Some(&layer.compression))?
+                    }
+                    None => return Err(map_error("Tile layer has no data")),
+                };
+                LayerData::Tiles(tile_layer(layer.width, layer.height, &gids, first_gids)?)
+            }
+            "objectgroup" => LayerData::Objects(
+                layer
+                    .objects
+                    .into_iter()
+                    .map(|object| convert_object(object, first_gids))
+                    .collect::<GameResult<_>>()?,
+            ),
+            "group" => {
+                convert_layers(layer.layers, first_gids, group, layers)?;
+                continue;
+            }
+            "imagelayer" => {
+                debug!(
+                    "Skipping image layer {:?}, they are not supported",
+                    layer.name
+                );
+                continue;
+            }
+            _ => continue,
+        };
+        layers.push(group.layer(layer.name, layer.properties.into(), data));
+    }
+    Ok(())
+}
+
+fn convert_object(object: JsonObject, first_gids: &[u32]) -> GameResult<MapObject> {
+    let points = |points: Vec<JsonPoint>| {
+        points
+            .into_iter()
+            .map(|p| mint::Point2 { x: p.x, y: p.y })
+            .collect()
+    };
+    let shape = if object.ellipse {
+        ObjectShape::Ellipse
+    } else if object.point {
+        ObjectShape::Point
+    } else if let Some(polygon) = object.polygon {
+        ObjectShape::Polygon(points(polygon))
+    } else if let Some(polyline) = object.polyline {
+        ObjectShape::Polyline(points(polyline))
+    } else {
+        ObjectShape::Rectangle
+    };
+    let tile = match object.gid {
+        Some(gid) => decode_gid(gid, first_gids)?,
+        None => None,
+    };
+    let kind = if object.kind.is_empty() {
+        object.class
+    } else {
+        object.kind
+    };
+    Ok(MapObject {
+        id: object.id,
+        name: object.name,
+        kind,
+        x: object.x,
+        y: object.y,
+        width: object.width,
+        height: object.height,
+        rotation: object.rotation.to_radians(),
+        visible: object.visible,
+        tile,
+        shape,
+        properties: object.properties.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::tilemap::Orientation;
+
+    const MAP: &str = r#"{
+        "compressionlevel": -1,
+        "height": 2,
+        "infinite": false,
+        "layers": [
+            {
+                "data": [1, 2, 3, 2147483652, 0, 66],
+                "height": 2, "width": 3,
+                "id": 1, "name": "ground", "opacity": 1, "type": "tilelayer",
+                "visible": true, "x": 0, "y": 0
+            },
+            {
+                "id": 2, "name": "decor", "type": "group", "opacity": 0.5, "offsetx": 4,
+                "visible": true, "x": 0, "y": 0,
+                "layers": [
+                    {
+                        "compression": "zlib", "encoding": "base64",
+                        "data": "eJxjZGBgYGKAAGYGhgYAAMQAhw==",
+                        "height": 2, "width": 2,
+                        "id": 3, "name": "props", "opacity": 0.5, "type": "tilelayer",
+                        "offsety": 2, "visible": false, "x": 0, "y": 0
+                    }
+                ]
+            },
+            {
+                "draworder": "topdown", "id": 4, "name": "spawns",
+                "objects": [
+                    { "id": 1, "name": "player", "type": "spawn", "point": true,
+                      "x": 16, "y": 8, "width": 0, "height": 0, "rotation": 0, "visible": true },
+                    { "id": 2, "name": "path", "class": "route",
+                      "polyline": [{ "x": 0, "y": 0 }, { "x": 4, "y": 8 }],
+                      "x": 1, "y": 2, "width": 0, "height": 0, "rotation": 180, "visible": true,
+                      "properties": [
+                          { "name": "speed", "type": "float", "value": 1.5 },
+                          { "name": "loop", "type": "bool", "value": true },
+                          { "name": "next", "type": "string", "value": "path2" }
+                      ] }
+                ],
+                "opacity": 1, "type": "objectgroup", "visible": true, "x": 0, "y": 0
+            },
+            { "id": 5, "image": "sky.png", "name": "sky", "type": "imagelayer",
+              "opacity": 1, "visible": true, "x": 0, "y": 0 }
+        ],
+        "orientation": "orthogonal",
+        "properties": [{ "name": "music", "type": "file", "value": "town.ogg" }],
+        "renderorder": "right-down",
+        "tileheight": 16,
+        "tilesets": [
+            { "firstgid": 1, "source": "terrain.tsj" },
+            { "columns": 2, "firstgid": 65, "image": "props.png", "imageheight": 99,
+              "imagewidth": 67, "margin": 1, "name": "props", "spacing": 1,
+              "tilecount": 4, "tileheight": 48, "tilewidth": 32,
+              "tileoffset": { "x": 0, "y": 4 } }
+        ],
+        "tilewidth": 16,
+        "type": "map",
+        "version": "1.10",
+        "width": 3
+    }"#;
+
+    #[test]
+    fn headless_test_parse_json() {
+        let map = parse_map(MAP.as_bytes()).unwrap();
+        assert_eq!(map.orientation, Orientation::Orthogonal);
+        assert_eq!((map.tile_width, map.tile_height), (16, 16));
+        assert_eq!(map.properties["music"], "town.ogg");
+        match map.tilesets[0].source {
+            TilesetSource::External(ref source) => assert_eq!(source, "terrain.tsj"),
+            ref other => panic!("Expected an external tileset, got {:?}", other),
+        }
+        match map.tilesets[1].source {
+            TilesetSource::Embedded(ref def) => {
+                assert_eq!(map.tilesets[1].first_gid, 65);
+                assert_eq!(def.image, "props.png");
+                assert_eq!(def.offset, mint::Vector2 { x: 0.0, y: 4.0 });
+            }
+            ref other => panic!("Expected an embedded tileset, got {:?}", other),
+        }
+
+        // The image layer is skipped and the group flattened.
+        assert_eq!(map.layers.len(), 3);
+        match map.layers[0].data {
+            LayerData::Tiles(ref tiles) => {
+                let flipped = tiles.get(0, 1).unwrap();
+                assert_eq!((flipped.tileset, flipped.id), (0, 3));
+                assert!(flipped.flip_horizontal);
+                assert_eq!(tiles.get(2, 1).unwrap().tileset, 1);
+            }
+            ref other => panic!("Expected tiles, got {:?}", other),
+        }
+        let props = &map.layers[1];
+        assert_eq!((props.visible, props.opacity), (false, 0.25));
+        assert_eq!(props.offset, mint::Vector2 { x: 4.0, y: 2.0 });
+        match props.data {
+            LayerData::Tiles(ref tiles) => {
+                assert_eq!(tiles.get(1, 0).unwrap().id, 1);
+                assert_eq!(tiles.get(0, 1), None);
+            }
+            ref other => panic!("Expected tiles, got {:?}", other),
+        }
+        let objects = match map.layers[2].data {
+            LayerData::Objects(ref objects) => objects,
+            ref other => panic!("Expected objects, got {:?}", other),
+        };
+        assert_eq!(objects[0].shape, ObjectShape::Point);
+        assert_eq!(objects[1].kind, "route");
+        assert_eq!(objects[1].rotation, std::f32::consts::PI);
+        assert_eq!(
+            objects[1].shape,
+            ObjectShape::Polyline(vec![
+                mint::Point2 { x: 0.0, y: 0.0 },
+                mint::Point2 { x: 4.0, y: 8.0 },
+            ])
+        );
+        assert_eq!(objects[1].properties["speed"], "1.5");
+        assert_eq!(objects[1].properties["loop"], "true");
+        assert_eq!(objects[1].properties["next"], "path2");
+    }
+
+    #[test]
+    fn headless_test_parse_json_tileset() {
+        let tileset = r#"{ "columns": 8, "image": "../images/terrain.png", "imageheight": 128,
+            "imagewidth": 128, "margin": 0, "name": "terrain", "spacing": 0,
+            "tilecount": 64, "tileheight": 16, "tilewidth": 16, "type": "tileset",
+            "properties": { "biome": "grass" } }"#;
+        let def = parse_tileset(tileset.as_bytes()).unwrap();
+        assert_eq!(def.image, "../images/terrain.png");
+        assert_eq!((def.columns, def.tile_count), (8, 64));
+        assert_eq!(def.properties["biome"], "grass");
+
+        let bad = MAP.replace("[1, 2, 3, 2147483652, 0, 66]", "[1, 2, 3]");
+        assert!(parse_map(bad.as_bytes()).is_err());
+    }
+}
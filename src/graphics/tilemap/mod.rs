@@ -0,0 +1,1148 @@
+//! Loading and drawing maps made with the [Tiled](https://www.mapeditor.org/)
+//! map editor.
+//!
+//! [`TileMap::new()`](struct.TileMap.html#method.new) reads a map saved
+//! either as TMX (XML) or as JSON from the ggez
+//! [`filesystem`](../../filesystem/index.html), along with any external
+//! tilesets it uses and their images.  Orthogonal and isometric maps are
+//! supported, with any number of tile layers, object layers and group
+//! layers (which are flattened into the layers they contain).
+//! Tile layer data can be stored as XML, CSV, or base64 with or without
+//! zlib or gzip compression.
+//!
+//! ```rust,no_run
+//! # use ggez::*;
+//! # use ggez::graphics::*;
+//! # use ggez::graphics::tilemap::{LayerData, TileMap};
+//! # fn main() -> GameResult {
+//! # let ctx = &mut ContextBuilder::new("foo", "bar").build().unwrap().0;
+//! let map = TileMap::new(ctx, "/maps/level1.tmx")?;
+//! graphics::draw(ctx, &map, DrawParam::new().dest([-64.0, -32.0]))?;
+//! for layer in map.layers() {
+//!     if let LayerData::Objects(ref objects) = layer.data {
+//!         for object in objects {
+//!             println!("{} at {},{}", object.name, object.x, object.y);
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each tile layer is drawn with one [`SpriteBatch`](../spritebatch/struct.SpriteBatch.html)
+//! per tileset it uses, holding only the tiles that are on screen; tiles
+//! outside of [`screen_coordinates()`](../fn.screen_coordinates.html)
+//! (after undoing the `DrawParam` and current transform) are skipped, and
+//! the batches are only refilled when the visible part of the map changes.
+//! Because of that, if a single layer uses more than one tileset, all the
+//! tiles from the first tileset are drawn before those of the second and
+//! so on.
+//!
+//! Object layers aren't drawn; they are there for your game to place
+//! things, spawn points, collision shapes and such.  Infinite maps, image
+//! layers and tilesets made of a collection of separate images are not
+//! supported.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use nalgebra as na;
+
+use crate::context::Context;
+use crate::error::{GameError, GameResult};
+use crate::graphics::spritebatch::SpriteBatch;
+use crate::graphics::{self, BlendMode, Color, DrawParam, DrawTransform, Drawable, Image, Rect};
+
+mod json;
+mod tmx;
+
+/// Set on a tile's gid if it is flipped horizontally.
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+/// Set on a tile's gid if it is flipped vertically.
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+/// Set on a tile's gid if it is flipped along its top-left to
+/// bottom-right diagonal.
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+/// Only used on hexagonal maps, which we don't support, but it
+/// still needs masking out.
+const ROTATED_HEXAGONAL_120: u32 = 0x1000_0000;
+const FLIP_FLAGS: u32 =
+    FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL_120;
+
+/// How the tiles of a map are laid out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// A plain grid of rectangles.
+    Orthogonal,
+    /// Diamond shaped tiles, with the first tile at the top and rows
+    /// going down and to the left.
+    Isometric,
+}
+
+/// A placed tile.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// The tile's global id, without the flip flags.
+    pub gid: u32,
+    /// The index of the tileset the tile is from in
+    /// [`TileMap::tilesets()`](struct.TileMap.html#method.tilesets).
+    pub tileset: usize,
+    /// The tile's id within its tileset.
+    pub id: u32,
+    /// Whether the tile is mirrored horizontally.
+    pub flip_horizontal: bool,
+    /// Whether the tile is mirrored vertically.
+    pub flip_vertical: bool,
+    /// Whether the tile is mirrored along its top-left to bottom-right
+    /// diagonal.  This is applied before the other two flips, and
+    /// together they are how Tiled rotates tiles.
+    pub flip_diagonal: bool,
+}
+
+/// A tileset: one image cut up into a grid of tiles.
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    /// The tileset's name.
+    pub name: String,
+    /// The gid of the tileset's first tile.
+    pub first_gid: u32,
+    /// The width of each tile in pixels.
+    pub tile_width: u32,
+    /// The height of each tile in pixels.
+    pub tile_height: u32,
+    /// The space between tiles in the image, in pixels.
+    pub spacing: u32,
+    /// The space around the tiles at the edges of the image, in pixels.
+    pub margin: u32,
+    /// The number of tiles in the tileset.
+    pub tile_count: u32,
+    /// The number of tiles in each row of the image.
+    pub columns: u32,
+    /// How far tiles from this tileset are moved when drawn, in pixels.
+    pub offset: mint::Vector2<f32>,
+    /// Custom properties.
+    pub properties: HashMap<String, String>,
+    /// The tileset's image.
+    pub image: Image,
+}
+
+impl Tileset {
+    /// Returns the region of the image that the tile with the given
+    /// (local) id is in, in pixels.
+    pub fn tile_rect(&self, id: u32) -> Option<Rect> {
+        if id >= self.tile_count || self.columns == 0 {
+            return None;
+        }
+        let (col, row) = (id % self.columns, id / self.columns);
+        Some(Rect::new(
+            (self.margin + col * (self.tile_width + self.spacing)) as f32,
+            (self.margin + row * (self.tile_height + self.spacing)) as f32,
+            self.tile_width as f32,
+            self.tile_height as f32,
+        ))
+    }
+
+    /// Returns the region of the image that the tile with the given
+    /// (local) id is in, as a fraction of the whole image, the same as
+    /// [`DrawParam::src`](../struct.DrawParam.html#structfield.src).
+    pub fn tile_src(&self, id: u32) -> Option<Rect> {
+        let dimensions = self.image.dimensions();
+        self.tile_rect(id)
+            .map(|r| Rect::fraction(r.x, r.y, r.w, r.h, &dimensions))
+    }
+}
+
+/// A layer of a map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    /// The layer's name.
+    pub name: String,
+    /// Whether the layer is shown.  Hidden layers aren't drawn.
+    pub visible: bool,
+    /// The layer's opacity, from 0.0 to 1.0.
+    pub opacity: f32,
+    /// How far the layer is moved when drawn, in pixels.
+    pub offset: mint::Vector2<f32>,
+    /// Custom properties.
+    pub properties: HashMap<String, String>,
+    /// What's on the layer.
+    pub data: LayerData,
+}
+
+/// What's on a [`Layer`](struct.Layer.html).
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayerData {
+    /// A grid of tiles.
+    Tiles(TileLayer),
+    /// Free-standing objects.
+    Objects(Vec<MapObject>),
+}
+
+/// A grid of tiles, stored row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileLayer {
+    /// The width of the layer in tiles.
+    pub width: u32,
+    /// The height of the layer in tiles.
+    pub height: u32,
+    /// The tiles, row by row; `None` where there is no tile.
+    pub tiles: Vec<Option<Tile>>,
+}
+
+impl TileLayer {
+    /// Returns the tile at the given column and row, if there is one.
+    pub fn get(&self, x: u32, y: u32) -> Option<Tile> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.tiles[(y * self.width + x) as usize]
+    }
+}
+
+/// An object on an object layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapObject {
+    /// The object's unique id.
+    pub id: u32,
+    /// The object's name.
+    pub name: String,
+    /// The object's type (called "class" in newer versions of Tiled).
+    pub kind: String,
+    /// The x coordinate of the object, in pixels.
+    pub x: f32,
+    /// The y coordinate of the object, in pixels.  For tile
+    /// objects, this is the bottom of the tile rather than the top.
+    pub y: f32,
+    /// The width of the object, in pixels.
+    pub width: f32,
+    /// The height of the object, in pixels.
+    pub height: f32,
+    /// The rotation of the object around (x, y), clockwise, in radians.
+    pub rotation: f32,
+    /// Whether the object is shown.
+    pub visible: bool,
+    /// The tile shown by the object, if it's a tile object.
+    pub tile: Option<Tile>,
+    /// The object's shape.
+    pub shape: ObjectShape,
+    /// Custom properties.
+    pub properties: HashMap<String, String>,
+}
+
+/// The shape of a [`MapObject`](struct.MapObject.html).
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObjectShape {
+    /// A rectangle of the object's width and height.
+    Rectangle,
+    /// An ellipse filling the object's width and height.
+    Ellipse,
+    /// A single point at the object's position.
+    Point,
+    /// A closed polygon, with points relative to the object's position.
+    Polygon(Vec<mint::Point2<f32>>),
+    /// An open line, with points relative to the object's position.
+    Polyline(Vec<mint::Point2<f32>>),
+}
+
+/// A map made with the Tiled map editor.
+///
+/// See the [module docs](index.html) for details.
+#[derive(Debug)]
+pub struct TileMap {
+    orientation: Orientation,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Layer>,
+    properties: HashMap<String, String>,
+    batches: RefCell<Vec<LayerBatches>>,
+    blend_mode: Option<BlendMode>,
+}
+
+/// The sprite batches a tile layer is drawn with, one per tileset,
+/// along with the range of tiles that are currently in them.
+#[derive(Debug, Default)]
+struct LayerBatches {
+    range: Option<TileRange>,
+    batches: Vec<Option<SpriteBatch>>,
+}
+
+/// A rectangle of tiles, from `(x0, y0)` up to but not including `(x1, y1)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TileRange {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl TileMap {
+    /// Loads a TMX or JSON map from the given path, along with any
+    /// external tilesets and images it refers to.  Those are looked for
+    /// relative to the file that refers to them.
+    pub fn new<P: AsRef<Path>>(ctx: &mut Context, path: P) -> GameResult<Self> {
+        let path = path.as_ref();
+        let map = parse_map(&read_file(ctx, path)?)?;
+        let dir = parent_dir(path);
+        let mut tilesets = Vec::with_capacity(map.tilesets.len());
+        for tileset in map.tilesets {
+            let (def, base) = match tileset.source {
+                TilesetSource::Embedded(def) => (def, dir.clone()),
+                TilesetSource::External(source) => {
+                    let tileset_path = resolve_path(&dir, &source);
+                    let def = parse_tileset(&read_file(ctx, &tileset_path)?)?;
+                    (def, parent_dir(&tileset_path))
+                }
+            };
+            let image = Image::new(ctx, resolve_path(&base, &def.image))?;
+            tilesets.push(Tileset {
+                name: def.name,
+                first_gid: tileset.first_gid,
+                tile_width: def.tile_width,
+                tile_height: def.tile_height,
+                spacing: def.spacing,
+                margin: def.margin,
+                tile_count: def.tile_count,
+                columns: def.columns,
+                offset: def.offset,
+                properties: def.properties,
+                image,
+            });
+        }
+        let batches = map.layers.iter().map(|_| LayerBatches::default()).collect();
+        Ok(TileMap {
+            orientation: map.orientation,
+            width: map.width,
+            height: map.height,
+            tile_width: map.tile_width,
+            tile_height: map.tile_height,
+            tilesets,
+            layers: map.layers,
+            properties: map.properties,
+            batches: RefCell::new(batches),
+            blend_mode: None,
+        })
+    }
+
+    /// Returns how the map's tiles are laid out.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Returns the size of the map in tiles, as (width, height).
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the size of a map cell in pixels, as (width, height).
+    pub fn tile_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    /// Returns the map's tilesets, in order of their `first_gid`.
+    pub fn tilesets(&self) -> &[Tileset] {
+        &self.tilesets
+    }
+
+    /// Returns the map's layers, from the bottom up.
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Returns the first layer with the given name.
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    /// Returns the map's custom properties.
+    pub fn properties(&self) -> &HashMap<String, String> {
+        &self.properties
+    }
+
+    /// Returns the top left corner of the bounding box of the
+    /// cell at the given column and row, in pixels.
+    pub fn tile_to_pixel(&self, x: u32, y: u32) -> mint::Point2<f32> {
+        let (tw, th) = (self.tile_width as f32, self.tile_height as f32);
+        let (x, y) = (x as f32, y as f32);
+        match self.orientation {
+            Orientation::Orthogonal => mint::Point2 {
+                x: x * tw,
+                y: y * th,
+            },
+            Orientation::Isometric => mint::Point2 {
+                x: (x - y + self.height as f32 - 1.0) * tw / 2.0,
+                y: (x + y) * th / 2.0,
+            },
+        }
+    }
+
+    /// Returns the column and row of the cell containing the given
+    /// point, in pixels, or `None` if it's outside of the map.
+    pub fn pixel_to_tile(&self, point: mint::Point2<f32>) -> Option<(u32, u32)> {
+        let (x, y) = cell_at(
+            self.orientation,
+            self.height,
+            self.tile_width as f32,
+            self.tile_height as f32,
+            point,
+        );
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some((x as u32, y as u32))
+    }
+
+    /// Draws a single layer of the map.  Drawing an object layer or
+    /// a hidden layer does nothing.
+    pub fn draw_layer(&self, ctx: &mut Context, index: usize, param: DrawParam) -> GameResult {
+        let layer = self.layers.get(index).ok_or_else(|| {
+            let msg = format!(
+                "Tried to draw layer {} of a map with {} layers",
+                index,
+                self.layers.len()
+            );
+            GameError::RenderError(msg)
+        })?;
+        let tiles = match layer.data {
+            LayerData::Tiles(ref tiles) if layer.visible => tiles,
+            _ => return Ok(()),
+        };
+        let range = match self.view(ctx, param, layer.offset) {
+            Some(view) => visible_range(
+                self.orientation,
+                (tiles.width, tiles.height),
+                (self.tile_width as f32, self.tile_height as f32),
+                view,
+            ),
+            None => return Ok(()),
+        };
+
+        let mut all_batches = self.batches.borrow_mut();
+        let layer_batches = &mut all_batches[index];
+        if layer_batches.range != Some(range) {
+            self.fill_batches(layer_batches, layer, tiles, range);
+        }
+        for batch in layer_batches.batches.iter_mut().flatten() {
+            batch.set_blend_mode(self.blend_mode);
+            batch.draw(ctx, param)?;
+        }
+        Ok(())
+    }
+
+    /// Works out which part of the map, in pixels, ends up on the
+    /// screen when drawn with the given `DrawParam`, grown to cover any
+    /// tiles that stick out of their cells.
+    fn view(
+        &self,
+        ctx: &Context,
+        param: DrawParam,
+        layer_offset: mint::Vector2<f32>,
+    ) -> Option<Rect> {
+        let screen = graphics::screen_coordinates(ctx);
+        let m: DrawTransform = param.into();
+        let inverse = (m.matrix * ctx.gfx_context.transform()).try_inverse()?;
+        let corners = [
+            (screen.x, screen.y),
+            (screen.x + screen.w, screen.y),
+            (screen.x, screen.y + screen.h),
+            (screen.x + screen.w, screen.y + screen.h),
+        ];
+        let bounds = bounding_box(corners.iter().map(|&(x, y)| {
+            let p = inverse * na::Vector4::new(x, y, 0.0, 1.0);
+            (p.x, p.y)
+        }));
+
+        // Tiles bigger than the cells they are in, or moved by their
+        // tileset's offset, can poke into the view from outside it.
+        let margin = self.tilesets.iter().fold(0.0f32, |margin, t| {
+            let w = t.tile_width as f32 - self.tile_width as f32;
+            let h = t.tile_height as f32 - self.tile_height as f32;
+            margin
+                .max(w)
+                .max(h)
+                .max(t.offset.x.abs())
+                .max(t.offset.y.abs())
+        });
+        Some(Rect::new(
+            bounds.x - layer_offset.x - margin,
+            bounds.y - layer_offset.y - margin,
+            bounds.w + 2.0 * margin,
+            bounds.h + 2.0 * margin,
+        ))
+    }
+
+    /// Refills the batches for a layer with the tiles in `range`.
+    fn fill_batches(
+        &self,
+        layer_batches: &mut LayerBatches,
+        layer: &Layer,
+        tiles: &TileLayer,
+        range: TileRange,
+    ) {
+        if layer_batches.batches.len() != self.tilesets.len() {
+            layer_batches.batches = self.tilesets.iter().map(|_| None).collect();
+        }
+        for batch in layer_batches.batches.iter_mut().flatten() {
+            batch.clear();
+        }
+        let color = Color::new(1.0, 1.0, 1.0, layer.opacity);
+        for y in range.y0..range.y1 {
+            for x in range.x0..range.x1 {
+                let tile = match tiles.get(x, y) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let tileset = &self.tilesets[tile.tileset];
+                let src = match tileset.tile_src(tile.id) {
+                    Some(src) => src,
+                    None => continue,
+                };
+                let param = self
+                    .tile_param(tileset, tile, x, y, layer.offset)
+                    .src(src)
+                    .color(color);
+                let _ = layer_batches.batches[tile.tileset]
+                    .get_or_insert_with(|| SpriteBatch::new(tileset.image.clone()))
+                    .add(param);
+            }
+        }
+        layer_batches.range = Some(range);
+    }
+
+    /// Works out where to draw a tile, flipped as it should be.
+    fn tile_param(
+        &self,
+        tileset: &Tileset,
+        tile: Tile,
+        x: u32,
+        y: u32,
+        layer_offset: mint::Vector2<f32>,
+    ) -> DrawParam {
+        let cell = self.tile_to_pixel(x, y);
+        let (tw, th) = (self.tile_width as f32, self.tile_height as f32);
+        let (w, h) = (tileset.tile_width as f32, tileset.tile_height as f32);
+        // Tiles bigger than a cell stick out upwards, and on isometric
+        // maps they are centered horizontally.
+        let left = match self.orientation {
+            Orientation::Orthogonal => cell.x,
+            Orientation::Isometric => cell.x + (tw - w) / 2.0,
+        };
+        let top = cell.y + th - h;
+        // Flip around the tile's center, so it stays in place.
+        let center = mint::Point2 {
+            x: left + w / 2.0 + tileset.offset.x + layer_offset.x,
+            y: top + h / 2.0 + tileset.offset.y + layer_offset.y,
+        };
+        let fx = if tile.flip_horizontal { -1.0 } else { 1.0 };
+        let fy = if tile.flip_vertical { -1.0 } else { 1.0 };
+        let param = DrawParam::new().dest(center).offset([0.5, 0.5]);
+        if tile.flip_diagonal {
+            // Mirroring along the diagonal and then flipping is the
+            // same as scaling and then turning a quarter clockwise.
+            param.rotation(std::f32::consts::FRAC_PI_2).scale([fy, -fx])
+        } else {
+            param.scale([fx, fy])
+        }
+    }
+}
+
+impl Drawable for TileMap {
+    fn draw(&self, ctx: &mut Context, param: DrawParam) -> GameResult {
+        for i in 0..self.layers.len() {
+            self.draw_layer(ctx, i, param)?;
+        }
+        Ok(())
+    }
+
+    fn dimensions(&self, _ctx: &mut Context) -> Option<Rect> {
+        let (tw, th) = (self.tile_width as f32, self.tile_height as f32);
+        let (w, h) = (self.width as f32, self.height as f32);
+        Some(match self.orientation {
+            Orientation::Orthogonal => Rect::new(0.0, 0.0, w * tw, h * th),
+            Orientation::Isometric => Rect::new(0.0, 0.0, (w + h) * tw / 2.0, (w + h) * th / 2.0),
+        })
+    }
+
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.blend_mode = mode;
+    }
+
+    fn blend_mode(&self) -> Option<BlendMode> {
+        self.blend_mode
+    }
+}
+
+/// Returns the fractional column and row of the cell containing
+/// the given point.
+fn cell_at(
+    orientation: Orientation,
+    map_height: u32,
+    tile_width: f32,
+    tile_height: f32,
+    point: mint::Point2<f32>,
+) -> (f32, f32) {
+    match orientation {
+        Orientation::Orthogonal => (point.x / tile_width, point.y / tile_height),
+        Orientation::Isometric => {
+            // The top corner of the first tile is here.
+            let x = point.x - map_height as f32 * tile_width / 2.0;
+            (
+                x / tile_width + point.y / tile_height,
+                point.y / tile_height - x / tile_width,
+            )
+        }
+    }
+}
+
+/// Returns the tiles with a cell touching the given rectangle.
+fn visible_range(
+    orientation: Orientation,
+    (width, height): (u32, u32),
+    (tile_width, tile_height): (f32, f32),
+    view: Rect,
+) -> TileRange {
+    let corners = [
+        mint::Point2 {
+            x: view.x,
+            y: view.y,
+        },
+        mint::Point2 {
+            x: view.x + view.w,
+            y: view.y,
+        },
+        mint::Point2 {
+            x: view.x,
+            y: view.y + view.h,
+        },
+        mint::Point2 {
+            x: view.x + view.w,
+            y: view.y + view.h,
+        },
+    ];
+    let cells = bounding_box(
+        corners
+            .iter()
+            .map(|&corner| cell_at(orientation, height, tile_width, tile_height, corner)),
+    );
+    let clamp = |v: f32, limit: u32| v.max(0.0).min(limit as f32) as u32;
+    let x0 = clamp(cells.x.floor(), width);
+    let y0 = clamp(cells.y.floor(), height);
+    TileRange {
+        x0,
+        y0,
+        x1: clamp((cells.x + cells.w).floor() + 1.0, width).max(x0),
+        y1: clamp((cells.y + cells.h).floor() + 1.0, height).max(y0),
+    }
+}
+
+/// Returns the smallest rectangle containing all the given points.
+fn bounding_box<I: Iterator<Item = (f32, f32)>>(mut points: I) -> Rect {
+    let (x, y) = points.next().unwrap_or((0.0, 0.0));
+    let (min, max) = points.fold(((x, y), (x, y)), |(min, max), (x, y)| {
+        ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+    });
+    Rect::new(min.0, min.1, max.0 - min.0, max.1 - min.1)
+}
+
+/// Turns a raw gid, flip flags and all, into a `Tile`.
+fn decode_gid(raw: u32, first_gids: &[u32]) -> GameResult<Option<Tile>> {
+    let gid = raw & !FLIP_FLAGS;
+    if gid == 0 {
+        return Ok(None);
+    }
+    let tileset = first_gids
+        .iter()
+        .enumerate()
+        .filter(|&(_, &first)| first <= gid)
+        .max_by_key(|&(_, &first)| first)
+        .map(|(i, _)| i)
+        .ok_or_else(|| map_error(format!("Tile {} isn't in any tileset", gid)))?;
+    Ok(Some(Tile {
+        gid,
+        tileset,
+        id: gid - first_gids[tileset],
+        flip_horizontal: raw & FLIPPED_HORIZONTALLY != 0,
+        flip_vertical: raw & FLIPPED_VERTICALLY != 0,
+        flip_diagonal: raw & FLIPPED_DIAGONALLY != 0,
+    }))
+}
+
+/// Decodes the text of a tile layer's data in the given
+/// encoding and compression into raw gids.
+fn decode_tile_data(
+    text: &str,
+    encoding: Option<&str>,
+    compression: Option<&str>,
+) -> GameResult<Vec<u32>> {
+    match encoding {
+        Some("csv") => text
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<u32>()
+                    .map_err(|_| map_error(format!("Invalid tile {:?} in CSV layer data", s)))
+            })
+            .collect(),
+        Some("base64") => {
+            let bytes = decode_base64(text)?;
+            let bytes = match compression {
+                None | Some("") => bytes,
+                Some("zlib") => inflate::inflate_bytes_zlib(&bytes).map_err(map_error)?,
+                Some("gzip") => gunzip(&bytes).map_err(map_error)?,
+                Some(other) => {
+                    return Err(map_error(format!(
+                        "Unsupported layer data compression {:?}",
+                        other
+                    )));
+                }
+            };
+            if bytes.len() % 4 != 0 {
+                return Err(map_error(format!(
+                    "Layer data is {} bytes long, which isn't a whole number of tiles",
+                    bytes.len()
+                )));
+            }
+            Ok(bytes
+                .chunks(4)
+                .map(|b| {
+                    u32::from(b[0])
+                        | u32::from(b[1]) << 8
+                        | u32::from(b[2]) << 16
+                        | u32::from(b[3]) << 24
+                })
+                .collect())
+        }
+        other => Err(map_error(format!(
+            "Unsupported layer data encoding {:?}",
+            other
+        ))),
+    }
+}
+
+fn decode_base64(text: &str) -> GameResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            c => {
+                return Err(map_error(format!(
+                    "Invalid character {:?} in base64 layer data",
+                    c as char
+                )));
+            }
+        };
+        buffer = buffer << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decompresses a gzip stream; just a header around deflate data.
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+    let invalid = || "Invalid gzip layer data".to_string();
+    if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 {
+        return Err(invalid());
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or_else(invalid)?;
+        pos += 2 + (usize::from(len[0]) | usize::from(len[1]) << 8);
+    }
+    for &flag in &[FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or_else(invalid)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    // The last 8 bytes are a checksum and the uncompressed size.
+    let deflated = data.get(pos..data.len() - 8).ok_or_else(invalid)?;
+    inflate::inflate_bytes(deflated)
+}
+
+fn map_error<S: Into<String>>(msg: S) -> GameError {
+    GameError::ResourceLoadError(format!("Could not load tilemap: {}", msg.into()))
+}
+
+fn read_file(ctx: &mut Context, path: &Path) -> GameResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut file = ctx.filesystem.open(path)?;
+    let _ = file.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .unwrap_or_else(|| Path::new("/"))
+        .to_path_buf()
+}
+
+/// Joins a path from a map file onto the directory it's relative to,
+/// getting rid of any `..`s, which Tiled is fond of.
+fn resolve_path(dir: &Path, relative: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::ParentDir => {
+                let _ = path.pop();
+            }
+            Component::CurDir => (),
+            Component::RootDir => path = PathBuf::from("/"),
+            Component::Normal(part) => path.push(part),
+            Component::Prefix(_) => (),
+        }
+    }
+    path
+}
+
+/// A map as read from a file, before any tilesets are loaded.
+#[derive(Debug)]
+struct MapData {
+    orientation: Orientation,
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<TilesetData>,
+    layers: Vec<Layer>,
+    properties: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+struct TilesetData {
+    first_gid: u32,
+    source: TilesetSource,
+}
+
+#[derive(Debug)]
+enum TilesetSource {
+    Embedded(TilesetDef),
+    External(String),
+}
+
+/// A tileset as read from a file, before its image is loaded.
+#[derive(Debug)]
+struct TilesetDef {
+    name: String,
+    tile_width: u32,
+    tile_height: u32,
+    spacing: u32,
+    margin: u32,
+    tile_count: u32,
+    columns: u32,
+    offset: mint::Vector2<f32>,
+    properties: HashMap<String, String>,
+    image: String,
+}
+
+impl TilesetDef {
+    /// Older files leave out the number of columns and tiles,
+    /// so work them out from the size of the image.
+    fn fill_in_grid(&mut self, image_width: u32, image_height: u32) {
+        let (margin, spacing) = (self.margin, self.spacing);
+        let fit = |size: u32, tile: u32| {
+            (size.saturating_sub(2 * margin) + spacing) / cmp::max(tile + spacing, 1)
+        };
+        if self.columns == 0 {
+            self.columns = fit(image_width, self.tile_width);
+        }
+        if self.tile_count == 0 {
+            self.tile_count = self.columns * fit(image_height, self.tile_height);
+        }
+    }
+}
+
+/// The visibility, opacity and offset of the group layers a
+/// layer is in, which all apply to the layer itself.
+#[derive(Copy, Clone, Debug)]
+struct Group {
+    visible: bool,
+    opacity: f32,
+    offset: mint::Vector2<f32>,
+}
+
+impl Group {
+    fn root() -> Self {
+        Group {
+            visible: true,
+            opacity: 1.0,
+            offset: mint::Vector2 { x: 0.0, y: 0.0 },
+        }
+    }
+
+    /// Combines a layer's own settings with the group's.
+    fn nest(self, visible: bool, opacity: f32, offset_x: f32, offset_y: f32) -> Self {
+        Group {
+            visible: self.visible && visible,
+            opacity: self.opacity * opacity,
+            offset: mint::Vector2 {
+                x: self.offset.x + offset_x,
+                y: self.offset.y + offset_y,
+            },
+        }
+    }
+
+    fn layer(self, name: String, properties: HashMap<String, String>, data: LayerData) -> Layer {
+        Layer {
+            name,
+            visible: self.visible,
+            opacity: self.opacity,
+            offset: self.offset,
+            properties,
+            data,
+        }
+    }
+}
+
+/// Turns a layer's raw gids into a `TileLayer`.
+fn tile_layer(width: u32, height: u32, gids: &[u32], first_gids: &[u32]) -> GameResult<TileLayer> {
+    if gids.len() != width as usize * height as usize {
+        return Err(map_error(format!(
+            "A {}x{} layer has {} tiles",
+            width,
+            height,
+            gids.len()
+        )));
+    }
+    let tiles = gids
+        .iter()
+        .map(|&gid| decode_gid(gid, first_gids))
+        .collect::<GameResult<_>>()?;
+    Ok(TileLayer {
+        width,
+        height,
+        tiles,
+    })
+}
+
+fn parse_orientation(orientation: &str) -> GameResult<Orientation> {
+    match orientation {
+        "orthogonal" => Ok(Orientation::Orthogonal),
+        "isometric" => Ok(Orientation::Isometric),
+        other => Err(map_error(format!("{} maps are not supported", other))),
+    }
+}
+
+/// Works out whether the data is TMX or JSON and parses it.
+fn parse_map(data: &[u8]) -> GameResult<MapData> {
+    match first_char(data) {
+        Some(b'<') => tmx::parse_map(data),
+        Some(b'{') => json::parse_map(data),
+        _ => Err(map_error("not a TMX or JSON map")),
+    }
+}
+
+fn parse_tileset(data: &[u8]) -> GameResult<TilesetDef> {
+    match first_char(data) {
+        Some(b'<') => tmx::parse_tileset(data),
+        Some(b'{') => json::parse_tileset(data),
+        _ => Err(map_error("not a TSX or JSON tileset")),
+    }
+}
+
+fn first_char(data: &[u8]) -> Option<u8> {
+    let data = if data.starts_with(b"\xEF\xBB\xBF") {
+        &data[3..]
+    } else {
+        data
+    };
+    data.iter().cloned().find(|c| !c.is_ascii_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_test_decode_gid() {
+        let first_gids = [1, 65, 129];
+        assert_eq!(decode_gid(0, &first_gids).unwrap(), None);
+        let tile = decode_gid(70 | FLIPPED_HORIZONTALLY | FLIPPED_DIAGONALLY, &first_gids)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tile,
+            Tile {
+                gid: 70,
+                tileset: 1,
+                id: 5,
+                flip_horizontal: true,
+                flip_vertical: false,
+                flip_diagonal: true,
+            }
+        );
+        assert_eq!(decode_gid(129, &first_gids).unwrap().unwrap().id, 0);
+        assert!(decode_gid(3, &[10]).is_err());
+    }
+
+    #[test]
+    fn headless_test_decode_tile_data() {
+        let expected = vec![1, 2, 0, 0x8000_0003];
+        assert_eq!(
+            decode_tile_data("1,2,\n0,2147483651\n", Some("csv"), None).unwrap(),
+            expected
+        );
+        // The same gids, little-endian, in base64...
+        assert_eq!(
+            decode_tile_data(" AQAAAAIAAAAAAAAAAwAAgA== ", Some("base64"), None).unwrap(),
+            expected
+        );
+        // ...compressed with zlib...
+        assert_eq!(
+            decode_tile_data("eJxjZGBgYGKAAGYGhgYAAMQAhw==", Some("base64"), Some("zlib")).unwrap(),
+            expected
+        );
+        // ...and with gzip.
+        assert_eq!(
+            decode_tile_data(
+                "H4sIAAAAAAACA2NkYGBgYoAAZgaGBgCVaOVREAAAAA==",
+                Some("base64"),
+                Some("gzip")
+            )
+            .unwrap(),
+            expected
+        );
+        assert!(decode_tile_data("AQAAAA==", Some("base64"), Some("zstd")).is_err());
+        assert!(decode_tile_data("AQAA", Some("base64"), None).is_err());
+    }
+
+    #[test]
+    fn headless_test_decode_base64() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        // Padding is optional...
+        assert_eq!(decode_base64("TWE").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ").unwrap(), b"M");
+        // ...and whitespace is ignored, wherever it is.
+        assert_eq!(
+            decode_base64("\n    TWFu\r\n    TW\tE=\n  ").unwrap(),
+            b"ManMa"
+        );
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert!(decode_base64("TW-u").is_err());
+    }
+
+    #[test]
+    fn headless_test_gunzip_header_flags() {
+        let expected = decode_base64("AQAAAAIAAAAAAAAAAwAAgA==").unwrap();
+        let gzipped = decode_base64("H4sIAAAAAAACA2NkYGBgYoAAZgaGBgCVaOVREAAAAA==").unwrap();
+        assert_eq!(gunzip(&gzipped).unwrap(), expected);
+
+        // The same data with optional header fields added after the fixed
+        // ten bytes of the header.
+        let (header, deflated) = gzipped.split_at(10);
+        let with_fields = |flags: u8, fields: &[u8]| {
+            let mut data = header.to_vec();
+            data[3] = flags;
+            data.extend_from_slice(fields);
+            data.extend_from_slice(deflated);
+            data
+        };
+        // FEXTRA is a length and that many bytes, FNAME and FCOMMENT are
+        // zero-terminated and FHCRC is a checksum of the header, which
+        // isn't checked.
+        let extra = with_fields(0x04, b"\x03\x00abc");
+        let name = with_fields(0x08, b"map.bin\0");
+        let comment = with_fields(0x10, b"a comment\0");
+        let hcrc = with_fields(0x02, b"\x12\x34");
+        let all = with_fields(0x1e, b"\x03\x00abcmap.bin\0a comment\0\x12\x34");
+        for data in &[extra, name, comment, hcrc, all] {
+            assert_eq!(gunzip(data).unwrap(), expected);
+        }
+
+        // Fields running past the end.
+        assert!(gunzip(&with_fields(0x04, b"\xff\x00abc")).is_err());
+        let mut unterminated = header.to_vec();
+        unterminated[3] = 0x08;
+        unterminated.extend_from_slice(&[b'x'; 20]);
+        assert!(gunzip(&unterminated).is_err());
+        assert!(gunzip(&gzipped[..12]).is_err());
+    }
+
+    #[test]
+    fn headless_test_visible_range() {
+        let ortho = visible_range(
+            Orientation::Orthogonal,
+            (100, 50),
+            (16.0, 16.0),
+            Rect::new(40.0, -10.0, 64.0, 48.0),
+        );
+        assert_eq!(
+            ortho,
+            TileRange {
+                x0: 2,
+                y0: 0,
+                x1: 7,
+                y1: 3
+            }
+        );
+        let outside = visible_range(
+            Orientation::Orthogonal,
+            (10, 10),
+            (16.0, 16.0),
+            Rect::new(-100.0, -100.0, 50.0, 50.0),
+        );
+        assert_eq!(outside.x0, outside.x1);
+
+        // On a 10x10 isometric map of 32x16 tiles, the first tile's top
+        // corner is at (160, 0), and tile (2, 3) is centered on (144, 48).
+        let iso = visible_range(
+            Orientation::Isometric,
+            (10, 10),
+            (32.0, 16.0),
+            Rect::new(142.0, 46.0, 4.0, 4.0),
+        );
+        assert!(iso.x0 <= 2 && iso.x1 > 2 && iso.y0 <= 3 && iso.y1 > 3);
+        assert!(iso.x1 - iso.x0 <= 2 && iso.y1 - iso.y0 <= 2);
+        let (x, y) = cell_at(
+            Orientation::Isometric,
+            10,
+            32.0,
+            16.0,
+            mint::Point2 { x: 144.0, y: 48.0 },
+        );
+        assert_eq!((x.floor(), y.floor()), (2.0, 3.0));
+    }
+
+    #[test]
+    fn headless_test_resolve_path() {
+        assert_eq!(
+            resolve_path(Path::new("/maps/world"), "../tiles/./grass.png"),
+            PathBuf::from("/maps/tiles/grass.png")
+        );
+        assert_eq!(
+            resolve_path(Path::new("/maps"), "/tiles.tsx"),
+            PathBuf::from("/tiles.tsx")
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! Reading maps and tilesets in Tiled's XML formats, TMX and TSX.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use super::{
+    decode_gid, decode_tile_data, map_error, parse_orientation, tile_layer, Group, Layer,
+    LayerData, MapData, MapObject, ObjectShape, TileLayer, TilesetData, TilesetDef, TilesetSource,
+};
+use crate::error::GameResult;
+
+/// Just enough of a DOM to pick maps apart with.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    fn parse_attr<T: FromStr>(&self, name: &str) -> GameResult<Option<T>> {
+        match self.attr(name) {
+            Some(value) => value.trim().parse().map(Some).map_err(|_| {
+                map_error(format!(
+                    "Invalid value {:?} for {} of <{}>",
+                    value, name, self.name
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn attr_or<T: FromStr>(&self, name: &str, default: T) -> GameResult<T> {
+        Ok(self.parse_attr(name)?.unwrap_or(default))
+    }
+
+    fn required<T: FromStr>(&self, name: &str) -> GameResult<T> {
+        self.parse_attr(name)?
+            .ok_or_else(|| map_error(format!("<{}> has no {}", self.name, name)))
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+fn parse_document(data: &[u8]) -> GameResult<Element> {
+    let mut stack: Vec<Element> = Vec::new();
+    for event in EventReader::new(data) {
+        let event = event.map_err(|e| map_error(format!("Invalid XML: {}", e)))?;
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|a| (a.name.local_name, a.value))
+                    .collect(),
+                ..Element::default()
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().expect("Unbalanced XML; should never happen");
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            _ => (),
+        }
+    }
+    Err(map_error("Empty XML document"))
+}
+
+pub(super) fn parse_map(data: &[u8]) -> GameResult<MapData> {
+    let root = parse_document(data)?;
+    if root.name != "map" {
+        return Err(map_error(format!("Expected <map>, found <{}>", root.name)));
+    }
+    if root.attr("infinite") == Some("1") {
+        return Err(map_error("infinite maps are not supported"));
+    }
+    let tilesets = root
+        .children("tileset")
+        .map(|tileset| {
+            let source = match tileset.attr("source") {
+                Some(source) => TilesetSource::External(source.to_string()),
+                None => TilesetSource::Embedded(tileset_def(tileset)?),
+            };
+            Ok(TilesetData {
+                first_gid: tileset.required("firstgid")?,
+                source,
+            })
+        })
+        .collect::<GameResult<Vec<_>>>()?;
+    let first_gids: Vec<u32> = tilesets.iter().map(|t| t.first_gid).collect();
+    let mut layers = Vec::new();
+    parse_layers(&root, &first_gids, Group::root(), &mut layers)?;
+    Ok(MapData {
+        orientation: parse_orientation(root.attr("orientation").unwrap_or("orthogonal"))?,
+        width: root.required("width")?,
+        height: root.required("height")?,
+        tile_width: root.required("tilewidth")?,
+        tile_height: root.required("tileheight")?,
+        tilesets,
+        layers,
+        properties: properties(&root),
+    })
+}
+
+pub(super) fn parse_tileset(data: &[u8]) -> GameResult<TilesetDef> {
+    let root = parse_document(data)?;
+    if root.name != "tileset" {
+        return Err(map_error(format!(
+            "Expected <tileset>, found <{}>",
+            root.name
+        )));
+    }
+    tileset_def(&root)
+}
+
+fn tileset_def(tileset: &Element) -> GameResult<TilesetDef> {
+    let image = tileset
+        .child("image")
+        .ok_or_else(|| map_error("tilesets made of a collection of images are not supported"))?;
+    let offset = match tileset.child("tileoffset") {
+        Some(offset) => mint::Vector2 {
+            x: offset.attr_or("x", 0.0)?,
+            y: offset.attr_or("y", 0.0)?,
+        },
+        None => mint::Vector2 { x: 0.0, y: 0.0 },
+    };
+    let mut def = TilesetDef {
+        name: tileset.attr("name").unwrap_or_default().to_string(),
+        tile_width: tileset.required("tilewidth")?,
+        tile_height: tileset.required("tileheight")?,
+        spacing: tileset.attr_or("spacing", 0)?,
+        margin: tileset.attr_or("margin", 0)?,
+        tile_count: tileset.attr_or("tilecount", 0)?,
+        columns: tileset.attr_or("columns", 0)?,
+        offset,
+        properties: properties(tileset),
+        image: image.required("source")?,
+    };
+    def.fill_in_grid(image.attr_or("width", 0)?, image.attr_or("height", 0)?);
+    Ok(def)
+}
+
+/// Reads all the layers in `parent`, flattening group layers.
+fn parse_layers(
+    parent: &Element,
+    first_gids: &[u32],
+    group: Group,
+    layers: &mut Vec<Layer>,
+) -> GameResult {
+    for element in &parent.children {
+        let name = element.attr("name").unwrap_or_default().to_string();
+        let group = group.nest(
+            element.attr("visible") != Some("0"),
+            element.attr_or("opacity", 1.0)?,
+            element.attr_or("offsetx", 0.0)?,
+            element.attr_or("offsety", 0.0)?,
+        );
+        let data = match element.name.as_str() {
+            "layer" => LayerData::Tiles(parse_tile_layer(element, first_gids)?),
+            "objectgroup" => LayerData::Objects(
+                element
+                    .children("object")
+                    .map(|object| parse_object(object, first_gids))
+                    .collect::<GameResult<_>>()?,
+            ),
+            "group" => {
+                parse_layers(element, first_gids, group, layers)?;
+                continue;
+            }
+            "imagelayer" => {
+                debug!("Skipping image layer {:?}, they are not supported", name);
+                continue;
+            }
+            _ => continue,
+        };
+        layers.push(group.layer(name, properties(element), data));
+    }
+    Ok(())
+}
+
+fn parse_tile_layer(layer: &Element, first_gids: &[u32]) -> GameResult<TileLayer> {
+    let width = layer.required("width")?;
+    let height = layer.required("height")?;
+    let data = layer
+        .child("data")
+        .ok_or_else(|| map_error("Tile layer has no <data>"))?;
+    if data.child("chunk").is_some() {
+        return Err(map_error("infinite maps are not supported"));
+    }
+    let gids = match data.attr("encoding") {
+        None => data
+            .children("tile")
+            .map(|tile| tile.attr_or("gid", 0))
+            .collect::<GameResult<Vec<u32>>>()?,
+        encoding => decode_tile_data(&data.text, encoding, data.attr("compression"))?,
+    };
+    tile_layer(width, height, &gids, first_gids)
+}
+
+fn parse_object(object: &Element, first_gids: &[u32]) -> GameResult<MapObject> {
+    let shape = if object.child("ellipse").is_some() {
+        ObjectShape::Ellipse
+    } else if object.child("point").is_some() {
+        ObjectShape::Point
+    } else if let Some(polygon) = object.child("polygon") {
+        ObjectShape::Polygon(parse_points(polygon)?)
+    } else if let Some(polyline) = object.child("polyline") {
+        ObjectShape::Polyline(parse_points(polyline)?)
+    } else {
+        ObjectShape::Rectangle
+    };
+    let tile = match object.parse_attr("gid")? {
+        Some(gid) => decode_gid(gid, first_gids)?,
+        None => None,
+    };
+    let kind = object
+        .attr("type")
+        .or_else(|| object.attr("class"))
+        .unwrap_or_default();
+    Ok(MapObject {
+        id: object.attr_or("id", 0)?,
+        name: object.attr("name").unwrap_or_default().to_string(),
+        kind: kind.to_string(),
+        x: object.attr_or("x", 0.0)?,
+        y: object.attr_or("y", 0.0)?,
+        width: object.attr_or("width", 0.0)?,
+        height: object.attr_or("height", 0.0)?,
+        rotation: object.attr_or("rotation", 0.0f32)?.to_radians(),
+        visible: object.attr("visible") != Some("0"),
+        tile,
+        shape,
+        properties: properties(object),
+    })
+}
+
+/// Parses a list of points in the form `"0,0 10,5 3,-2"`.
+fn parse_points(element: &Element) -> GameResult<Vec<mint::Point2<f32>>> {
+    let points: &str = element.attr("points").unwrap_or_default();
+    points
+        .split_whitespace()
+        .map(|point| {
+            let mut coords = point.split(',').map(|c| c.parse::<f32>());
+            match (coords.next(), coords.next(), coords.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Ok(mint::Point2 { x, y }),
+                _ => Err(map_error(format!("Invalid point {:?}", point))),
+            }
+        })
+        .collect()
+}
+
+fn properties(element: &Element) -> HashMap<String, String> {
+    element
+        .child("properties")
+        .map(|properties| {
+            properties
+                .children("property")
+                .filter_map(|p| {
+                    // Multi-line strings go in the element's text instead.
+                    let value = p.attr("value").unwrap_or(&p.text);
+                    p.attr("name")
+                        .map(|name| (name.to_string(), value.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::tilemap::Orientation;
+
+    const MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" orientation="isometric" renderorder="right-down" width="3" height="2" tilewidth="32" tileheight="16" infinite="0">
+ <properties>
+  <property name="music" value="/sounds/town.ogg"/>
+ </properties>
+ <tileset firstgid="1" source="../tiles/terrain.tsx"/>
+ <tileset firstgid="65" name="props" tilewidth="32" tileheight="48" spacing="1" margin="1">
+  <tileoffset x="0" y="4"/>
+  <image source="props.png" width="67" height="99"/>
+ </tileset>
+ <layer name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,3,
+2147483652,0,66
+</data>
+ </layer>
+ <group name="decor" opacity="0.5" offsetx="4">
+  <layer name="props" width="3" height="2" visible="0" opacity="0.5" offsety="2">
+   <data>
+    <tile gid="65"/><tile/><tile/>
+    <tile/><tile/><tile gid="1073741889"/>
+   </data>
+  </layer>
+ </group>
+ <objectgroup name="spawns">
+  <object id="1" name="player" type="spawn" x="16" y="8"><point/></object>
+  <object id="2" name="pond" x="0" y="0" width="20" height="10" rotation="90"><ellipse/>
+   <properties><property name="note">deep
+water</property></properties>
+  </object>
+  <object id="3" x="1" y="2"><polygon points="0,0 10,0 10,-5.5"/></object>
+  <object id="4" gid="2147483714" x="32" y="48" width="32" height="48"/>
+ </objectgroup>
+</map>
+"#;
+
+    #[test]
+    fn headless_test_parse_tmx() {
+        let map = parse_map(MAP.as_bytes()).unwrap();
+        assert_eq!(map.orientation, Orientation::Isometric);
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.properties["music"], "/sounds/town.ogg");
+        match map.tilesets[0].source {
+            TilesetSource::External(ref source) => assert_eq!(source, "../tiles/terrain.tsx"),
+            ref other => panic!("Expected an external tileset, got {:?}", other),
+        }
+        let props = match map.tilesets[1].source {
+            TilesetSource::Embedded(ref def) => def,
+            ref other => panic!("Expected an embedded tileset, got {:?}", other),
+        };
+        // Worked out from the image size: (67 - 2 + 1) / 33 by (99 - 2 + 1) / 49.
+        assert_eq!((props.columns, props.tile_count), (2, 4));
+        assert_eq!(props.offset, mint::Vector2 { x: 0.0, y: 4.0 });
+
+        assert_eq!(map.layers.len(), 3);
+        let ground = match map.layers[0].data {
+            LayerData::Tiles(ref tiles) => tiles,
+            ref other => panic!("Expected tiles, got {:?}", other),
+        };
+        let flipped = ground.get(0, 1).unwrap();
+        assert_eq!((flipped.tileset, flipped.id), (0, 3));
+        assert!(flipped.flip_horizontal && !flipped.flip_vertical);
+        assert_eq!(ground.get(1, 1), None);
+        assert_eq!(ground.get(2, 1).unwrap().tileset, 1);
+
+        let decor = &map.layers[1];
+        assert_eq!(decor.name, "props");
+        assert!(!decor.visible);
+        assert_eq!(decor.opacity, 0.25);
+        assert_eq!(decor.offset, mint::Vector2 { x: 4.0, y: 2.0 });
+        match decor.data {
+            LayerData::Tiles(ref tiles) => {
+                assert_eq!(tiles.get(0, 0).unwrap().id, 0);
+                assert!(tiles.get(2, 1).unwrap().flip_vertical);
+            }
+            ref other => panic!("Expected tiles, got {:?}", other),
+        }
+
+        let objects = match map.layers[2].data {
+            LayerData::Objects(ref objects) => objects,
+            ref other => panic!("Expected objects, got {:?}", other),
+        };
+        assert_eq!(objects[0].kind, "spawn");
+        assert_eq!(objects[0].shape, ObjectShape::Point);
+        assert_eq!(objects[1].shape, ObjectShape::Ellipse);
+        assert_eq!(objects[1].rotation, std::f32::consts::FRAC_PI_2);
+        assert_eq!(objects[1].properties["note"], "deep\nwater");
+        assert_eq!(
+            objects[2].shape,
+            ObjectShape::Polygon(vec![
+                mint::Point2 { x: 0.0, y: 0.0 },
+                mint::Point2 { x: 10.0, y: 0.0 },
+                mint::Point2 { x: 10.0, y: -5.5 },
+            ])
+        );
+        let tile = objects[3].tile.unwrap();
+        assert_eq!((tile.tileset, tile.id, tile.flip_horizontal), (1, 1, true));
+    }
+
+    #[test]
+    fn headless_test_parse_tsx() {
+        let tsx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.2" name="terrain" tilewidth="16" tileheight="16" tilecount="64" columns="8">
+ <image source="terrain.png" width="128" height="128"/>
+</tileset>"#;
+        let def = parse_tileset(tsx.as_bytes()).unwrap();
+        assert_eq!(def.name, "terrain");
+        assert_eq!(def.image, "terrain.png");
+        assert_eq!((def.columns, def.tile_count), (8, 64));
+
+        let collection = r#"<tileset name="things" tilewidth="16" tileheight="16" tilecount="1" columns="0">
+ <tile id="0"><image source="thing.png" width="16" height="16"/></tile>
+</tileset>"#;
+        assert!(parse_tileset(collection.as_bytes()).is_err());
+        let infinite = MAP.replace("infinite=\"0\"", "infinite=\"1\"");
+        assert!(parse_map(infinite.as_bytes()).is_err());
+    }
+}
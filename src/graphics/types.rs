@@ -179,6 +179,20 @@ impl Rect {
         let h = f32::max(self.bottom(), other.bottom()) - y;
         Rect { x, y, w, h }
     }
+
+    /// Returns the `Rect` covered by both of these two `Rect`s,
+    /// or `None` if they don't overlap.
+    pub fn intersection(self, other: Rect) -> Option<Rect> {
+        let x = f32::max(self.x, other.x);
+        let y = f32::max(self.y, other.y);
+        let w = f32::min(self.right(), other.right()) - x;
+        let h = f32::min(self.bottom(), other.bottom()) - y;
+        if w < 0.0 || h < 0.0 {
+            None
+        } else {
+            Some(Rect { x, y, w, h })
+        }
+    }
 }
 
 impl approx::AbsDiffEq for Rect {
@@ -657,6 +671,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn headless_test_rect_intersection() {
+        let a = Rect::new(0.0, 0.0, 2.0, 2.0);
+        let b = Rect::new(1.0, -1.0, 2.0, 2.0);
+        assert_relative_eq!(a.intersection(b).unwrap(), Rect::new(1.0, 0.0, 1.0, 1.0));
+        assert_relative_eq!(a.intersection(a).unwrap(), a);
+        assert_eq!(a.intersection(Rect::new(3.0, 0.0, 1.0, 1.0)), None);
+    }
+
     #[test]
     fn headless_test_rect_rotate() {
         {
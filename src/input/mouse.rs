@@ -92,23 +92,30 @@ pub fn set_cursor_hidden(ctx: &mut Context, hidden: bool) {
 
 /// Get the current position of the mouse cursor, in pixels.
 /// Complement to [`set_position()`](fn.set_position.html).
-/// Uses strictly window-only coordinates.
+/// Uses strictly window-only coordinates, or virtual screen
+/// coordinates if a [virtual resolution](../../graphics/fn.set_virtual_resolution.html)
+/// is set; use
+/// [`Camera2D::screen_to_world()`](../../graphics/struct.Camera2D.html#method.screen_to_world)
+/// to find out where that is in your game world.
 pub fn position(ctx: &Context) -> mint::Point2<f32> {
     ctx.mouse_context.last_position.into()
 }
 
 /// Set the current position of the mouse cursor, in pixels.
-/// Uses strictly window-only coordinates.
+/// Uses strictly window-only coordinates, or virtual screen
+/// coordinates if a [virtual resolution](../../graphics/fn.set_virtual_resolution.html)
+/// is set.
 pub fn set_position<P>(ctx: &mut Context, point: P) -> GameResult<()>
 where
     P: Into<mint::Point2<f32>>,
 {
-    let mintpoint = point.into();
-    ctx.mouse_context.last_position = Point2::from(mintpoint);
+    let point = Point2::from(point.into());
+    ctx.mouse_context.last_position = point;
+    let window_point = ctx.gfx_context.screen_to_window(point);
     graphics::window(ctx)
         .set_cursor_position(dpi::LogicalPosition {
-            x: f64::from(mintpoint.x),
-            y: f64::from(mintpoint.y),
+            x: f64::from(window_point.x),
+            y: f64::from(window_point.y),
         })
         .map_err(|_| GameError::WindowError("Couldn't set mouse cursor position!".to_owned()))
 }
@@ -106,14 +106,280 @@ fn save_screenshot() {
     save_screenshot_test(c);
 }
 
-// Not supported, see https://github.com/ggez/ggez/issues/751
-// #[test]
-// fn save_screenshot_with_antialiasing() {
-//     let cb = ContextBuilder::new("ggez_unit_tests", "ggez")
-//         .window_setup(conf::WindowSetup::default().samples(conf::NumSamples::Eight));
-//     let (c, _e) = &mut tests::make_context_from_contextbuilder(cb);
-//     save_screenshot_test(c);
-// }
+#[test]
+fn save_screenshot_with_antialiasing() {
+    let cb = ContextBuilder::new("ggez_unit_tests", "ggez")
+        .window_setup(conf::WindowSetup::default().samples(conf::NumSamples::Eight));
+    let (c, _e) = &mut tests::make_context_from_contextbuilder(cb);
+    save_screenshot_test(c);
+}
+
+/// Anti-aliased canvases get resolved into their image
+/// once drawing switches back to the screen.
+#[test]
+fn antialiased_canvas_resolves() {
+    let (c, _e) = &mut tests::make_context();
+    let canvas = graphics::Canvas::new(c, 64, 32, conf::NumSamples::Four).unwrap();
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    graphics::set_canvas(c, Some(&canvas));
+    graphics::clear(c, red);
+    graphics::set_canvas(c, None);
+
+    assert_eq!(canvas.image().width(), 64);
+    let rgba_buf = canvas.image().to_rgba8(c).unwrap();
+    assert_eq!(rgba_buf.len(), 64 * 32 * 4);
+    assert_eq!(&rgba_buf[..4], &[255, 0, 0, 255]);
+
+    // The canvas can be drawn like any other.
+    graphics::draw(c, &canvas, graphics::DrawParam::new()).unwrap();
+    graphics::present(c).unwrap();
+}
+
+/// Screenshots of anti-aliased canvases in another format than the
+/// screen get resolved and converted.
+#[test]
+fn screenshot_antialiased_float_canvas() {
+    let (c, _e) = &mut tests::make_context();
+    let canvas = graphics::CanvasBuilder::new(64, 32)
+        .samples(conf::NumSamples::Four)
+        .format(graphics::CanvasFormat::Rgba16Float)
+        .build(c)
+        .unwrap();
+    graphics::set_canvas(c, Some(&canvas));
+    graphics::clear(c, Color::new(1.0, 0.0, 0.0, 1.0));
+    let screenshot = graphics::screenshot(c).unwrap();
+    graphics::set_canvas(c, None);
+
+    let rgba_buf = screenshot.to_rgba8(c).unwrap();
+    assert!(rgba_buf.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+}
+
+/// Post-processing passes cover the whole output, and blurring
+/// a single color leaves it as it is.
+#[test]
+fn postprocess_chain_covers_output() {
+    use crate::graphics::postprocess::{Blur, Effect, PostProcess};
+    let (c, _e) = &mut tests::make_context();
+    let input = graphics::Canvas::new(c, 64, 32, conf::NumSamples::One).unwrap();
+    let output = graphics::Canvas::new(c, 64, 32, conf::NumSamples::One).unwrap();
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    graphics::set_canvas(c, Some(&input));
+    graphics::clear(c, red);
+
+    let mut post = PostProcess::new(graphics::CanvasFormat::Screen);
+    let mut blur = Blur::new(c, 4.0).unwrap();
+    let mut blur_again = Blur::new(c, 2.0).unwrap();
+    let effects: &mut [&mut dyn Effect] = &mut [&mut blur, &mut blur_again];
+    post.apply(c, &input, Some(&output), effects).unwrap();
+    graphics::set_canvas(c, None);
+
+    let rgba_buf = output.image().to_rgba8(c).unwrap();
+    assert!(rgba_buf.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+}
+
+/// Lights only light up what is in reach and not behind an occluder.
+#[test]
+fn lighting_casts_shadows() {
+    use crate::graphics::lighting::{Light, Lighting, Occluder};
+    let (c, _e) = &mut tests::make_context();
+    let (width, height) = graphics::drawable_size(c);
+    let (cx, cy) = (width / 2.0, height / 2.0);
+    let mut lighting = Lighting::new(c).unwrap();
+    let light = Light::point([cx, cy], graphics::WHITE, height / 2.0);
+    // A wall just right of the light, shadowing everything past it.
+    let wall = Occluder::polygon(
+        c,
+        &[
+            [cx + 20.0, cy - 40.0],
+            [cx + 30.0, cy - 40.0],
+            [cx + 30.0, cy + 40.0],
+            [cx + 20.0, cy + 40.0],
+        ],
+    )
+    .unwrap();
+    lighting.render(c, &[light], &[wall]).unwrap();
+
+    let rgba_buf = lighting.canvas().image().to_rgba8(c).unwrap();
+    let w = width as usize;
+    let sample = |x: f32, y: f32| get_rgba_sample(&rgba_buf, w, Point2::new(x, y)).0;
+    // Canvas images are upside down, but everything here is symmetric.
+    assert!(sample(cx - 10.0, cy) > 128);
+    assert_eq!(sample(cx + 60.0, cy), 0);
+    assert_eq!(sample(1.0, 1.0), 0);
+}
+
+/// Rendering lights goes back to drawing to whatever canvas was being
+/// drawn to before.
+#[test]
+fn lighting_keeps_canvas() {
+    use crate::graphics::lighting::{Light, Lighting};
+    let (c, _e) = &mut tests::make_context();
+    let (width, height) = graphics::drawable_size(c);
+    let mut lighting = Lighting::new(c).unwrap();
+    let canvas = graphics::Canvas::with_window_size(c).unwrap();
+    graphics::set_canvas(c, Some(&canvas));
+    let light = Light::point([width / 2.0, height / 2.0], graphics::WHITE, 50.0);
+    lighting.render(c, &[light], &[]).unwrap();
+    graphics::clear(c, Color::new(1.0, 0.0, 0.0, 1.0));
+    graphics::set_canvas(c, None);
+
+    let rgba_buf = canvas.image().to_rgba8(c).unwrap();
+    let w = width as usize;
+    let sample = get_rgba_sample(&rgba_buf, w, Point2::new(1.0, 1.0));
+    assert_eq!((255, 0, 0, 255), sample);
+}
+
+/// Nine-slices keep their borders the size they are in the image
+/// and stretch the middle.
+#[test]
+fn nineslice_stretches_center() {
+    let (c, _e) = &mut tests::make_context();
+    graphics::clear(c, Color::new(0.0, 0.0, 0.0, 1.0));
+    // White borders two pixels wide around a blue center.
+    let mut rgba = Vec::new();
+    for y in 0..6 {
+        for x in 0..6 {
+            let border = !(2..4).contains(&x) || !(2..4).contains(&y);
+            rgba.extend_from_slice(if border { &[255, 255, 255, 255] } else { &[0, 0, 255, 255] });
+        }
+    }
+    let mut image = graphics::Image::from_rgba8(c, 6, 6, &rgba).unwrap();
+    image.set_filter(graphics::FilterMode::Nearest);
+    let panel = graphics::NineSlice::new(image, graphics::Insets::uniform(2.0), 40.0, 30.0)
+        .center(graphics::SliceMode::Tile);
+    graphics::draw(c, &panel, graphics::DrawParam::new().dest([10.0, 10.0])).unwrap();
+
+    let screenshot = graphics::screenshot(c).unwrap();
+    let rgba_buf = screenshot.to_rgba8(c).unwrap();
+    let w = graphics::drawable_size(c).0 as usize;
+    let sample = |x: f32, y: f32| get_rgba_sample(&rgba_buf, w, Point2::new(x, y));
+    assert_eq!(graphics::WHITE.to_rgba(), sample(10.5, 10.5));
+    assert_eq!(graphics::WHITE.to_rgba(), sample(48.5, 38.5));
+    assert_eq!((0, 0, 255, 255), sample(12.5, 12.5));
+    assert_eq!((0, 0, 255, 255), sample(30.0, 25.0));
+    assert_eq!((0, 0, 0, 255), sample(51.0, 25.0));
+}
+
+/// Replacing part of an image changes only those pixels, in place.
+#[test]
+fn image_replace_region() {
+    let (c, _e) = &mut tests::make_context();
+    let image = graphics::Image::solid(c, 4, graphics::WHITE).unwrap();
+    let shared = image.clone();
+    let red: Vec<u8> = (0..4).flat_map(|_| vec![255, 0, 0, 255]).collect();
+    image
+        .replace_region(c, graphics::Rect::new(1.0, 2.0, 2.0, 2.0), &red)
+        .unwrap();
+    let rgba_buf = shared.to_rgba8(c).unwrap();
+    let sample = |x: f32, y: f32| get_rgba_sample(&rgba_buf, 4, Point2::new(x, y));
+    assert_eq!((255, 0, 0, 255), sample(1.0, 2.0));
+    assert_eq!((255, 0, 0, 255), sample(2.0, 3.0));
+    assert_eq!(graphics::WHITE.to_rgba(), sample(0.0, 2.0));
+    assert_eq!(graphics::WHITE.to_rgba(), sample(1.0, 1.0));
+
+    let outside = graphics::Rect::new(3.0, 3.0, 2.0, 2.0);
+    assert!(image.replace_region(c, outside, &red).is_err());
+    let fractional = graphics::Rect::new(0.5, 0.0, 2.0, 2.0);
+    assert!(image.replace_region(c, fractional, &red).is_err());
+    assert!(image.replace(c, &red).is_err());
+    image.replace(c, &[0; 4 * 4 * 4]).unwrap();
+    assert!(image.to_rgba8(c).unwrap().iter().all(|&b| b == 0));
+}
+
+/// Consecutive image draws get batched, but must still end up on
+/// the screen in the order they were drawn relative to everything else.
+#[test]
+fn batched_draws_keep_order() {
+    let (c, _e) = &mut tests::make_context();
+    graphics::clear(c, Color::new(0.0, 0.0, 0.0, 1.0));
+    let (width, height) = graphics::drawable_size(c);
+    let half_w = width / 2.0;
+    let half_h = height / 2.0;
+
+    let image = graphics::Image::solid(c, 1, graphics::WHITE).unwrap();
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let blue = Color::new(0.0, 0.0, 1.0, 1.0);
+    let quadrant = |x, y| {
+        graphics::DrawParam::new()
+            .dest(Point2::new(x, y))
+            .scale([half_w, half_h])
+    };
+
+    // Left half white, batched into one draw...
+    graphics::draw(c, &image, quadrant(0.0, 0.0)).unwrap();
+    graphics::draw(c, &image, quadrant(0.0, half_h)).unwrap();
+    // ...then a mesh on top of the top left quadrant...
+    let rect = graphics::Mesh::new_rectangle(
+        c,
+        graphics::DrawMode::fill(),
+        graphics::types::Rect::new(0.0, 0.0, half_w, half_h),
+        red,
+    )
+    .unwrap();
+    graphics::draw(c, &rect, graphics::DrawParam::new()).unwrap();
+    // ...and the right half drawn with a different color and transform.
+    graphics::draw(c, &image, quadrant(half_w, 0.0).color(blue)).unwrap();
+    graphics::push_transform(
+        c,
+        Some(
+            graphics::DrawParam::new()
+                .dest(Point2::new(half_w, half_h))
+                .to_matrix(),
+        ),
+    );
+    graphics::apply_transformations(c).unwrap();
+    graphics::draw(c, &image, quadrant(0.0, 0.0).color(red)).unwrap();
+    graphics::pop_transform(c);
+    graphics::apply_transformations(c).unwrap();
+
+    let screenshot = graphics::screenshot(c).unwrap();
+    let rgba_buf = screenshot.to_rgba8(c).unwrap();
+    let w = width as usize;
+    let sample = |x: f32, y: f32| get_rgba_sample(&rgba_buf, w, Point2::new(x, y));
+    assert_eq!(red.to_rgba(), sample(half_w / 2.0, half_h / 2.0));
+    assert_eq!(
+        graphics::WHITE.to_rgba(),
+        sample(half_w / 2.0, half_h * 1.5)
+    );
+    assert_eq!(blue.to_rgba(), sample(half_w * 1.5, half_h / 2.0));
+    assert_eq!(red.to_rgba(), sample(half_w * 1.5, half_h * 1.5));
+}
+
+/// Draws of the same mesh get batched as well, and images drawn in
+/// between still end up in the right order.
+#[test]
+fn batched_mesh_draws_keep_order() {
+    let (c, _e) = &mut tests::make_context();
+    graphics::clear(c, Color::new(0.0, 0.0, 0.0, 1.0));
+    let red = Color::new(1.0, 0.0, 0.0, 1.0);
+    let rect = graphics::Mesh::new_rectangle(
+        c,
+        graphics::DrawMode::fill(),
+        graphics::types::Rect::new(0.0, 0.0, 10.0, 10.0),
+        red,
+    )
+    .unwrap();
+    let image = graphics::Image::solid(c, 10, graphics::WHITE).unwrap();
+    let at = |x: f32| graphics::DrawParam::new().dest(Point2::new(x, 0.0));
+
+    for &x in &[0.0, 20.0, 40.0] {
+        graphics::draw(c, &rect, at(x)).unwrap();
+    }
+    graphics::draw(c, &image, at(20.0)).unwrap();
+    graphics::draw(c, &image, at(40.0)).unwrap();
+    graphics::draw(c, &rect, at(40.0)).unwrap();
+    graphics::draw(c, &rect, at(60.0)).unwrap();
+
+    let screenshot = graphics::screenshot(c).unwrap();
+    let rgba_buf = screenshot.to_rgba8(c).unwrap();
+    let w = graphics::drawable_size(c).0 as usize;
+    let sample = |x: f32| get_rgba_sample(&rgba_buf, w, Point2::new(x, 5.0));
+    assert_eq!(red.to_rgba(), sample(5.0));
+    assert_eq!(graphics::WHITE.to_rgba(), sample(25.0));
+    assert_eq!(red.to_rgba(), sample(45.0));
+    assert_eq!(red.to_rgba(), sample(65.0));
+    assert_eq!((0, 0, 0, 255), sample(15.0));
+}
 
 #[test]
 fn load_images() {
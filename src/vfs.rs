@@ -10,11 +10,12 @@
 //! convenient.
 
 use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::{self, Debug};
 use std::fs;
 use std::io::{self, Read, Seek, Write};
 use std::path::{self, Path, PathBuf};
+use std::rc::Rc;
 
 use zip;
 
@@ -116,6 +117,14 @@ pub trait VFS: Debug {
             OpenOptions::new().write(true).create(true).append(true),
         )
     }
+    /// Replace the contents of the file at this path with `bytes`, creating
+    /// it if necessary, such that the file either ends up with the new
+    /// contents or keeps its old ones, even if the program crashes halfway.
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> GameResult;
+
+    /// Move a file or directory, replacing whatever is at `to`.
+    fn rename(&self, from: &Path, to: &Path) -> GameResult;
+
     /// Create a directory at the location by this path
     fn mkdir(&self, path: &Path) -> GameResult;
 
@@ -134,8 +143,62 @@ pub trait VFS: Debug {
     /// Retrieve all file and directory entries in the given directory.
     fn read_dir(&self, path: &Path) -> GameResult<Box<dyn Iterator<Item = GameResult<PathBuf>>>>;
 
-    /// Retrieve the actual location of the VFS root, if available.
-    fn to_path_buf(&self) -> Option<PathBuf>;
+    /// Describes where this VFS gets its files from, if it is a single
+    /// place rather than a collection of other VFS's.
+    fn source(&self) -> Option<LayerSource>;
+
+    /// Turns strict path checking on or off, see
+    /// [`filesystem::set_strict()`](../filesystem/fn.set_strict.html).
+    /// Does nothing for VFS's that can't escape their root anyway.
+    fn set_strict(&mut self, _strict: bool) {}
+}
+
+/// Where one of the layers the filesystem searches for resources
+/// gets its files from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayerSource {
+    /// A directory on disk.
+    Directory {
+        /// The directory everything is looked up in.
+        root: PathBuf,
+        /// Whether or not ggez is allowed to write files in it.
+        readonly: bool,
+    },
+    /// A zip file on disk.
+    ZipFile(PathBuf),
+    /// A zip file read from some other `Read + Seek` object, such as
+    /// one added with
+    /// [`ContextBuilder::add_zipfile_bytes()`](../struct.ContextBuilder.html#method.add_zipfile_bytes).
+    ZipReader,
+}
+
+impl fmt::Display for LayerSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayerSource::Directory { ref root, readonly } => {
+                let mode = if readonly { "read-only" } else { "writable" };
+                write!(f, "{} directory {}", mode, root.display())
+            }
+            LayerSource::ZipFile(ref p) => write!(f, "zip file {}", p.display()),
+            LayerSource::ZipReader => write!(f, "zip file read from memory or a reader"),
+        }
+    }
+}
+
+/// One of the layers the filesystem searches for resources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    /// Where in the search order this layer is; layer 0 is searched
+    /// first.
+    pub index: usize,
+    /// Where the layer gets its files from.
+    pub source: LayerSource,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "layer {} ({})", self.index, self.source)
+    }
 }
 
 pub trait VMetadata {
@@ -154,11 +217,13 @@ pub trait VMetadata {
 /// file hierarchy.
 ///
 /// It IS allowed to have symlinks in it!  They're surprisingly
-/// difficult to get rid of.
+/// difficult to get rid of.  Unless it is `strict`, in which case
+/// any path that resolves to somewhere outside of the root is refused.
 #[derive(Clone)]
 pub struct PhysicalFS {
     root: PathBuf,
     readonly: bool,
+    strict: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -218,6 +283,7 @@ impl PhysicalFS {
         PhysicalFS {
             root: root.into(),
             readonly,
+            strict: false,
         }
     }
 
@@ -229,7 +295,14 @@ impl PhysicalFS {
         if let Some(safe_path) = sanitize_path(p) {
             let mut root_path = self.root.clone();
             root_path.push(safe_path);
-            Ok(root_path)
+            if self.strict {
+                self.confine(root_path)
+            } else {
+                Ok(root_path)
+            }
+        } else if self.strict && p.components().any(|c| c == path::Component::ParentDir) {
+            let msg = format!("Path {:?} refers to a parent directory", p);
+            Err(GameError::SandboxViolation(msg))
         } else {
             let msg = format!(
                 "Path {:?} is not valid: must be an absolute path with no \
@@ -240,6 +313,52 @@ impl PhysicalFS {
         }
     }
 
+    /// Resolves all symlinks in `path`, which should be inside our
+    /// root, and makes sure the result is still inside the root.
+    ///
+    /// The path doesn't have to exist yet; we resolve as much of it as
+    /// does, and the rest can't contain symlinks since it isn't there.
+    /// Of course, someone else could create them between us checking
+    /// and actually opening the file, so this only protects against
+    /// what is on disk, not against other processes messing with it.
+    fn confine(&self, path: PathBuf) -> GameResult<PathBuf> {
+        // If the root doesn't exist then nothing in it does, so there
+        // is nothing to follow.
+        let root = match fs::canonicalize(&self.root) {
+            Ok(root) => root,
+            Err(_) => return Ok(path),
+        };
+
+        let mut existing = path.as_path();
+        let mut missing = Vec::new();
+        while fs::symlink_metadata(existing).is_err() {
+            match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name);
+                    existing = parent;
+                }
+                _ => break,
+            }
+        }
+
+        // A dangling symlink makes `canonicalize()` fail, and we can't
+        // tell where creating a file through it would end up.
+        let mut resolved = fs::canonicalize(existing).map_err(|e| {
+            GameError::SandboxViolation(format!("Could not resolve path {:?}: {}", path, e))
+        })?;
+        resolved.extend(missing.iter().rev());
+
+        if resolved.starts_with(&root) {
+            Ok(resolved)
+        } else {
+            let msg = format!(
+                "Path {:?} resolves to {:?}, which is outside of {:?}",
+                path, resolved, root
+            );
+            Err(GameError::SandboxViolation(msg))
+        }
+    }
+
     /// Creates the PhysicalFS's root directory if necessary.
     /// Idempotent.
     /// This way we can not create the directory until it's
@@ -284,6 +403,64 @@ impl VFS for PhysicalFS {
             .map_err(GameError::from)
     }
 
+    /// Write the file by writing a temporary file next to it and then
+    /// renaming it over the top, since renames within a directory are
+    /// atomic.
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> GameResult {
+        if self.readonly {
+            let msg = format!(
+                "Cannot write file {:?} in root {:?}, filesystem read-only",
+                path, self
+            );
+            return Err(GameError::FilesystemError(msg));
+        }
+        self.create_root()?;
+        let p = self.to_absolute(path)?;
+        let file_name = p.file_name().ok_or_else(|| {
+            GameError::FilesystemError(format!("Path {:?} has no file name", path))
+        })?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = p.with_file_name(temp_name);
+
+        let write_and_rename = || -> io::Result<()> {
+            let mut f = fs::File::create(&temp_path)?;
+            f.write_all(bytes)?;
+            f.sync_all()?;
+            fs::rename(&temp_path, &p)
+        };
+        if let Err(e) = write_and_rename() {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+
+        // Make sure the rename itself makes it to disk.  Windows doesn't
+        // let you do this, and doesn't need you to.
+        #[cfg(unix)]
+        {
+            if let Some(dir) = p.parent() {
+                let _ = fs::File::open(dir).and_then(|d| d.sync_all());
+            }
+        }
+        Ok(())
+    }
+
+    /// Move a file or directory
+    fn rename(&self, from: &Path, to: &Path) -> GameResult {
+        if self.readonly {
+            let msg = format!(
+                "Cannot rename {:?} in root {:?}, filesystem read-only",
+                from, self
+            );
+            return Err(GameError::FilesystemError(msg));
+        }
+        self.create_root()?;
+        let from = self.to_absolute(from)?;
+        let to = self.to_absolute(to)?;
+        fs::rename(from, to).map_err(GameError::from)
+    }
+
     /// Create a directory at the location by this path
     fn mkdir(&self, path: &Path) -> GameResult {
         if self.readonly {
@@ -382,9 +559,15 @@ impl VFS for PhysicalFS {
         Ok(Box::new(itr))
     }
 
-    /// Retrieve the actual location of the VFS root, if available.
-    fn to_path_buf(&self) -> Option<PathBuf> {
-        Some(self.root.clone())
+    fn source(&self) -> Option<LayerSource> {
+        Some(LayerSource::Directory {
+            root: self.root.clone(),
+            readonly: self.readonly,
+        })
+    }
+
+    fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
     }
 }
 
@@ -414,25 +597,49 @@ impl OverlayFS {
         self.roots.push_back(fs);
     }
 
-    pub fn roots(&self) -> &VecDeque<Box<dyn VFS>> {
-        &self.roots
+    /// Returns every root along with a description of it, in the
+    /// order they are searched.
+    pub fn layers(&self) -> impl Iterator<Item = (Layer, &dyn VFS)> {
+        self.roots.iter().enumerate().map(|(index, vfs)| {
+            let source = vfs
+                .source()
+                .expect("OverlayFS inside an OverlayFS?  Should never happen!");
+            (Layer { index, source }, &**vfs)
+        })
+    }
+
+    /// Returns every layer that has something at the given path, in the
+    /// order they are searched, so the first is the one that will
+    /// actually be used.  If none of them do, returns a
+    /// `GameError::ResourceNotFound` with why not for each layer.
+    pub fn resolve(&self, path: &Path) -> GameResult<Vec<Layer>> {
+        let mut found = Vec::new();
+        let mut tried = Vec::new();
+        for (layer, vfs) in self.layers() {
+            match vfs.metadata(path) {
+                Ok(_) => found.push(layer),
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
+                Err(e) => tried.push((layer, e)),
+            }
+        }
+        if found.is_empty() {
+            let errmessage = String::from(convenient_path_to_str(path)?);
+            Err(GameError::ResourceNotFound(errmessage, tried))
+        } else {
+            Ok(found)
+        }
     }
 }
 
 impl VFS for OverlayFS {
     /// Open the file at this path with the given options
     fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
-        let mut tried: Vec<(PathBuf, GameError)> = vec![];
+        let mut tried: Vec<(Layer, GameError)> = vec![];
 
-        for vfs in &self.roots {
+        for (layer, vfs) in self.layers() {
             match vfs.open_options(path, open_options) {
-                Err(e) => {
-                    if let Some(vfs_path) = vfs.to_path_buf() {
-                        tried.push((vfs_path, e));
-                    } else {
-                        tried.push((PathBuf::from("<invalid path>"), e));
-                    }
-                }
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
+                Err(e) => tried.push((layer, e)),
                 f => return f,
             }
         }
@@ -440,10 +647,41 @@ impl VFS for OverlayFS {
         Err(GameError::ResourceNotFound(errmessage, tried))
     }
 
+    /// Write the file in the first VFS that lets us
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> GameResult {
+        for vfs in &self.roots {
+            match vfs.write_atomic(path, bytes) {
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
+                Err(_) => (),
+                f => return f,
+            }
+        }
+        Err(GameError::FilesystemError(format!(
+            "Could not find anywhere writeable to write file {:?}",
+            path
+        )))
+    }
+
+    /// Move a file or directory within the first VFS that lets us
+    fn rename(&self, from: &Path, to: &Path) -> GameResult {
+        for vfs in &self.roots {
+            match vfs.rename(from, to) {
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
+                Err(_) => (),
+                f => return f,
+            }
+        }
+        Err(GameError::FilesystemError(format!(
+            "Could not rename {:?} to {:?}",
+            from, to
+        )))
+    }
+
     /// Create a directory at the location by this path
     fn mkdir(&self, path: &Path) -> GameResult {
         for vfs in &self.roots {
             match vfs.mkdir(path) {
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
                 Err(_) => (),
                 f => return f,
             }
@@ -458,6 +696,7 @@ impl VFS for OverlayFS {
     fn rm(&self, path: &Path) -> GameResult {
         for vfs in &self.roots {
             match vfs.rm(path) {
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
                 Err(_) => (),
                 f => return f,
             }
@@ -472,6 +711,7 @@ impl VFS for OverlayFS {
     fn rmrf(&self, path: &Path) -> GameResult {
         for vfs in &self.roots {
             match vfs.rmrf(path) {
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
                 Err(_) => (),
                 f => return f,
             }
@@ -497,6 +737,7 @@ impl VFS for OverlayFS {
     fn metadata(&self, path: &Path) -> GameResult<Box<dyn VMetadata>> {
         for vfs in &self.roots {
             match vfs.metadata(path) {
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
                 Err(_) => (),
                 f => return f,
             }
@@ -507,81 +748,162 @@ impl VFS for OverlayFS {
         )))
     }
 
-    /// Retrieve the path entries in this path
+    /// Retrieve the path entries in this path.  Entries that exist in
+    /// more than one VFS are only listed once.
     fn read_dir(&self, path: &Path) -> GameResult<Box<dyn Iterator<Item = GameResult<PathBuf>>>> {
         // This is tricky 'cause we have to actually merge iterators together...
         // Doing it the simple and stupid way works though.
         let mut v = Vec::new();
+        let mut seen = HashSet::new();
         for fs in &self.roots {
-            if let Ok(rddir) = fs.read_dir(path) {
-                v.extend(rddir)
+            match fs.read_dir(path) {
+                Ok(rddir) => {
+                    for entry in rddir {
+                        match entry {
+                            Ok(p) => {
+                                if seen.insert(p.clone()) {
+                                    v.push(Ok(p));
+                                }
+                            }
+                            e => v.push(e),
+                        }
+                    }
+                }
+                Err(e @ GameError::SandboxViolation(_)) => return Err(e),
+                Err(_) => (),
             }
         }
         Ok(Box::new(v.into_iter()))
     }
 
-    /// Retrieve the actual location of the VFS root, if available.
-    fn to_path_buf(&self) -> Option<PathBuf> {
+    fn source(&self) -> Option<LayerSource> {
         None
     }
+
+    fn set_strict(&mut self, strict: bool) {
+        for vfs in &mut self.roots {
+            vfs.set_strict(strict);
+        }
+    }
 }
 
-trait ZipArchiveAccess {
-    fn by_name<'a>(&'a mut self, name: &str) -> zip::result::ZipResult<zip::read::ZipFile<'a>>;
-    fn by_index<'a>(
-        &'a mut self,
-        file_number: usize,
-    ) -> zip::result::ZipResult<zip::read::ZipFile<'a>>;
-    fn len(&self) -> usize;
+/// Anything we can read raw zip bytes out of.
+trait ReadSeek: Read + Seek {}
+
+impl<T> ReadSeek for T where T: Read + Seek {}
+
+/// Where the bytes of a zip file live.  Every file we open out of the
+/// archive gets its own reader from here, so open files never fight
+/// over a shared cursor.
+enum ZipSource {
+    /// A file on disk; each reader is a fresh file handle.
+    Path(PathBuf),
+    /// Some arbitrary `Read + Seek` object, shared between readers
+    /// that each remember their own position in it.
+    Shared(Rc<RefCell<dyn ReadSeek>>),
 }
 
-impl<T: Read + Seek> ZipArchiveAccess for zip::ZipArchive<T> {
-    fn by_name(&mut self, name: &str) -> zip::result::ZipResult<zip::read::ZipFile> {
-        //let filename = sanitize_path(Path::new(name)).unwrap_or(PathBuf::from(&name));
-        let filename = sanitize_path(Path::new(name)).ok_or(zip::result::ZipError::FileNotFound)?;
-        self.by_name(filename.to_str().unwrap_or(name))
+impl ZipSource {
+    fn reader(&self) -> io::Result<Box<dyn ReadSeek>> {
+        match *self {
+            ZipSource::Path(ref p) => Ok(Box::new(fs::File::open(p)?)),
+            ZipSource::Shared(ref r) => Ok(Box::new(SharedReader {
+                inner: r.clone(),
+                pos: 0,
+            })),
+        }
     }
+}
 
-    fn by_index(&mut self, file_number: usize) -> zip::result::ZipResult<zip::read::ZipFile> {
-        self.by_index(file_number)
+impl Debug for ZipSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ZipSource::Path(ref p) => write!(f, "{}", p.display()),
+            // Hide the contents; for an io::Cursor, this would print what is
+            // likely to be megabytes of data.
+            ZipSource::Shared(_) => write!(f, "<reader>"),
+        }
     }
+}
+
+/// A cursor into a reader shared with other `SharedReader`'s.
+///
+/// The `RefCell` is only ever borrowed for the duration of a single
+/// `read()` or `seek()` call, so this can't panic on a double borrow.
+struct SharedReader {
+    inner: Rc<RefCell<dyn ReadSeek>>,
+    pos: u64,
+}
 
-    fn len(&self) -> usize {
-        self.len()
+impl io::Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+        let _ = inner.seek(io::SeekFrom::Start(self.pos))?;
+        let n = inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
     }
 }
 
-impl Debug for dyn ZipArchiveAccess {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        // Hide the contents; for an io::Cursor, this would print what is
-        // likely to be megabytes of data.
-        write!(f, "<ZipArchiveAccess>")
+impl io::Seek for SharedReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            io::SeekFrom::Start(p) => p,
+            _ => {
+                let mut inner = self.inner.borrow_mut();
+                let _ = inner.seek(io::SeekFrom::Start(self.pos))?;
+                inner.seek(pos)?
+            }
+        };
+        Ok(self.pos)
     }
 }
 
+/// Everything we need to know to read a file out of the archive
+/// without going through `zip::ZipArchive` again.
+#[derive(Debug, Clone)]
+struct ZipEntry {
+    name: String,
+    compression: zip::CompressionMethod,
+    /// Offset of the (possibly compressed) file data in the archive.
+    data_start: u64,
+    compressed_size: u64,
+    size: u64,
+    is_dir: bool,
+    /// We can't decrypt entries, so these can be listed but not opened.
+    encrypted: bool,
+}
+
 /// A filesystem backed by a zip file.
-#[derive(Debug)]
+///
+/// We read the zip's central directory once when it is created and
+/// keep our own index of it.  Opening a file then just means getting
+/// a new reader on the archive and pointing it at the right place:
+/// stored (uncompressed) files can be read and seeked directly, compressed
+/// ones are decompressed on the fly as they are read.
+///
+/// Zip files don't need to have entries for directories, just files
+/// with long names like `levels/one/map.toml`, so we also work out
+/// which directories are implied by the names of everything in it.
 pub struct ZipFS {
-    // It's... a bit jankity.
-    // Zip files aren't really designed to be virtual filesystems,
-    // and the structure of the `zip` crate doesn't help.  See the various
-    // issues that have been filed on it by icefoxen.
-    //
-    // ALSO THE SEMANTICS OF ZIPARCHIVE AND HAVING ZIPFILES BORROW IT IS
-    // HORRIFICALLY BROKEN BY DESIGN SO WE'RE JUST GONNA REFCELL IT AND COPY
-    // ALL CONTENTS OUT OF IT AAAAA.
-    source: Option<PathBuf>,
-    archive: RefCell<Box<dyn ZipArchiveAccess>>,
-    // We keep an index of what files are in the zip file
-    // because trying to read it lazily is a pain in the butt.
-    index: Vec<String>,
+    source: Rc<ZipSource>,
+    entries: Vec<ZipEntry>,
+    // Maps names as made by `zip_name()` to entries.
+    index: HashMap<String, usize>,
+    // Maps the name of every directory, explicit or implied, to the
+    // names of everything in it.  The root directory is `""`.
+    dirs: HashMap<String, BTreeSet<String>>,
 }
 
 impl ZipFS {
     pub fn new(filename: &Path) -> GameResult<Self> {
         let f = fs::File::open(filename)?;
-        let archive = Box::new(zip::ZipArchive::new(f)?);
-        ZipFS::from_boxed_archive(archive, Some(filename.into()))
+        let mut archive = zip::ZipArchive::new(f)?;
+        let entries = ZipFS::read_entries(&mut archive)?;
+        Ok(ZipFS::from_entries(
+            ZipSource::Path(filename.into()),
+            entries,
+        ))
     }
 
     /// Creates a `ZipFS` from any `Read+Seek` object, most useful with an
@@ -590,62 +912,260 @@ impl ZipFS {
     where
         R: Read + Seek + 'static,
     {
-        let archive = Box::new(zip::ZipArchive::new(reader)?);
-        ZipFS::from_boxed_archive(archive, None)
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let entries = ZipFS::read_entries(&mut archive)?;
+        let reader = Rc::new(RefCell::new(archive.into_inner()));
+        Ok(ZipFS::from_entries(ZipSource::Shared(reader), entries))
     }
 
-    fn from_boxed_archive(
-        mut archive: Box<dyn ZipArchiveAccess>,
-        source: Option<PathBuf>,
-    ) -> GameResult<Self> {
-        let idx = (0..archive.len())
+    fn read_entries<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> GameResult<Vec<ZipEntry>> {
+        (0..archive.len())
             .map(|i| {
-                archive
-                    .by_index(i)
-                    .expect("Should never happen!")
-                    .name()
-                    .to_string()
+                // Only `by_index()` checks for encryption; anything else
+                // wrong with the entry turns up when it is opened.
+                let encrypted = match archive.by_index(i) {
+                    Err(zip::result::ZipError::UnsupportedArchive(msg)) => {
+                        msg == zip::result::ZipError::PASSWORD_REQUIRED
+                    }
+                    _ => false,
+                };
+                // Raw access, so we don't need to care about compression
+                // just to find out where the data lives.
+                let zipfile = archive.by_index_raw(i)?;
+                Ok(ZipEntry {
+                    name: zipfile.name().to_string(),
+                    compression: zipfile.compression(),
+                    data_start: zipfile.data_start(),
+                    compressed_size: zipfile.compressed_size(),
+                    size: zipfile.size(),
+                    is_dir: zipfile.is_dir(),
+                    encrypted,
+                })
             })
-            .collect();
-        Ok(Self {
-            source,
-            archive: RefCell::new(archive),
-            index: idx,
-        })
+            .collect()
+    }
+
+    fn from_entries(source: ZipSource, entries: Vec<ZipEntry>) -> Self {
+        let mut index = HashMap::new();
+        let mut dirs: HashMap<String, BTreeSet<String>> = HashMap::new();
+        let _ = dirs.insert(String::new(), BTreeSet::new());
+        for (i, entry) in entries.iter().enumerate() {
+            let parts: Vec<&str> = entry.name.split('/').filter(|s| !s.is_empty()).collect();
+            // Names with these in them can't be looked up anyway.
+            if parts.is_empty() || parts.iter().any(|&s| s == "." || s == "..") {
+                continue;
+            }
+            let name = parts.join("/");
+            for n in 1..=parts.len() {
+                let _ = dirs
+                    .entry(parts[..n - 1].join("/"))
+                    .or_default()
+                    .insert(parts[..n].join("/"));
+            }
+            if entry.is_dir {
+                let _ = dirs.entry(name.clone()).or_default();
+            }
+            let _ = index.insert(name, i);
+        }
+        Self {
+            source: Rc::new(source),
+            entries,
+            index,
+            dirs,
+        }
+    }
+
+    fn entry(&self, path: &Path) -> Option<&ZipEntry> {
+        let name = zip_name(path)?;
+        self.index.get(&name).map(|&i| &self.entries[i])
+    }
+
+    fn dir(&self, path: &Path) -> Option<&BTreeSet<String>> {
+        let name = zip_name(path)?;
+        self.dirs.get(&name)
+    }
+}
+
+impl Debug for ZipFS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "<ZipFS source: {:?}>", self.source)
+    }
+}
+
+/// Turns an absolute path into the form zip files name things with:
+/// no leading slash and `/` between components.  The root is `""`.
+fn zip_name(path: &Path) -> Option<String> {
+    let safe_path = sanitize_path(path)?;
+    let parts = safe_path
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+/// A window onto the bytes of a single zip entry, as they are
+/// stored in the archive.
+struct ZipSlice {
+    reader: Box<dyn ReadSeek>,
+    start: u64,
+    len: u64,
+    pos: u64,
+    // Whether `reader` is actually at `start + pos`.
+    positioned: bool,
+}
+
+impl ZipSlice {
+    fn new(reader: Box<dyn ReadSeek>, start: u64, len: u64) -> Self {
+        ZipSlice {
+            reader,
+            start,
+            len,
+            pos: 0,
+            positioned: false,
+        }
+    }
+}
+
+impl io::Read for ZipSlice {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        if remaining == 0 {
+            return Ok(0);
+        }
+        if !self.positioned {
+            let _ = self
+                .reader
+                .seek(io::SeekFrom::Start(self.start + self.pos))?;
+            self.positioned = true;
+        }
+        let max = buf.len().min(remaining as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Seek for ZipSlice {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.len, pos)?;
+        self.positioned = false;
+        Ok(self.pos)
+    }
+}
+
+/// Works out where a `SeekFrom` lands in a stream of length `len`.
+fn seek_position(current: u64, len: u64, pos: io::SeekFrom) -> io::Result<u64> {
+    let (base, offset) = match pos {
+        io::SeekFrom::Start(p) => return Ok(p),
+        io::SeekFrom::End(o) => (len, o),
+        io::SeekFrom::Current(o) => (current, o),
+    };
+    if offset >= 0 {
+        Ok(base + offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid seek to a negative position",
+                )
+            })
+    }
+}
+
+/// Wraps the raw data of a zip entry in whatever decompresses it,
+/// or returns `None` if we can't decompress it.
+fn zip_decoder(compression: zip::CompressionMethod, slice: ZipSlice) -> Option<Box<dyn Read>> {
+    match compression {
+        zip::CompressionMethod::Stored => Some(Box::new(slice)),
+        #[cfg(feature = "bzip2")]
+        zip::CompressionMethod::Bzip2 => Some(Box::new(bzip2_decoder::read::BzDecoder::new(slice))),
+        _ => None,
     }
 }
 
-/// A wrapper to contain a zipfile so we can implement
-/// (janky) Seek on it and such.
+/// A compressed file in a zip archive, decompressed as it is read.
 ///
-/// We're going to do it the *really* janky way and just read
-/// the whole `ZipFile` into a buffer, which is kind of awful but means
-/// we don't have to deal with lifetimes, self-borrowing structs,
-/// rental, re-implementing Seek on compressed data, making multiple zip
-/// zip file objects share a single file handle, or any of that
-/// other nonsense.
-#[derive(Clone)]
-pub struct ZipFileWrapper {
-    buffer: io::Cursor<Vec<u8>>,
+/// Seeking forward decompresses and throws away everything up to the
+/// new position, seeking backwards starts decompressing again from the
+/// beginning of the file, so it works but isn't fast.  Stored files
+/// are read through a `ZipSlice` instead, which can seek for free.
+struct ZipStream {
+    source: Rc<ZipSource>,
+    entry: ZipEntry,
+    decoder: Box<dyn Read>,
+    pos: u64,
 }
 
-impl ZipFileWrapper {
-    fn new(z: &mut zip::read::ZipFile) -> GameResult<Self> {
-        let mut b = Vec::new();
-        let _ = z.read_to_end(&mut b)?;
-        Ok(Self {
-            buffer: io::Cursor::new(b),
+impl ZipStream {
+    fn new(source: Rc<ZipSource>, entry: ZipEntry) -> GameResult<Self> {
+        let decoder = ZipStream::decoder(&source, &entry)?.ok_or_else(|| {
+            GameError::ResourceLoadError(format!(
+                "Zip file entry {:?} uses unsupported compression method {}",
+                entry.name, entry.compression
+            ))
+        })?;
+        Ok(ZipStream {
+            source,
+            entry,
+            decoder,
+            pos: 0,
         })
     }
+
+    fn decoder(source: &ZipSource, entry: &ZipEntry) -> io::Result<Option<Box<dyn Read>>> {
+        let slice = ZipSlice::new(source.reader()?, entry.data_start, entry.compressed_size);
+        Ok(zip_decoder(entry.compression, slice))
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.decoder = ZipStream::decoder(&self.source, &self.entry)?.expect(
+            "Zip compression method was checked when the file was opened; should never happen!",
+        );
+        self.pos = 0;
+        Ok(())
+    }
 }
 
-impl io::Read for ZipFileWrapper {
+impl io::Read for ZipStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.buffer.read(buf)
+        let n = self.decoder.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
     }
 }
 
-impl io::Write for ZipFileWrapper {
+impl io::Seek for ZipStream {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let target = seek_position(self.pos, self.entry.size, pos)?;
+        if target < self.pos {
+            self.rewind()?;
+        }
+        let skip = target - self.pos;
+        let skipped = io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        // Like a file, seeking past the end is fine; reads from
+        // there just return nothing.
+        self.pos += skip - skipped;
+        Ok(self.pos)
+    }
+}
+
+/// A file opened from a zip archive.
+enum ZipFileReader {
+    Stored(ZipSlice),
+    Compressed(ZipStream),
+}
+
+impl io::Read for ZipFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            ZipFileReader::Stored(ref mut r) => r.read(buf),
+            ZipFileReader::Compressed(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+impl io::Write for ZipFileReader {
     fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
         panic!("Cannot write to a zip file!")
     }
@@ -655,15 +1175,23 @@ impl io::Write for ZipFileWrapper {
     }
 }
 
-impl io::Seek for ZipFileWrapper {
+impl io::Seek for ZipFileReader {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
-        self.buffer.seek(pos)
+        match *self {
+            ZipFileReader::Stored(ref mut r) => r.seek(pos),
+            ZipFileReader::Compressed(ref mut r) => r.seek(pos),
+        }
     }
 }
 
-impl Debug for ZipFileWrapper {
+impl Debug for ZipFileReader {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "<Zipfile>")
+        match *self {
+            ZipFileReader::Stored(_) => write!(f, "<Zipfile (stored)>"),
+            ZipFileReader::Compressed(ref r) => {
+                write!(f, "<Zipfile ({})>", r.entry.compression)
+            }
+        }
     }
 }
 
@@ -675,23 +1203,21 @@ struct ZipMetadata {
 }
 
 impl ZipMetadata {
-    /// Returns a ZipMetadata, or None if the file does not exist or such.
-    /// This is not QUITE correct; since zip archives don't actually have
-    /// directories (just long filenames), we can't get a directory's metadata
-    /// this way without basically just faking it.
-    ///
-    /// This does make listing a directory rather screwy.
-    fn new(name: &str, archive: &mut dyn ZipArchiveAccess) -> Option<Self> {
-        match archive.by_name(name) {
-            Err(_) => None,
-            Ok(zipfile) => {
-                let len = zipfile.size();
-                Some(ZipMetadata {
-                    len,
-                    is_file: true,
-                    is_dir: false, // mu
-                })
-            }
+    fn new(entry: &ZipEntry) -> Self {
+        ZipMetadata {
+            len: entry.size,
+            is_file: !entry.is_dir,
+            is_dir: entry.is_dir,
+        }
+    }
+
+    /// Metadata for a directory that only exists because there are
+    /// files in it.
+    fn implied_dir() -> Self {
+        ZipMetadata {
+            len: 0,
+            is_file: false,
+            is_dir: true,
         }
     }
 }
@@ -711,7 +1237,6 @@ impl VMetadata for ZipMetadata {
 impl VFS for ZipFS {
     fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
         // Zip is readonly
-        let path = convenient_path_to_str(path)?;
         if open_options.write || open_options.create || open_options.append || open_options.truncate
         {
             let msg = format!(
@@ -720,14 +1245,42 @@ impl VFS for ZipFS {
             );
             return Err(GameError::FilesystemError(msg));
         }
-        let mut stupid_archive_borrow = self.archive
-            .try_borrow_mut()
-            .expect("Couldn't borrow ZipArchive in ZipFS::open_options(); should never happen! Report a bug at https://github.com/ggez/ggez/");
-        let mut f = stupid_archive_borrow.by_name(path)?;
-        let zipfile = ZipFileWrapper::new(&mut f)?;
+        let entry = match self.entry(path) {
+            Some(entry) if !entry.is_dir => entry,
+            _ => return Err(zip::result::ZipError::FileNotFound.into()),
+        };
+        if entry.encrypted {
+            let msg = format!(
+                "Cannot open file {:?} in zipfile {:?}, it is encrypted",
+                path, self
+            );
+            return Err(GameError::FilesystemError(msg));
+        }
+        let zipfile = if entry.compression == zip::CompressionMethod::Stored {
+            let reader = self.source.reader()?;
+            ZipFileReader::Stored(ZipSlice::new(reader, entry.data_start, entry.size))
+        } else {
+            ZipFileReader::Compressed(ZipStream::new(self.source.clone(), entry.clone())?)
+        };
         Ok(Box::new(zipfile) as Box<dyn VFile>)
     }
 
+    fn write_atomic(&self, path: &Path, _bytes: &[u8]) -> GameResult {
+        let msg = format!(
+            "Cannot write file {:?} in zipfile {:?}, filesystem read-only",
+            path, self
+        );
+        Err(GameError::FilesystemError(msg))
+    }
+
+    fn rename(&self, from: &Path, _to: &Path) -> GameResult {
+        let msg = format!(
+            "Cannot rename {:?} in zipfile {:?}, filesystem read-only",
+            from, self
+        );
+        Err(GameError::FilesystemError(msg))
+    }
+
     fn mkdir(&self, path: &Path) -> GameResult {
         let msg = format!(
             "Cannot mkdir {:?} in zipfile {:?}, filesystem read-only",
@@ -753,45 +1306,42 @@ impl VFS for ZipFS {
     }
 
     fn exists(&self, path: &Path) -> bool {
-        let mut stupid_archive_borrow = self.archive
-            .try_borrow_mut()
-            .expect("Couldn't borrow ZipArchive in ZipFS::exists(); should never happen!  Report a bug at https://github.com/ggez/ggez/");
-        if let Ok(path) = convenient_path_to_str(path) {
-            stupid_archive_borrow.by_name(path).is_ok()
-        } else {
-            false
-        }
+        self.entry(path).is_some() || self.dir(path).is_some()
     }
 
     fn metadata(&self, path: &Path) -> GameResult<Box<dyn VMetadata>> {
-        let path = convenient_path_to_str(path)?;
-        let mut stupid_archive_borrow = self.archive
-            .try_borrow_mut()
-            .expect("Couldn't borrow ZipArchive in ZipFS::metadata(); should never happen! Report a bug at https://github.com/ggez/ggez/");
-        match ZipMetadata::new(path, &mut **stupid_archive_borrow) {
-            None => Err(GameError::FilesystemError(format!(
-                "Metadata not found in zip file for {}",
-                path
-            ))),
-            Some(md) => Ok(Box::new(md) as Box<dyn VMetadata>),
-        }
+        let md = match (self.entry(path), self.dir(path)) {
+            (Some(entry), _) => ZipMetadata::new(entry),
+            (None, Some(_)) => ZipMetadata::implied_dir(),
+            (None, None) => {
+                return Err(GameError::FilesystemError(format!(
+                    "Metadata not found in zip file for {:?}",
+                    path
+                )))
+            }
+        };
+        Ok(Box::new(md) as Box<dyn VMetadata>)
     }
 
-    /// Zip files don't have real directories, so we (incorrectly) hack it by
-    /// just looking for a path prefix for now.
     fn read_dir(&self, path: &Path) -> GameResult<Box<dyn Iterator<Item = GameResult<PathBuf>>>> {
-        let path = convenient_path_to_str(path)?;
-        let itr = self
-            .index
+        let dir = self.dir(path).ok_or_else(|| {
+            GameError::FilesystemError(format!(
+                "Directory {:?} not found in zip file {:?}",
+                path, self
+            ))
+        })?;
+        let itr = dir
             .iter()
-            .filter(|s| s.starts_with(path))
-            .map(|s| Ok(PathBuf::from(s)))
+            .map(|name| Ok(Path::new("/").join(name)))
             .collect::<Vec<_>>();
         Ok(Box::new(itr.into_iter()))
     }
 
-    fn to_path_buf(&self) -> Option<PathBuf> {
-        self.source.clone()
+    fn source(&self) -> Option<LayerSource> {
+        match *self.source {
+            ZipSource::Path(ref p) => Some(LayerSource::ZipFile(p.clone())),
+            ZipSource::Shared(_) => Some(LayerSource::ZipReader),
+        }
     }
 }
 
@@ -953,8 +1503,8 @@ mod tests {
         let _bytes = finished_zip_bytes.seek(io::SeekFrom::Start(0)).unwrap();
         let zfs = ZipFS::from_read(finished_zip_bytes).unwrap();
 
-        assert!(zfs.exists(Path::new("/fake_file_name.txt".into())));
-        assert!(!zfs.exists(Path::new("fake_file_name.txt".into())));
+        assert!(zfs.exists(Path::new("/fake_file_name.txt")));
+        assert!(!zfs.exists(Path::new("fake_file_name.txt")));
 
         let mut contents = String::new();
         let _bytes = zfs
@@ -964,5 +1514,165 @@ mod tests {
         assert_eq!(contents, "Zip contents!");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn headless_test_strict_mode() {
+        use std::os::unix::fs::symlink;
+
+        let base = std::env::temp_dir().join("ggez_headless_test_strict_mode");
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("root");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(base.join("secret.txt"), b"secret").unwrap();
+        fs::write(root.join("dir/file.txt"), b"file").unwrap();
+        symlink(base.join("secret.txt"), root.join("escape.txt")).unwrap();
+        symlink(&base, root.join("escape_dir")).unwrap();
+        symlink(base.join("nowhere.txt"), root.join("dangling.txt")).unwrap();
+        symlink(root.join("dir"), root.join("inside")).unwrap();
+
+        let mut fs = PhysicalFS::new(&root, false);
+        // Not strict, so everything goes.
+        assert!(fs.open(Path::new("/escape.txt")).is_ok());
+        assert!(fs.open(Path::new("/escape_dir/secret.txt")).is_ok());
+
+        fs.set_strict(true);
+        let assert_violation = |r: GameResult<Box<dyn VFile>>| match r {
+            Err(GameError::SandboxViolation(_)) => (),
+            other => panic!("Expected a sandbox violation, got {:?}", other),
+        };
+        assert_violation(fs.open(Path::new("/escape.txt")));
+        assert_violation(fs.open(Path::new("/escape_dir/secret.txt")));
+        assert_violation(fs.open(Path::new("/../secret.txt")));
+        assert_violation(fs.create(Path::new("/escape_dir/new.txt")));
+        assert_violation(fs.create(Path::new("/dangling.txt")));
+        assert!(!fs.exists(Path::new("/escape.txt")));
+        assert!(!base.join("new.txt").exists());
+        assert!(!base.join("nowhere.txt").exists());
+
+        // Symlinks that stay inside the root are fine.
+        assert!(fs.open(Path::new("/dir/file.txt")).is_ok());
+        assert!(fs.open(Path::new("/inside/file.txt")).is_ok());
+        assert!(fs.create(Path::new("/inside/new.txt")).is_ok());
+        assert!(root.join("dir/new.txt").exists());
+
+        // And the overlay doesn't go looking elsewhere after a violation.
+        let mut ofs = OverlayFS::new();
+        ofs.push_back(Box::new(PhysicalFS::new(&root, true)));
+        ofs.push_back(Box::new(PhysicalFS::new(&base, true)));
+        ofs.set_strict(true);
+        assert_violation(ofs.open(Path::new("/escape.txt")));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    fn zip_with_file(options: zip::write::FileOptions, contents: &[u8]) -> ZipFS {
+        let mut finished_zip_bytes: io::Cursor<_> = {
+            let zip_bytes = io::Cursor::new(vec![]);
+            let mut zip_archive = zip::ZipWriter::new(zip_bytes);
+
+            zip_archive.add_directory("dir/", options).unwrap();
+            zip_archive.start_file("dir/file.bin", options).unwrap();
+            zip_archive.write_all(contents).unwrap();
+            zip_archive.finish().unwrap()
+        };
+        let _bytes = finished_zip_bytes.seek(io::SeekFrom::Start(0)).unwrap();
+        ZipFS::from_read(finished_zip_bytes).unwrap()
+    }
+
+    fn check_zip_seeking(zfs: &ZipFS, contents: &[u8]) {
+        let m = zfs.metadata(Path::new("/dir")).unwrap();
+        assert!(m.is_dir());
+        let m = zfs.metadata(Path::new("/dir/file.bin")).unwrap();
+        assert!(m.is_file());
+        assert_eq!(m.len(), contents.len() as u64);
+        assert!(zfs.open(Path::new("/dir")).is_err());
+
+        let mut f1 = zfs.open(Path::new("/dir/file.bin")).unwrap();
+        let mut f2 = zfs.open(Path::new("/dir/file.bin")).unwrap();
+        let mut buf = [0; 4];
+
+        // Open files don't share a position.
+        let _ = f1.seek(io::SeekFrom::Start(1000)).unwrap();
+        f1.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, &contents[1000..1004]);
+        f2.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, &contents[0..4]);
+
+        // Backwards, relative and from the end.
+        assert_eq!(f1.seek(io::SeekFrom::Current(-504)).unwrap(), 500);
+        f1.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, &contents[500..504]);
+        let _ = f1.seek(io::SeekFrom::End(-4)).unwrap();
+        f1.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, &contents[contents.len() - 4..]);
+        assert!(f1.seek(io::SeekFrom::Current(-100_000)).is_err());
+
+        let mut all = Vec::new();
+        let _ = f2.seek(io::SeekFrom::Start(0)).unwrap();
+        let _ = f2.read_to_end(&mut all).unwrap();
+        assert_eq!(&all[..], contents);
+    }
+
+    #[test]
+    fn headless_test_zip_stored_seek() {
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let zfs = zip_with_file(options, &contents);
+        check_zip_seeking(&zfs, &contents);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn headless_test_zip_compressed_seek() {
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Bzip2);
+        let zfs = zip_with_file(options, &contents);
+        check_zip_seeking(&zfs, &contents);
+    }
+
+    #[test]
+    fn headless_test_zip_encrypted() {
+        let mut zip_bytes = {
+            let mut zip_archive = zip::ZipWriter::new(io::Cursor::new(vec![]));
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored);
+            zip_archive.start_file("plain.txt", options).unwrap();
+            zip_archive.write_all(b"plain").unwrap();
+            zip_archive.start_file("secret.txt", options).unwrap();
+            zip_archive.write_all(b"secret").unwrap();
+            zip_archive.finish().unwrap().into_inner()
+        };
+        // The zip crate can't write encrypted files, so set the "encrypted"
+        // flag of the second file in its local and central headers.
+        let find = |bytes: &[u8], signature: &[u8], name: &[u8], name_offset: usize| {
+            (0..bytes.len())
+                .find(|&i| {
+                    bytes[i..].starts_with(signature) && bytes[i + name_offset..].starts_with(name)
+                })
+                .unwrap()
+        };
+        let local = find(&zip_bytes, b"PK\x03\x04", b"secret", 30);
+        zip_bytes[local + 6] |= 1;
+        let central = find(&zip_bytes, b"PK\x01\x02", b"secret", 46);
+        zip_bytes[central + 8] |= 1;
+
+        let zfs = ZipFS::from_read(io::Cursor::new(zip_bytes)).unwrap();
+        assert!(zfs.exists(Path::new("/secret.txt")));
+        match zfs.open(Path::new("/secret.txt")) {
+            Err(GameError::FilesystemError(_)) => (),
+            Err(e) => panic!("Expected a filesystem error, got {:?}", e),
+            Ok(_) => panic!("Opened an encrypted file"),
+        }
+        let mut contents = String::new();
+        let _bytes = zfs
+            .open(Path::new("/plain.txt"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "plain");
+    }
+
     // BUGGO: TODO: Make sure all functions are tested for OverlayFS and ZipFS!!
 }